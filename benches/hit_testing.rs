@@ -0,0 +1,115 @@
+// Compares finding the placement area under a dropped domino by scanning
+// every area (how drops used to be resolved) against `resolve_drop`, which
+// looks it up straight from the grid coordinates.
+//
+// Run with `cargo bench --bench hit_testing`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use bevy::math::Vec2;
+use domino_prototype::helpers::grid::*;
+use domino_prototype::systems::domino_grid::board::Board;
+use domino_prototype::systems::domino_grid::components::{DominoColor, DominoType, PlacementArea};
+use domino_prototype::systems::domino_grid::placement::{resolve_drop, PlacementSettings};
+use domino_prototype::systems::domino_grid::rules::free_form::FreeForm;
+use domino_prototype::systems::domino_grid::rules::RuleSet;
+
+const DROPS: usize = 10_000;
+const BOARD_SIZES: [i32; 4] = [10, 25, 50, 100];
+
+/** Only what's right under the drop, as the scan did */
+const EXACT: PlacementSettings = PlacementSettings { snap_radius: 0., auto_rotate: false };
+
+/** Every area of the board, as spawned one entity per area before */
+fn all_areas(board: &Board) -> Vec<PlacementArea> {
+  board.areas().collect()
+}
+
+fn linear_scan(board: &Board, rules: &dyn RuleSet, areas: &[PlacementArea], point: Vec2, d_type: DominoType, horizontal: bool) -> Option<PlacementArea> {
+  areas
+    .iter()
+    .filter(|area| area.is_horizontal() == horizontal)
+    .find(|area| {
+      let center = area.center();
+      let half = TILE_SIZE / 2.;
+      point.x > center.x - half && point.x < center.x + half
+        && point.y > center.y - half && point.y < center.y + half
+    })
+    .filter(|area| rules.validate(board, area, d_type).is_ok())
+    .copied()
+}
+
+/** Deterministic drop points spread over the board and a little beyond it */
+fn drop_points(size: i32) -> Vec<(Vec2, bool)> {
+  let mut state: u32 = 0x9e37_79b9;
+  let mut next = move || {
+    state ^= state << 13;
+    state ^= state >> 17;
+    state ^= state << 5;
+    state
+  };
+  let extent = (size + 2) as f32 * TILE_SIZE;
+
+  (0..DROPS)
+    .map(|_| {
+      let x = (next() % 10_000) as f32 / 10_000. * extent - TILE_SIZE;
+      let y = (next() % 10_000) as f32 / 10_000. * extent - TILE_SIZE;
+      (Vec2::new(x, y), next() % 2 == 0)
+    })
+    .collect()
+}
+
+fn time(mut run: impl FnMut()) -> Duration {
+  let start = Instant::now();
+  run();
+  start.elapsed()
+}
+
+fn main() {
+  let rules = FreeForm;
+  let d_type = DominoType::new(DominoColor::Blue, DominoColor::Red);
+  let snapping = PlacementSettings::default();
+
+  println!("{:>9} {:>8} {:>14} {:>14} {:>14}", "board", "areas", "scan/drop", "exact/drop", "snapping/drop");
+
+  for size in BOARD_SIZES {
+    let board = Board::new(size, size);
+    let areas = all_areas(&board);
+    let points = drop_points(size);
+
+    // Both approaches have to agree before their timings mean anything. The
+    // old hitboxes excluded their edges, so only check points they accepted.
+    for (point, horizontal) in points.iter() {
+      if let Some(area) = linear_scan(&board, &rules, &areas, *point, d_type, *horizontal) {
+        let resolved = resolve_drop(&board, &rules, &EXACT, *point, d_type, *horizontal).map(|target| target.area);
+        assert_eq!(Ok(area), resolved, "{point} ({horizontal})");
+      }
+    }
+
+    let scan = time(|| {
+      for (point, horizontal) in points.iter() {
+        black_box(linear_scan(&board, &rules, black_box(&areas), *point, d_type, *horizontal));
+      }
+    });
+    let exact = time(|| {
+      for (point, horizontal) in points.iter() {
+        black_box(resolve_drop(black_box(&board), &rules, &EXACT, *point, d_type, *horizontal).ok());
+      }
+    });
+    let snapped = time(|| {
+      for (point, horizontal) in points.iter() {
+        black_box(resolve_drop(black_box(&board), &rules, &snapping, *point, d_type, *horizontal).ok());
+      }
+    });
+
+    println!(
+      "{:>9} {:>8} {:>14?} {:>14?} {:>14?}",
+      format!("{size}x{size}"),
+      areas.len(),
+      scan / DROPS as u32,
+      exact / DROPS as u32,
+      snapped / DROPS as u32,
+    );
+  }
+}
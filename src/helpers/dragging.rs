@@ -4,6 +4,4 @@ use crate::prelude::*;
 pub struct Draggable;
 
 #[derive(Component)]
-pub struct IsDragging {
-  pub start_location: Vec2,
-}
\ No newline at end of file
+pub struct IsDragging;
\ No newline at end of file
@@ -0,0 +1,27 @@
+// Grid math shared by hit testing and rendering.
+use bevy::math::{IVec2, Vec2};
+
+pub const TILE_SIZE: f32 = 32.;
+
+/**
+  The first cell (left or bottom) of the domino-sized area whose hitbox
+  contains the point.
+
+  A horizontal area is centered on the edge between its two cells, so the
+  column is found by flooring while the row is the nearest one. Vertical
+  areas are the same with the axes swapped.
+ */
+pub fn area_origin_at(point: Vec2, horizontal: bool) -> IVec2 {
+  let scaled = point / TILE_SIZE;
+  if horizontal {
+    IVec2::new(scaled.x.floor() as i32, scaled.y.round() as i32)
+  } else {
+    IVec2::new(scaled.x.round() as i32, scaled.y.floor() as i32)
+  }
+}
+
+/** The world position of the center of an area, in pixels */
+pub fn area_center(origin: IVec2, horizontal: bool) -> Vec2 {
+  let offset = if horizontal { Vec2::new(0.5, 0.) } else { Vec2::new(0., 0.5) };
+  (origin.as_vec2() + offset) * TILE_SIZE
+}
@@ -1,2 +1,3 @@
 pub mod dragging;
+pub mod grid;
 pub mod transform;
\ No newline at end of file
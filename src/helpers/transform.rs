@@ -1,6 +1,6 @@
 use crate::prelude::*;
 
-pub const TILE_SIZE: f32 = 32.;
+use super::grid::TILE_SIZE;
 
 /**
   Transform tile world coordinates to actual screen pixels based
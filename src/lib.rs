@@ -0,0 +1,11 @@
+pub mod state;
+pub mod systems;
+pub mod helpers;
+
+pub mod prelude {
+  pub use bevy::prelude::*;
+
+  pub use crate::helpers::*;
+  pub use crate::state::*;
+  pub use crate::systems::*;
+}
@@ -1,27 +1,35 @@
-mod state;
-mod systems;
-mod helpers;
-
-mod prelude {
-  pub use bevy::prelude::*;
-
-  pub use crate::helpers::*;
-  pub use crate::state::*;
-  pub use crate::systems::*;
-}
-
-use prelude::*;
+use domino_prototype::prelude::*;
 
 fn main() {
-  App::new()
+  // Strategies can be compared without a window: see `TournamentConfig`
+  if let Some(tournament) = TournamentConfig::from_args(std::env::args().skip(1)) {
+    tournament.run();
+    return;
+  }
+  // Likewise agents can be trained: see `EnvServer`
+  if let Some(env) = EnvServer::from_args(std::env::args().skip(1)) {
+    env.run();
+    return;
+  }
+
+  let mut app = App::new();
+  app
     .add_plugins(
       DefaultPlugins
         .set(ImagePlugin::default_nearest())
         .set(get_window_plugin())
     )
     .add_plugins(StatesPlugin)
-    .add_plugins(SystemsPlugin)
-    .run();
+    .add_plugins(SystemsPlugin);
+
+  // Serves the Bevy remote protocol on localhost:15702, so tools can inspect and edit the live board
+  #[cfg(feature = "remote")]
+  app.add_plugins((
+    bevy::remote::RemotePlugin::default(),
+    bevy::remote::http::RemoteHttpPlugin::default(),
+  ));
+
+  app.run();
 }
 
 pub const WINDOW_WIDTH: f32 = 640. * 2.;
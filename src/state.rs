@@ -28,6 +28,11 @@ pub enum MenuState {
 
 #[derive(States, Default, Debug, Clone, PartialEq, Eq, Hash, Reflect)]
 pub enum GameState {
+  /** Single player, building freely */
   #[default]
   Build,
+  /** Hotseat: the current player is choosing a move */
+  PlayerTurn,
+  /** Hotseat: between turns, with every hand hidden until the next player is ready */
+  HandOver,
 }
\ No newline at end of file
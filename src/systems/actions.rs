@@ -1,30 +1,299 @@
+use std::collections::BTreeMap;
+
 use leafwing_input_manager::{plugin::InputManagerPlugin, prelude::{ActionState, InputMap}, Actionlike};
+use serde::{Deserialize, Serialize};
 
 use crate::prelude::*;
 
+use super::rebinding::RebindingPlugin;
+use super::settings::{controls_path, load_controls};
+
 pub struct ActionsPlugin;
 
 impl Plugin for ActionsPlugin {
   fn build(&self, app: &mut App) {
+    let controls = load_controls(controls_path().as_deref());
+
     app
-      .add_plugins(InputManagerPlugin::<DominoControlAction>::default())
+      .add_plugins((
+        InputManagerPlugin::<DominoControlAction>::default(),
+        RebindingPlugin,
+      ))
       .init_resource::<ActionState<DominoControlAction>>()
-      .insert_resource(DominoControlAction::default_input_map())
+      .insert_resource(controls.input_map())
+      .insert_resource(controls)
+      .add_systems(Update, sync_input_map)
       ;
   }
 }
 
-#[derive(Actionlike, PartialEq, Eq, Hash, Clone, Copy, Debug, Reflect)]
+#[derive(Actionlike, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug, Reflect, Serialize, Deserialize)]
 pub enum DominoControlAction {
   Rotate,
+  Flip,
+  Draw,
+  Pass,
+  NextLevel,
+  Reset,
+  ToggleHitboxes,
+  ToggleHighlights,
+  SelectNext,
+  SelectPrevious,
+  CursorUp,
+  CursorDown,
+  CursorLeft,
+  CursorRight,
+  Place,
+  Cancel,
+  PanUp,
+  PanDown,
+  PanLeft,
+  PanRight,
+  ZoomIn,
+  ZoomOut,
+  FitBoard,
+  ScrollTrayUp,
+  ScrollTrayDown,
+  SortTray,
+  Hold,
+  SaveReplay,
+  PlayReplay,
+  ReplayPause,
+  ReplayStep,
+  ReplayBack,
+  ReplayForward,
+  OpenControls,
+  ToggleConsole,
+  ToggleOverlay,
 }
 
 impl DominoControlAction {
-  pub fn default_input_map() -> InputMap<Self> {
-    let mut input_map = InputMap::default();
+  pub const ALL: [Self; 36] = [
+    Self::Rotate,
+    Self::Flip,
+    Self::Draw,
+    Self::Pass,
+    Self::NextLevel,
+    Self::Reset,
+    Self::ToggleHitboxes,
+    Self::ToggleHighlights,
+    Self::SelectNext,
+    Self::SelectPrevious,
+    Self::CursorUp,
+    Self::CursorDown,
+    Self::CursorLeft,
+    Self::CursorRight,
+    Self::Place,
+    Self::Cancel,
+    Self::PanUp,
+    Self::PanDown,
+    Self::PanLeft,
+    Self::PanRight,
+    Self::ZoomIn,
+    Self::ZoomOut,
+    Self::FitBoard,
+    Self::ScrollTrayUp,
+    Self::ScrollTrayDown,
+    Self::SortTray,
+    Self::Hold,
+    Self::SaveReplay,
+    Self::PlayReplay,
+    Self::ReplayPause,
+    Self::ReplayStep,
+    Self::ReplayBack,
+    Self::ReplayForward,
+    Self::OpenControls,
+    Self::ToggleConsole,
+    Self::ToggleOverlay,
+  ];
+
+  pub fn label(&self) -> &'static str {
+    match self {
+      Self::Rotate => "Rotate",
+      Self::Flip => "Flip",
+      Self::Draw => "Draw",
+      Self::Pass => "Pass",
+      Self::NextLevel => "Next level",
+      Self::Reset => "Reset",
+      Self::ToggleHitboxes => "Toggle hitboxes",
+      Self::ToggleHighlights => "Toggle highlights",
+      Self::SelectNext => "Select next",
+      Self::SelectPrevious => "Select previous",
+      Self::CursorUp => "Cursor up",
+      Self::CursorDown => "Cursor down",
+      Self::CursorLeft => "Cursor left",
+      Self::CursorRight => "Cursor right",
+      Self::Place => "Place",
+      Self::Cancel => "Cancel",
+      Self::PanUp => "Pan up",
+      Self::PanDown => "Pan down",
+      Self::PanLeft => "Pan left",
+      Self::PanRight => "Pan right",
+      Self::ZoomIn => "Zoom in",
+      Self::ZoomOut => "Zoom out",
+      Self::FitBoard => "Fit board",
+      Self::ScrollTrayUp => "Scroll tray up",
+      Self::ScrollTrayDown => "Scroll tray down",
+      Self::SortTray => "Sort tray",
+      Self::Hold => "Hold",
+      Self::SaveReplay => "Save replay",
+      Self::PlayReplay => "Play replay",
+      Self::ReplayPause => "Pause replay",
+      Self::ReplayStep => "Step replay",
+      Self::ReplayBack => "Replay back",
+      Self::ReplayForward => "Replay forward",
+      Self::OpenControls => "Controls",
+      Self::ToggleConsole => "Console",
+      Self::ToggleOverlay => "Debug overlay",
+    }
+  }
+}
+
+/** A single key or gamepad button that can trigger an action */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+  Key(KeyCode),
+  Gamepad(GamepadButton),
+}
 
-    input_map.insert(Self::Rotate, KeyCode::KeyR);
-    
+/** The keys and gamepad buttons bound to one action */
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ActionBindings {
+  pub keys: Vec<KeyCode>,
+  pub gamepad: Vec<GamepadButton>,
+}
+
+/**
+ The player's controls, as saved to the settings file. The `InputMap` is
+ rebuilt from this whenever it changes.
+ */
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Controls(pub BTreeMap<DominoControlAction, ActionBindings>);
+
+impl Default for Controls {
+  fn default() -> Self {
+    use DominoControlAction::*;
+
+    let mut controls = Self(BTreeMap::new());
+    let mut bind = |action, keys: &[KeyCode], gamepad: &[GamepadButton]| {
+      controls.0.insert(action, ActionBindings {
+        keys: keys.to_vec(),
+        gamepad: gamepad.to_vec(),
+      });
+    };
+
+    bind(Rotate, &[KeyCode::KeyR], &[GamepadButton::West]);
+    bind(Flip, &[KeyCode::KeyF], &[GamepadButton::RightThumb]);
+    bind(Draw, &[KeyCode::KeyD], &[GamepadButton::North]);
+    bind(Pass, &[KeyCode::KeyP], &[GamepadButton::Select]);
+    bind(NextLevel, &[KeyCode::KeyM], &[GamepadButton::Start]);
+    bind(Reset, &[KeyCode::Backspace], &[]);
+    bind(ToggleHitboxes, &[KeyCode::Escape], &[]);
+    bind(ToggleHighlights, &[KeyCode::KeyH], &[]);
+    bind(SelectNext, &[KeyCode::KeyE], &[GamepadButton::RightTrigger]);
+    bind(SelectPrevious, &[KeyCode::KeyQ], &[GamepadButton::LeftTrigger]);
+    bind(CursorUp, &[KeyCode::ArrowUp], &[GamepadButton::DPadUp]);
+    bind(CursorDown, &[KeyCode::ArrowDown], &[GamepadButton::DPadDown]);
+    bind(CursorLeft, &[KeyCode::ArrowLeft], &[GamepadButton::DPadLeft]);
+    bind(CursorRight, &[KeyCode::ArrowRight], &[GamepadButton::DPadRight]);
+    bind(Place, &[KeyCode::Enter, KeyCode::Space], &[GamepadButton::South]);
+    bind(Cancel, &[KeyCode::KeyX], &[GamepadButton::East]);
+    bind(PanUp, &[KeyCode::KeyI], &[]);
+    bind(PanDown, &[KeyCode::KeyK], &[]);
+    bind(PanLeft, &[KeyCode::KeyJ], &[]);
+    bind(PanRight, &[KeyCode::KeyL], &[]);
+    bind(ZoomIn, &[KeyCode::Equal], &[GamepadButton::RightTrigger2]);
+    bind(ZoomOut, &[KeyCode::Minus], &[GamepadButton::LeftTrigger2]);
+    bind(FitBoard, &[KeyCode::Home], &[GamepadButton::LeftThumb]);
+    bind(ScrollTrayUp, &[KeyCode::PageUp], &[]);
+    bind(ScrollTrayDown, &[KeyCode::PageDown], &[]);
+    bind(SortTray, &[KeyCode::KeyT], &[]);
+    bind(Hold, &[KeyCode::KeyC], &[]);
+    bind(SaveReplay, &[KeyCode::F5], &[]);
+    bind(PlayReplay, &[KeyCode::F6], &[]);
+    bind(ReplayPause, &[KeyCode::F7], &[]);
+    bind(ReplayStep, &[KeyCode::F8], &[]);
+    bind(ReplayBack, &[KeyCode::F9], &[]);
+    bind(ReplayForward, &[KeyCode::F10], &[]);
+    bind(OpenControls, &[KeyCode::F1], &[]);
+    bind(ToggleConsole, &[KeyCode::Backquote], &[]);
+    bind(ToggleOverlay, &[KeyCode::F2], &[]);
+
+    controls
+  }
+}
+
+impl Controls {
+  pub fn get(&self, action: DominoControlAction) -> Option<&ActionBindings> {
+    self.0.get(&action)
+  }
+
+  /** Every binding, along with the action it triggers */
+  pub fn bindings(&self) -> impl Iterator<Item = (DominoControlAction, Binding)> + '_ {
+    self.0.iter().flat_map(|(action, bindings)| {
+      let keys = bindings.keys.iter().map(|key| (*action, Binding::Key(*key)));
+      let gamepad = bindings.gamepad.iter().map(|button| (*action, Binding::Gamepad(*button)));
+      keys.chain(gamepad)
+    })
+  }
+
+  /** The action other than `action` that already uses this binding, if any */
+  pub fn bound_elsewhere(&self, action: DominoControlAction, binding: Binding) -> Option<DominoControlAction> {
+    self.bindings()
+      .find(|(other, other_binding)| *other != action && *other_binding == binding)
+      .map(|(other, _)| other)
+  }
+
+  /** Bindings shared by more than one action, with the two actions involved */
+  pub fn conflicts(&self) -> Vec<(Binding, DominoControlAction, DominoControlAction)> {
+    let bindings: Vec<_> = self.bindings().collect();
+    let mut conflicts = Vec::new();
+    for (index, (action, binding)) in bindings.iter().enumerate() {
+      for (other, other_binding) in &bindings[index + 1..] {
+        if binding == other_binding && action != other {
+          conflicts.push((*binding, *action, *other));
+        }
+      }
+    }
+    conflicts
+  }
+
+  /**
+    Makes `binding` the only key (or gamepad button) for the action, leaving
+    its bindings on the other device alone. Refuses bindings that another
+    action already uses, returning that action instead.
+   */
+  pub fn rebind(&mut self, action: DominoControlAction, binding: Binding) -> Result<(), DominoControlAction> {
+    if let Some(other) = self.bound_elsewhere(action, binding) {
+      return Err(other);
+    }
+
+    let bindings = self.0.entry(action).or_default();
+    match binding {
+      Binding::Key(key) => bindings.keys = vec![key],
+      Binding::Gamepad(button) => bindings.gamepad = vec![button],
+    }
+    Ok(())
+  }
+
+  pub fn input_map(&self) -> InputMap<DominoControlAction> {
+    let mut input_map = InputMap::default();
+    for (action, binding) in self.bindings() {
+      match binding {
+        Binding::Key(key) => input_map.insert(action, key),
+        Binding::Gamepad(button) => input_map.insert(action, button),
+      };
+    }
     input_map
   }
 }
+
+/** Keeps the `InputMap` in step with the player's controls */
+fn sync_input_map(
+  controls: Res<Controls>,
+  mut input_map: ResMut<InputMap<DominoControlAction>>,
+) {
+  if controls.is_changed() && !controls.is_added() {
+    *input_map = controls.input_map();
+  }
+}
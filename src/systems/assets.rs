@@ -91,14 +91,13 @@ impl DominoesAssetInfo {
   pub fn get_domino(&self, domino: &DominoType) -> Sprite {
     Sprite {
       image: self.image_asset.image.clone(),
-      texture_atlas: if self.image_asset.atlas.is_some() {
-        let atlas = self.image_asset.atlas.as_ref().unwrap();
+      texture_atlas: if let Some(atlas) = &self.image_asset.atlas {
         Some(TextureAtlas {
-          index: self.sprite_info(&domino).index,
+          index: self.sprite_info(domino).index,
           layout: atlas.clone()
         })
       } else { unreachable!("Dominoes atlas should be set on inititialization") },
-      flip_x: self.sprite_info(&domino).flipped,
+      flip_x: self.sprite_info(domino).flipped,
       ..default()
     }
   }
@@ -111,21 +110,6 @@ impl ImageAsset {
       ..default()
     }
   }
-
-  /** More generic, for getting a sprite at any index of a loaded assets atlas */
-  pub fn get_sprite_at(&self, atlas_index: usize) -> Sprite {
-    Sprite {
-      image: self.image.clone(),
-      texture_atlas: if self.atlas.is_some() {
-        let atlas = self.atlas.as_ref().unwrap();
-        Some(TextureAtlas {
-          index: atlas_index,
-          layout: atlas.clone()
-        })
-      } else { None },
-      ..default()
-    }
-  }
 }
 
 pub fn init_assets(
@@ -134,6 +118,7 @@ pub fn init_assets(
   mut loaded_assets: ResMut<LoadedAssets>,
   mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
 ) {
+  let _span = info_span!("init_assets").entered();
   let ground_image = asset_server.load::<Image>("images/ground.png");
   let dominoes_image = asset_server.load::<Image>("images/dominoes.png");
   let placement_area = asset_server.load::<Image>("images/tile_placement_area.png");
@@ -170,6 +155,7 @@ pub fn check_assets_loading(
   assets_loading: Res<AssetsLoading>,
   mut app_state: ResMut<NextState<AppState>>,
 ) {
+  let _span = info_span!("check_assets_loading", assets = assets_loading.0.len()).entered();
   let mut any_unloaded = false;
 
   for asset in assets_loading.0.iter() {
@@ -179,8 +165,8 @@ pub fn check_assets_loading(
   }
 
   let all_loaded = !any_unloaded;
-  debug!("Finished loading assets!");
   if all_loaded {
+    debug!(assets = assets_loading.0.len(), "Finished loading assets!");
     app_state.set(AppState::Ready);
   }
 }
\ No newline at end of file
@@ -1,4 +1,194 @@
+use bevy::input::gestures::PinchGesture;
+use bevy::input::mouse::{AccumulatedMouseMotion, AccumulatedMouseScroll, MouseScrollUnit};
+use bevy::render::camera::ScalingMode;
+use bevy::window::PrimaryWindow;
+use leafwing_input_manager::{common_conditions::action_just_pressed, prelude::ActionState};
+
 use crate::prelude::*;
 
+use super::actions::DominoControlAction;
+use grid::TILE_SIZE;
+
+/** Smallest and largest orthographic scale. Below 1 is zoomed in */
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 4.;
+/** How much one line of mouse wheel scrolling zooms by */
+const WHEEL_ZOOM_STEP: f32 = 0.1;
+/** Tiles per second moved by the pan keys, at a scale of 1 */
+const KEY_PAN_SPEED: f32 = 12.;
+/** Space left around the board by the fit board action, in tiles */
+const FIT_MARGIN: f32 = 1.;
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+  fn build(&self, app: &mut App) {
+    app
+      .init_resource::<CameraBounds>()
+      .add_systems(Update, (
+        zoom_camera,
+        pan_camera,
+        fit_board.run_if(action_just_pressed(DominoControlAction::FitBoard)),
+        clamp_camera,
+      ).chain().in_set(CameraControlSet))
+      .add_systems(PostUpdate, check_main_camera)
+      ;
+  }
+}
+
 #[derive(Component)]
-pub struct MainCamera;
\ No newline at end of file
+pub struct MainCamera;
+
+/** Camera movement for the frame. Anything reading the camera should run after it */
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CameraControlSet;
+
+/**
+ What the camera may look at, in world pixels. Whoever lays out the board
+ and the tray keeps this up to date.
+ */
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct CameraBounds {
+  /** The board along with the tray. The camera center is kept inside it */
+  pub area: Rect,
+  /** Just the board, for fitting it to the window */
+  pub board: Rect,
+}
+
+impl Default for CameraBounds {
+  fn default() -> Self {
+    let board = Rect::new(0., 0., 10. * TILE_SIZE, 10. * TILE_SIZE);
+    Self { area: board, board }
+  }
+}
+
+/** Zooms with the mouse wheel, pinching and the zoom actions, keeping the point under the cursor still */
+fn zoom_camera(
+  scroll: Res<AccumulatedMouseScroll>,
+  mut pinches: EventReader<PinchGesture>,
+  action_state: Res<ActionState<DominoControlAction>>,
+  time: Res<Time>,
+  q_window: Query<&Window, With<PrimaryWindow>>,
+  mut q_camera: Query<(&mut Transform, &mut OrthographicProjection), With<MainCamera>>,
+) {
+  let Ok((mut transform, mut projection)) = q_camera.get_single_mut() else {
+    return;
+  };
+
+  let wheel_lines = match scroll.unit {
+    MouseScrollUnit::Line => scroll.delta.y,
+    MouseScrollUnit::Pixel => scroll.delta.y / 16.,
+  };
+  let mut zoom = -wheel_lines * WHEEL_ZOOM_STEP;
+  zoom -= pinches.read().map(|pinch| pinch.0).sum::<f32>();
+  if action_state.pressed(&DominoControlAction::ZoomIn) {
+    zoom -= time.delta_secs();
+  }
+  if action_state.pressed(&DominoControlAction::ZoomOut) {
+    zoom += time.delta_secs();
+  }
+  if zoom == 0. {
+    return;
+  }
+
+  let old_scale = projection.scale;
+  let new_scale = (old_scale * (1. + zoom)).clamp(MIN_ZOOM, MAX_ZOOM);
+  projection.scale = new_scale;
+
+  if let Some(cursor) = q_window.get_single().ok().and_then(|window| {
+    window.cursor_position().map(|cursor| screen_to_world(window, &transform, old_scale, cursor))
+  }) {
+    let camera_position = transform.translation.truncate();
+    let new_position = cursor - (cursor - camera_position) * (new_scale / old_scale);
+    transform.translation.x = new_position.x;
+    transform.translation.y = new_position.y;
+  }
+}
+
+/** Pans while the middle mouse button is held, and with the pan actions */
+fn pan_camera(
+  buttons: Res<ButtonInput<MouseButton>>,
+  motion: Res<AccumulatedMouseMotion>,
+  action_state: Res<ActionState<DominoControlAction>>,
+  time: Res<Time>,
+  mut q_camera: Query<(&mut Transform, &OrthographicProjection), With<MainCamera>>,
+) {
+  let Ok((mut transform, projection)) = q_camera.get_single_mut() else {
+    return;
+  };
+
+  let mut pan = Vec2::ZERO;
+  if buttons.pressed(MouseButton::Middle) {
+    // Screen y grows downwards, world y upwards
+    pan += Vec2::new(-motion.delta.x, motion.delta.y);
+  }
+
+  let mut direction = Vec2::ZERO;
+  for (action, step) in [
+    (DominoControlAction::PanUp, Vec2::Y),
+    (DominoControlAction::PanDown, Vec2::NEG_Y),
+    (DominoControlAction::PanLeft, Vec2::NEG_X),
+    (DominoControlAction::PanRight, Vec2::X),
+  ] {
+    if action_state.pressed(&action) {
+      direction += step;
+    }
+  }
+  pan += direction.normalize_or_zero() * KEY_PAN_SPEED * TILE_SIZE * time.delta_secs();
+
+  transform.translation += (pan * projection.scale).extend(0.);
+}
+
+/** Centers the board and zooms so that all of it is in view */
+fn fit_board(
+  bounds: Res<CameraBounds>,
+  q_window: Query<&Window, With<PrimaryWindow>>,
+  mut q_camera: Query<(&mut Transform, &mut OrthographicProjection), With<MainCamera>>,
+) {
+  let (Ok(window), Ok((mut transform, mut projection))) = (q_window.get_single(), q_camera.get_single_mut()) else {
+    return;
+  };
+
+  let board = bounds.board.inflate(FIT_MARGIN * TILE_SIZE);
+  let scale = (board.size() / window.size()).max_element();
+  projection.scale = scale.clamp(MIN_ZOOM, MAX_ZOOM);
+  transform.translation.x = board.center().x;
+  transform.translation.y = board.center().y;
+}
+
+fn clamp_camera(
+  bounds: Res<CameraBounds>,
+  mut q_camera: Query<&mut Transform, With<MainCamera>>,
+) {
+  for mut transform in q_camera.iter_mut() {
+    let position = transform.translation.truncate().clamp(bounds.area.min, bounds.area.max);
+    transform.translation.x = position.x;
+    transform.translation.y = position.y;
+  }
+}
+
+/** Whatever `screen_to_world` takes for granted about the main camera */
+#[allow(clippy::type_complexity)]
+fn check_main_camera(
+  q_camera: Query<(&Camera, &OrthographicProjection, Has<Parent>), (With<MainCamera>, Or<(Changed<Camera>, Changed<OrthographicProjection>, Added<Parent>)>)>,
+) {
+  for (camera, projection, has_parent) in q_camera.iter() {
+    debug_assert!(camera.viewport.is_none(), "the main camera must fill the window");
+    debug_assert!(matches!(projection.scaling_mode, ScalingMode::WindowSize), "the main camera must scale with the window size");
+    debug_assert!(!has_parent, "the main camera must not be parented");
+  }
+}
+
+/**
+  Where a point in the window lands in the world. Worked out from the camera's
+  `Transform` rather than its `GlobalTransform`, which is only brought up to
+  date after `Update`, so it stays right in the frame the camera moves.
+
+  Unlike `Camera::viewport_to_world_2d` this takes the camera to fill the
+  window, to be scaled with `ScalingMode::WindowSize` and to have no parent.
+  `check_main_camera` asserts as much in debug builds.
+ */
+pub fn screen_to_world(window: &Window, camera_transform: &Transform, scale: f32, point: Vec2) -> Vec2 {
+  let from_center = point - window.size() / 2.;
+  camera_transform.translation.truncate() + Vec2::new(from_center.x, -from_center.y) * scale
+}
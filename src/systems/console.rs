@@ -0,0 +1,232 @@
+use std::collections::BTreeMap;
+
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::input::ButtonState;
+use leafwing_input_manager::{common_conditions::action_just_pressed, prelude::ActionState};
+
+use crate::prelude::*;
+
+use super::actions::*;
+
+/** How many lines of output are kept, and how many are shown */
+const KEPT_LINES: usize = 200;
+const SHOWN_LINES: usize = 14;
+
+/**
+ A developer console, opened with the backquote key. Plugins contribute
+ their own commands with `add_console_command`; `help` lists them all.
+ */
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+  fn build(&self, app: &mut App) {
+    app
+      .init_resource::<Console>()
+      .add_console_command("help", "[command]", "Lists the commands, or explains one", help_command)
+      .add_systems(Update, (
+        toggle_console.run_if(action_just_pressed(DominoControlAction::ToggleConsole)),
+        type_into_console,
+        run_console_commands,
+        update_console_text,
+      ).chain())
+      ;
+  }
+}
+
+/** Runs a command with its arguments, answering with what to print or what went wrong */
+pub type ConsoleHandler = fn(&mut World, &[&str]) -> Result<String, String>;
+
+#[derive(Clone, Copy)]
+pub struct ConsoleCommand {
+  /** The arguments it takes, like `<x> <y>` */
+  pub usage: &'static str,
+  pub help: &'static str,
+  pub run: ConsoleHandler,
+}
+
+#[derive(Resource, Default)]
+pub struct ConsoleCommands(pub BTreeMap<&'static str, ConsoleCommand>);
+
+pub trait AddConsoleCommand {
+  fn add_console_command(&mut self, name: &'static str, usage: &'static str, help: &'static str, run: ConsoleHandler) -> &mut Self;
+}
+
+impl AddConsoleCommand for App {
+  fn add_console_command(&mut self, name: &'static str, usage: &'static str, help: &'static str, run: ConsoleHandler) -> &mut Self {
+    // Whichever plugin is built first sets up the list
+    self.init_resource::<ConsoleCommands>();
+    self.world_mut().resource_mut::<ConsoleCommands>().0.insert(name, ConsoleCommand { usage, help, run });
+    self
+  }
+}
+
+#[derive(Resource, Default)]
+pub struct Console {
+  pub open: bool,
+  pub input: String,
+  pub output: Vec<String>,
+  /** What was entered before, oldest first */
+  history: Vec<String>,
+  /** How far back through the history the arrow keys have gone */
+  recalled: Option<usize>,
+  /** Entered, to be run with the world to hand */
+  pending: Vec<String>,
+}
+
+impl Console {
+  pub fn print(&mut self, text: &str) {
+    self.output.extend(text.lines().map(str::to_string));
+    let excess = self.output.len().saturating_sub(KEPT_LINES);
+    self.output.drain(..excess);
+  }
+}
+
+#[derive(Component)]
+pub struct ConsoleMenu;
+
+#[derive(Component)]
+pub struct ConsoleOutputText;
+
+#[derive(Component)]
+pub struct ConsoleInputText;
+
+fn toggle_console(
+  mut commands: Commands,
+  mut console: ResMut<Console>,
+  mut action_state: ResMut<ActionState<DominoControlAction>>,
+  menus: Query<Entity, With<ConsoleMenu>>,
+) {
+  console.open = !console.open;
+
+  // Typing shouldn't rotate dominoes or reset the level
+  for action in DominoControlAction::ALL {
+    if console.open && action != DominoControlAction::ToggleConsole {
+      action_state.disable_action(&action);
+    } else {
+      action_state.enable_action(&action);
+    }
+  }
+
+  for menu in menus.iter() {
+    commands.entity(menu).despawn_recursive();
+  }
+  if !console.open {
+    return;
+  }
+
+  commands.spawn((
+    ConsoleMenu,
+    Node {
+      position_type: PositionType::Absolute,
+      width: Val::Percent(100.),
+      height: Val::Percent(45.),
+      flex_direction: FlexDirection::Column,
+      justify_content: JustifyContent::FlexEnd,
+      padding: UiRect::all(Val::Px(8.)),
+      ..default()
+    },
+    BackgroundColor(Color::srgba(0., 0., 0., 0.85)),
+    GlobalZIndex(20),
+  )).with_children(|parent| {
+    parent.spawn((ConsoleOutputText, Text::default(), TextFont::from_font_size(14.)));
+    parent.spawn((ConsoleInputText, Text::default(), TextFont::from_font_size(14.)));
+  });
+}
+
+fn type_into_console(
+  mut console: ResMut<Console>,
+  mut keyboard_evr: EventReader<KeyboardInput>,
+  controls: Res<Controls>,
+) {
+  if !console.open {
+    keyboard_evr.clear();
+    return;
+  }
+  let toggle_keys = controls.get(DominoControlAction::ToggleConsole).map(|bindings| bindings.keys.clone()).unwrap_or_default();
+
+  for event in keyboard_evr.read() {
+    if event.state != ButtonState::Pressed || toggle_keys.contains(&event.key_code) {
+      continue;
+    }
+
+    match &event.logical_key {
+      Key::Enter => {
+        let line = std::mem::take(&mut console.input);
+        console.recalled = None;
+        if line.trim().is_empty() { continue; }
+        console.history.push(line.clone());
+        console.pending.push(line);
+      },
+      Key::Backspace => { console.input.pop(); },
+      Key::ArrowUp | Key::ArrowDown => {
+        let len = console.history.len();
+        if len == 0 { continue; }
+        let recalled = match (console.recalled, event.logical_key == Key::ArrowUp) {
+          (None, true) => Some(len - 1),
+          (Some(index), true) => Some(index.saturating_sub(1)),
+          (Some(index), false) if index + 1 < len => Some(index + 1),
+          _ => None,
+        };
+        console.recalled = recalled;
+        console.input = recalled.map(|index| console.history[index].clone()).unwrap_or_default();
+      },
+      Key::Space => console.input.push(' '),
+      Key::Character(text) => console.input.push_str(text),
+      _ => {},
+    }
+  }
+}
+
+fn run_console_commands(world: &mut World) {
+  let pending = std::mem::take(&mut world.resource_mut::<Console>().pending);
+
+  for line in pending {
+    let words: Vec<_> = line.split_whitespace().collect();
+    let Some((name, args)) = words.split_first() else { continue; };
+    let command = world.resource::<ConsoleCommands>().0.get(name).copied();
+
+    let answer = match command {
+      Some(command) => (command.run)(world, args).unwrap_or_else(|error| format!("{name}: {error}")),
+      None => format!("No command called {name}, try help"),
+    };
+    info!("Console: {line}");
+
+    let mut console = world.resource_mut::<Console>();
+    console.print(&format!("> {line}"));
+    if !answer.is_empty() {
+      console.print(&answer);
+    }
+  }
+}
+
+fn update_console_text(
+  console: Res<Console>,
+  mut outputs: Query<&mut Text, (With<ConsoleOutputText>, Without<ConsoleInputText>)>,
+  mut inputs: Query<&mut Text, (With<ConsoleInputText>, Without<ConsoleOutputText>)>,
+) {
+  if !console.is_changed() { return; }
+
+  let shown = &console.output[console.output.len().saturating_sub(SHOWN_LINES)..];
+  for mut text in outputs.iter_mut() {
+    text.0 = shown.join("\n");
+  }
+  for mut text in inputs.iter_mut() {
+    text.0 = format!("> {}_", console.input);
+  }
+}
+
+fn help_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+  let commands = world.resource::<ConsoleCommands>();
+  match args {
+    [] => Ok(commands.0
+      .iter()
+      .map(|(name, command)| format!("{name} {}: {}", command.usage, command.help))
+      .collect::<Vec<_>>()
+      .join("\n")),
+    [name] => commands.0
+      .get(name)
+      .map(|command| format!("{name} {}: {}", command.usage, command.help))
+      .ok_or_else(|| format!("no command called {name}")),
+    _ => Err("takes at most one command".to_string()),
+  }
+}
@@ -1,8 +1,9 @@
+use bevy::render::camera::CameraUpdateSystem;
 use bevy::window::PrimaryWindow;
 
 use crate::prelude::*;
 
-use super::camera::*;
+use super::camera::MainCamera;
 
 #[derive(Resource, Default)]
 pub struct CursorWorldCoords(pub Vec2);
@@ -13,8 +14,11 @@ impl Plugin for CursorPlugin {
   fn build(&self, app: &mut App) {
     app
       .init_resource::<CursorWorldCoords>()
-      .add_systems(Update, (
-        update_cursor_world_pos,
+      // Once the camera's GlobalTransform and projection are brought up to date
+      .add_systems(PostUpdate, (
+        update_cursor_world_pos
+          .after(TransformSystem::TransformPropagate)
+          .after(CameraUpdateSystem),
       ))
       ;
   }
@@ -36,9 +40,9 @@ pub fn update_cursor_world_pos(
 
   // check if the cursor is inside the window and get its position
   // then, ask bevy to convert into world coordinates, and truncate to discard Z
-  if let Some(cursor) = window.cursor_position() {
-    if let Ok(world_coords) = camera.viewport_to_world_2d(camera_transform, cursor) {
-      mycoords.0 = world_coords;
-    }
+  if let Some(world_position) = window.cursor_position()
+    .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor).ok())
+  {
+    mycoords.0 = world_position;
   }
-}
\ No newline at end of file
+}
@@ -0,0 +1,402 @@
+use std::cmp::Ordering;
+use std::io::{BufRead, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::systems::random::{GameRng, StreamRng};
+
+use super::super::level::*;
+use super::game::SimulatedGame;
+use super::tournament::Entrant;
+use super::*;
+
+/** Board channels: empty first, then one per color in `DominoColor::ALL` order */
+pub const BOARD_CHANNELS: usize = 1 + DominoColor::ALL.len();
+/** How a domino can sit on its cell: flat or upright, each as held or flipped */
+pub const ORIENTATIONS: usize = 4;
+/** Reward for an action the mask ruled out. It is played as a pass */
+pub const ILLEGAL_ACTION_REWARD: f32 = -1.;
+
+const DEFAULT_LEVEL: &str = "Versus computer";
+const DEFAULT_OPPONENT: &str = "easy";
+
+/**
+ The game as a reinforcement learning environment, gym style: `reset` deals
+ a seeded game and `step` plays one of the agent's actions, then lets the
+ opponents move until it is the agent's turn again. Everything runs on
+ `SimulatedGame`, so the real rules are used and nothing is rendered.
+
+ Actions are numbered over (tray slot, cell, orientation): the domino in
+ that slot of the hand goes with its first half on the cell (the left of a
+ flat domino, the bottom of an upright one), as held or flipped. The last
+ action passes. The mask says which actions are legal; anything else is
+ played as a pass and rewarded with `ILLEGAL_ACTION_REWARD`.
+
+ The reward is how much the agent's score improved over the step, so it is
+ negative when it got worse, counting lower scores as better where the rules do.
+ */
+pub struct DominoEnv {
+  level: Level,
+  registry: RuleSetRegistry,
+  /** The other seats, in turn order after the agent */
+  opponents: Vec<Entrant>,
+  game: SimulatedGame,
+  opponent_rngs: Vec<StreamRng>,
+}
+
+/** The sizes of everything, for setting up a model */
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct EnvSpec {
+  /** [channel, row, column] */
+  pub board_shape: [usize; 3],
+  /** [slot, half, color] */
+  pub hand_shape: [usize; 3],
+  pub action_count: usize,
+  pub pass_action: usize,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Observation {
+  /** Laid out as `EnvSpec::board_shape`, 1 where the cell holds that color (or nothing, for channel 0) */
+  pub board: Vec<f32>,
+  /** Laid out as `EnvSpec::hand_shape`, the agent's hand one-hot by color. Empty slots are all zero */
+  pub hand: Vec<f32>,
+  pub action_mask: Vec<bool>,
+  pub scores: Vec<i32>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Step {
+  pub observation: Observation,
+  pub reward: f32,
+  pub done: bool,
+}
+
+impl DominoEnv {
+  /** Levels without hand settings are played with the default hand. Until `reset`, the game is dealt from seed 0 */
+  pub fn new(level: Level, opponents: Vec<Entrant>) -> Self {
+    let registry = RuleSetRegistry::default();
+    let game = SimulatedGame::new(&level, &registry, opponents.len() + 1, 0);
+    let opponent_rngs = opponent_rngs(0, opponents.len());
+
+    Self {
+      level,
+      registry,
+      opponents,
+      game,
+      opponent_rngs,
+    }
+  }
+
+  fn width(&self) -> usize {
+    self.level.grid_width as usize
+  }
+
+  fn height(&self) -> usize {
+    self.level.grid_height as usize
+  }
+
+  fn hand_size(&self) -> usize {
+    self.level.hand.unwrap_or_default().hand_size
+  }
+
+  pub fn spec(&self) -> EnvSpec {
+    let pass_action = self.hand_size() * self.width() * self.height() * ORIENTATIONS;
+    EnvSpec {
+      board_shape: [BOARD_CHANNELS, self.height(), self.width()],
+      hand_shape: [self.hand_size(), 2, DominoColor::ALL.len()],
+      action_count: pass_action + 1,
+      pass_action,
+    }
+  }
+
+  pub fn reset(&mut self, seed: u64) -> Observation {
+    self.game = SimulatedGame::new(&self.level, &self.registry, self.opponents.len() + 1, seed);
+    self.opponent_rngs = opponent_rngs(seed, self.opponents.len());
+    self.observe()
+  }
+
+  pub fn step(&mut self, action: usize) -> Step {
+    if self.game.is_over() {
+      return Step { observation: self.observe(), reward: 0., done: true };
+    }
+
+    let before = self.game.scores[0];
+    let mut reward = 0.;
+    let mv = self.decode(action);
+    if mv.is_none() && action != self.spec().pass_action {
+      reward += ILLEGAL_ACTION_REWARD;
+    }
+    self.game.play(mv);
+
+    while self.game.current != 0 && !self.game.is_over() {
+      let seat = self.game.current;
+      let opponent = &self.opponents[seat - 1];
+      let chosen = opponent.strategy.choose(&self.game.position(), &Budget::new(opponent.budget), &mut self.opponent_rngs[seat - 1]);
+      self.game.play(chosen);
+    }
+
+    // Rewarded for improving the score, whichever way the rules count that
+    let after = self.game.scores[0];
+    let change = (after - before).abs() as f32;
+    reward += match self.game.rules.compare_scores(after, before) {
+      Ordering::Greater => change,
+      Ordering::Less => -change,
+      Ordering::Equal => 0.,
+    };
+    Step { observation: self.observe(), reward, done: self.game.is_over() }
+  }
+
+  /** The move an action stands for, if it is a legal one */
+  pub fn decode(&self, action: usize) -> Option<Move> {
+    let cells = self.width() * self.height();
+    let slot = action / (cells * ORIENTATIONS);
+    let cell = action / ORIENTATIONS % cells;
+    let orientation = action % ORIENTATIONS;
+
+    let held = *self.game.hands[0].get(slot)?;
+    let (x, y) = ((cell % self.width()) as i32, (cell / self.width()) as i32);
+    let area = if orientation < 2 {
+      PlacementArea::Horizontal { left_cell_col: x, row: y }
+    } else {
+      PlacementArea::Vertical { bottom_cell_row: y, column: x }
+    };
+    let d_type = if orientation.is_multiple_of(2) { held } else { held.flipped() };
+
+    let legal = self.game.board.contains(&area) && self.game.rules.validate(&self.game.board, &area, d_type).is_ok();
+    legal.then_some(Move { domino: slot, area, d_type })
+  }
+
+  fn action_mask(&self) -> Vec<bool> {
+    let spec = self.spec();
+    let mut mask: Vec<_> = (0..spec.action_count).map(|action| self.decode(action).is_some()).collect();
+    // Passing is only offered when there is nothing else to do
+    mask[spec.pass_action] = !self.game.is_over() && !mask.contains(&true);
+    mask
+  }
+
+  fn observe(&self) -> Observation {
+    let spec = self.spec();
+    let (width, height) = (self.width(), self.height());
+
+    let mut board = vec![0.; spec.board_shape.iter().product()];
+    for y in 0..height {
+      for x in 0..width {
+        let channel = self.game.board
+          .get(IVec2::new(x as i32, y as i32))
+          .map_or(0, |cell| 1 + cell.color.index());
+        board[(channel * height + y) * width + x] = 1.;
+      }
+    }
+
+    let mut hand = vec![0.; spec.hand_shape.iter().product()];
+    for (slot, domino) in self.game.hands[0].iter().take(self.hand_size()).enumerate() {
+      let (first, second) = domino.colors();
+      for (half, color) in [first, second].into_iter().enumerate() {
+        hand[(slot * 2 + half) * DominoColor::ALL.len() + color.index()] = 1.;
+      }
+    }
+
+    Observation {
+      board,
+      hand,
+      action_mask: self.action_mask(),
+      scores: self.game.scores.clone(),
+    }
+  }
+}
+
+/** The opponents' random streams, named by their seat as in the live game */
+fn opponent_rngs(seed: u64, opponents: usize) -> Vec<StreamRng> {
+  let rng = GameRng::new(seed);
+  (1..=opponents).map(|seat| rng.fork(&format!("ai {seat}"))).collect()
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum EnvRequest {
+  Spec,
+  Reset { seed: u64 },
+  Step { action: usize },
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum EnvResponse {
+  Spec(EnvSpec),
+  Reset(Observation),
+  Step(Step),
+  Error(String),
+}
+
+/**
+ Serves a `DominoEnv` over standard input and output, one JSON object per
+ line, so agents can be trained from other languages. Started with
+ `--rl-env`, optionally with `--level <name or number>` and
+ `--opponents <a,b,..>` (named as for tournaments). Requests are
+ `"spec"`, `{"reset": {"seed": 1}}` and `{"step": {"action": 12}}`, and
+ each is answered with an object named the same way, or `{"error": ..}`.
+ */
+pub struct EnvServer {
+  pub level: String,
+  pub opponents: Vec<String>,
+}
+
+impl EnvServer {
+  pub fn from_args(args: impl IntoIterator<Item = String>) -> Option<Self> {
+    let args: Vec<_> = args.into_iter().collect();
+    if !args.iter().any(|arg| arg == "--rl-env") {
+      return None;
+    }
+
+    let mut server = Self {
+      level: DEFAULT_LEVEL.to_string(),
+      opponents: vec![DEFAULT_OPPONENT.to_string()],
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+      match (arg.as_str(), args.next()) {
+        ("--level", Some(level)) => server.level = level,
+        ("--opponents", Some(opponents)) => {
+          server.opponents = opponents.split(',').map(|name| name.trim().to_string()).collect();
+        },
+        ("--level" | "--opponents", None) => eprintln!("{arg} needs a value"),
+        _ => {},
+      }
+    }
+
+    Some(server)
+  }
+
+  pub fn run(&self) {
+    let Some(level) = Levels::default().find(&self.level).cloned() else {
+      eprintln!("No level called {}", self.level);
+      return;
+    };
+
+    let opponents: Result<Vec<_>, _> = self.opponents.iter().map(|spec| Entrant::parse(spec, None)).collect();
+    let opponents = match opponents {
+      Ok(opponents) => opponents,
+      Err(error) => {
+        eprintln!("Bad opponent: {error}");
+        return;
+      },
+    };
+
+    let mut env = DominoEnv::new(level, opponents);
+    let mut stdout = std::io::stdout().lock();
+    for line in std::io::stdin().lock().lines() {
+      let Ok(line) = line else { break; };
+      if line.trim().is_empty() { continue; }
+
+      let response = match serde_json::from_str::<EnvRequest>(&line) {
+        Ok(EnvRequest::Spec) => EnvResponse::Spec(env.spec()),
+        Ok(EnvRequest::Reset { seed }) => EnvResponse::Reset(env.reset(seed)),
+        Ok(EnvRequest::Step { action }) => EnvResponse::Step(env.step(action)),
+        Err(error) => EnvResponse::Error(error.to_string()),
+      };
+
+      let written = serde_json::to_string(&response)
+        .map_err(|error| error.to_string())
+        .and_then(|json| writeln!(stdout, "{json}").and_then(|_| stdout.flush()).map_err(|error| error.to_string()));
+      if let Err(error) = written {
+        eprintln!("Could not answer: {error}");
+        break;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use super::*;
+
+  fn env(level: &str) -> DominoEnv {
+    let mut level = Levels::default().find(level).cloned().unwrap();
+    level.grid_width = 5;
+    level.grid_height = 4;
+    DominoEnv::new(level, vec![Entrant::parse("random", Some(Duration::ZERO)).unwrap()])
+  }
+
+  #[test]
+  fn steps_before_a_reset() {
+    let mut env = env(DEFAULT_LEVEL);
+    let step = env.step(env.spec().pass_action);
+    assert_eq!(step.reward, 0.);
+  }
+
+  #[test]
+  fn encodes_the_board_and_hand_one_hot() {
+    let mut env = env(DEFAULT_LEVEL);
+    let spec = env.spec();
+    let observation = env.reset(1);
+    assert_eq!(observation.board.len(), spec.board_shape.iter().product::<usize>());
+    assert_eq!(observation.hand.len(), spec.hand_shape.iter().product::<usize>());
+
+    let action = observation.action_mask.iter().position(|legal| *legal).unwrap();
+    let mv = env.decode(action).unwrap();
+    let observation = env.step(action).observation;
+
+    // Every cell is in exactly one channel, and the domino's cells are in their colors'
+    let [channels, height, width] = spec.board_shape;
+    for y in 0..height {
+      for x in 0..width {
+        let set: Vec<_> = (0..channels).filter(|channel| observation.board[(channel * height + y) * width + x] == 1.).collect();
+        assert_eq!(set.len(), 1);
+      }
+    }
+    let ([first, second], (first_color, second_color)) = (mv.area.cells(), mv.d_type.colors());
+    let channel_at = |cell: IVec2| (0..channels).find(|channel| {
+      observation.board[(channel * height + cell.y as usize) * width + cell.x as usize] == 1.
+    });
+    assert_eq!(channel_at(first), Some(1 + first_color.index()));
+    assert_eq!(channel_at(second), Some(1 + second_color.index()));
+
+    let hand = &env.game.hands[0];
+    for slot in 0..spec.hand_shape[0] {
+      let ones = observation.hand[slot * 2 * DominoColor::ALL.len()..(slot + 1) * 2 * DominoColor::ALL.len()]
+        .iter()
+        .filter(|value| **value == 1.)
+        .count();
+      assert_eq!(ones, if slot < hand.len() { 2 } else { 0 });
+    }
+  }
+
+  #[test]
+  fn the_mask_allows_exactly_the_moves_that_decode() {
+    let mut env = env(DEFAULT_LEVEL);
+    let spec = env.spec();
+    let observation = env.reset(2);
+    let legal = env.game.position().legal_moves();
+
+    for action in 0..spec.pass_action {
+      let decoded = env.decode(action);
+      assert_eq!(observation.action_mask[action], decoded.is_some());
+      if let Some(mv) = decoded {
+        assert!(legal.contains(&mv));
+      }
+    }
+    assert!(!observation.action_mask[spec.pass_action]);
+    // And every legal move has an action, doubles having one each way round
+    let decoded: Vec<_> = (0..spec.pass_action).filter_map(|action| env.decode(action)).collect();
+    assert!(legal.iter().all(|mv| decoded.contains(mv)));
+  }
+
+  #[test]
+  fn rewards_improving_the_score_whichever_way_it_counts() {
+    // More points win with free form, fewer pips with classic
+    for level in [DEFAULT_LEVEL, "Classic dominoes (block)"] {
+      let mut env = env(level);
+      let observation = env.reset(3);
+      let action = observation.action_mask.iter().position(|legal| *legal).unwrap();
+      assert!(env.step(action).reward > 0., "{level}");
+    }
+
+    let mut env = env(DEFAULT_LEVEL);
+    let observation = env.reset(3);
+    let illegal = observation.action_mask.iter().position(|legal| !*legal).unwrap();
+    assert!(env.step(illegal).reward <= ILLEGAL_ACTION_REWARD);
+  }
+}
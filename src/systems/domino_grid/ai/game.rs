@@ -0,0 +1,114 @@
+use crate::systems::random::GameRng;
+
+use super::super::draw_pile::*;
+use super::super::level::Level;
+use super::super::rules::free_form::FreeForm;
+use super::*;
+
+/**
+ A whole game played out without the ECS, for pitting strategies against
+ each other. It deals from the same random streams as the live game, so a
+ level and seed set up the same board, pile and hands either way, and it
+ scores turns the way `Turns` does.
+ */
+pub struct SimulatedGame {
+  pub board: Board,
+  pub rules: Box<dyn RuleSet>,
+  pub pile: DrawPile,
+  /** Each seat's dominoes, in the order they were dealt */
+  pub hands: Vec<Vec<DominoType>>,
+  pub scores: Vec<i32>,
+  pub current: usize,
+  /** How many placements have been made */
+  pub turns_played: usize,
+  hand_size: usize,
+  score_before_turn: i32,
+  passes_in_a_row: usize,
+}
+
+impl SimulatedGame {
+  /** Levels without hand settings are played with the default hand, as everyone needs one */
+  pub fn new(level: &Level, registry: &RuleSetRegistry, seats: usize, seed: u64) -> Self {
+    let rng = GameRng::new(seed);
+    let mut rules = registry.build(&level.rules, &mut rng.fork("rules")).unwrap_or_else(|error| {
+      warn!("Playing free form, as the level's rules can't be built: {error}");
+      Box::new(FreeForm)
+    });
+    let settings = level.hand.unwrap_or_default();
+    let mut pile = DrawPile::new(Some(settings), rules.deal(), &mut rng.fork("draw_pile"));
+    let hands = rules
+      .deal_hands(seats.max(1))
+      .unwrap_or_else(|| (0..seats.max(1)).map(|_| pile.deal_hand()).collect());
+    let board = Board::new(level.grid_width, level.grid_height);
+    let scores = (0..seats.max(1)).map(|seat| rules.seat_score(&board, seat).unwrap_or(0)).collect();
+
+    Self {
+      board,
+      rules,
+      pile,
+      hands,
+      scores,
+      current: 0,
+      turns_played: 0,
+      hand_size: settings.hand_size,
+      score_before_turn: 0,
+      passes_in_a_row: 0,
+    }
+  }
+
+  /** What the player whose turn it is gets to see */
+  pub fn position(&self) -> Position {
+    Position {
+      board: self.board.clone(),
+      rules: self.rules.boxed_clone(),
+      hand: self.hands[self.current].clone(),
+    }
+  }
+
+  /** Over the same way a game played in `Turns` is */
+  pub fn is_over(&self) -> bool {
+    let dominoes_left = self.hands.iter().map(Vec::len).sum::<usize>() + self.pile.remaining();
+    is_game_over(self.rules.as_ref(), &self.board, self.hands.len(), self.passes_in_a_row, dominoes_left)
+  }
+
+  /**
+    Plays the current player's move, or passes on `None`, then moves on to
+    the next player. A placement tops the hand back up from the pile.
+   */
+  pub fn play(&mut self, mv: Option<Move>) {
+    match mv {
+      Some(mv) => {
+        let hand = &mut self.hands[self.current];
+        hand.remove(mv.domino);
+        self.board.place(mv.area, mv.d_type);
+        self.rules.on_placed(&self.board, &mv.area, mv.d_type);
+
+        while hand.len() < self.hand_size {
+          let Some(d_type) = self.pile.draw() else { break; };
+          hand.push(d_type);
+        }
+
+        let total = self.rules.score(&self.board);
+        self.scores[self.current] += total - self.score_before_turn;
+        self.score_before_turn = total;
+        self.turns_played += 1;
+        self.passes_in_a_row = 0;
+      },
+      None => {
+        let can_move = can_move(self.rules.as_ref(), &self.board, &self.hands[self.current]);
+        self.rules.pass(&self.board, can_move);
+        self.passes_in_a_row += 1;
+      },
+    }
+
+    // Rules that score each seat by its hand keep the scores themselves
+    for (seat, score) in self.scores.iter_mut().enumerate() {
+      if let Some(kept) = self.rules.seat_score(&self.board, seat) {
+        *score = kept;
+      }
+    }
+
+    self.current = (self.current + 1) % self.hands.len();
+    self.rules.begin_turn(self.current);
+  }
+}
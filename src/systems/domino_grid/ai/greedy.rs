@@ -0,0 +1,36 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::systems::random::StreamRng;
+
+use super::*;
+
+/**
+ Takes whichever move scores the most right away. Once the budget is spent
+ it settles for the best of the moves it has looked at so far.
+ */
+pub struct Greedy {
+  /** How often a random legal move is played instead, to make for an easier opponent */
+  pub blunder_chance: f64,
+}
+
+impl Strategy for Greedy {
+  fn name(&self) -> &'static str {
+    "Greedy"
+  }
+
+  fn choose(&self, position: &Position, budget: &Budget, rng: &mut StreamRng) -> Option<Move> {
+    let moves = position.legal_moves();
+    if rng.gen_bool(self.blunder_chance) {
+      return moves.choose(rng).copied();
+    }
+
+    let scored: Vec<_> = moves
+      .into_iter()
+      .enumerate()
+      .take_while(|(index, _)| *index == 0 || !budget.is_spent())
+      .map(|(_, mv)| (position.after(mv).value(), mv))
+      .collect();
+    pick_best(&scored, rng)
+  }
+}
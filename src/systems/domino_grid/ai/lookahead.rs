@@ -0,0 +1,49 @@
+use crate::systems::random::StreamRng;
+
+use super::*;
+
+/**
+ Plans several of its own moves ahead, assuming the hand stays as it is.
+ Only the `width` most promising moves are followed at each step, which
+ keeps the search small enough to fit the budget; when the budget runs
+ out the remaining lines are judged on how they stand, and moves not yet
+ tried are left out.
+ */
+pub struct Lookahead {
+  /** How many moves to look ahead, counting the one being chosen */
+  pub depth: usize,
+  pub width: usize,
+}
+
+impl Lookahead {
+  /** The best value reachable from the position within `depth` more moves */
+  fn search(&self, position: &Position, depth: usize, budget: &Budget) -> f32 {
+    if depth == 0 || budget.is_spent() {
+      return position.value();
+    }
+
+    position.ranked_moves(budget)
+      .into_iter()
+      .take(self.width)
+      .map(|(_, _, next)| self.search(&next, depth - 1, budget))
+      .max_by(f32::total_cmp)
+      .unwrap_or_else(|| position.value())
+  }
+}
+
+impl Strategy for Lookahead {
+  fn name(&self) -> &'static str {
+    "Lookahead"
+  }
+
+  fn choose(&self, position: &Position, budget: &Budget, rng: &mut StreamRng) -> Option<Move> {
+    let depth = self.depth.max(1);
+    let scored: Vec<_> = position.ranked_moves(budget)
+      .into_iter()
+      .take(self.width.max(1))
+      .map(|(_, mv, next)| (self.search(&next, depth - 1, budget), mv))
+      .collect();
+
+    pick_best(&scored, rng)
+  }
+}
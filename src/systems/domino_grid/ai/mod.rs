@@ -0,0 +1,595 @@
+pub mod env;
+pub mod game;
+pub mod greedy;
+pub mod lookahead;
+pub mod monte_carlo;
+pub mod tournament;
+
+use std::time::{Duration, Instant};
+
+use bevy::tasks::{block_on, poll_once, AsyncComputeTaskPool, Task};
+use leafwing_input_manager::plugin::InputManagerSystem;
+use leafwing_input_manager::prelude::ActionState;
+use rand::seq::SliceRandom;
+
+use crate::prelude::*;
+use crate::systems::random::{GameRng, StreamRng};
+use crate::systems::console::AddConsoleCommand;
+use crate::systems::DominoControlAction;
+
+use super::board::*;
+use super::components::*;
+use super::console_commands::drop_from_hand;
+use super::draw_pile::InHoldSlot;
+use super::events::*;
+use super::net::NetSession;
+use super::replay::ReplayState;
+use super::rules::*;
+use super::tray::TraySlot;
+use super::turns::*;
+
+use greedy::*;
+use lookahead::*;
+use monte_carlo::*;
+
+use dragging::Draggable;
+use dragging::IsDragging;
+
+/** How long the computer waits before each step of its move, so it can be followed */
+const STEP_DELAY: f32 = 0.4;
+
+/**
+ Computer players for hotseat levels. On its turn the computer works out
+ every legal placement for its hand, lets its difficulty's strategy pick
+ one, and then plays it the way a person would: it picks the domino up,
+ turns it with the rotate and flip actions and drops it onto the area, so
+ the placement goes through `handle_drag_end` like any other.
+ */
+pub struct AiPlugin;
+
+impl Plugin for AiPlugin {
+  fn build(&self, app: &mut App) {
+    app
+      .init_resource::<AiPlayer>()
+      .add_systems(PreUpdate, play_ai_turn
+        .in_set(InputManagerSystem::ManualControl)
+        .run_if(in_state(GameState::PlayerTurn)))
+      .add_systems(Update, reset_ai_player.run_if(on_event::<LevelStarted>))
+      .add_console_command("solve", "[easy|normal|hard]", "Plays the move the computer would make from the current hand", solve_command)
+      ;
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Difficulty {
+  Easy,
+  Normal,
+  Hard,
+}
+
+impl Difficulty {
+  pub fn label(&self) -> &'static str {
+    match self {
+      Difficulty::Easy => "Easy",
+      Difficulty::Normal => "Normal",
+      Difficulty::Hard => "Hard",
+    }
+  }
+
+  pub fn strategy(&self) -> Box<dyn Strategy> {
+    match self {
+      Difficulty::Easy => Box::new(Greedy { blunder_chance: 0.3 }),
+      Difficulty::Normal => Box::new(Lookahead { depth: 2, width: 8 }),
+      Difficulty::Hard => Box::new(MonteCarlo { width: 12, playout_depth: 4 }),
+    }
+  }
+
+  /** How long the strategy may think for. It thinks off the main thread, over as many frames as that takes */
+  pub fn time_budget(&self) -> Duration {
+    match self {
+      Difficulty::Easy => Duration::from_millis(10),
+      Difficulty::Normal => Duration::from_millis(60),
+      Difficulty::Hard => Duration::from_millis(150),
+    }
+  }
+}
+
+/** A placement of one of the hand's dominoes */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Move {
+  /** Index into the hand */
+  pub domino: usize,
+  pub area: PlacementArea,
+  /** The domino as read in `area`, which may be flipped from how it is held */
+  pub d_type: DominoType,
+}
+
+/** What a strategy knows when choosing: the board, the rules and its own hand */
+pub struct Position {
+  pub board: Board,
+  pub rules: Box<dyn RuleSet>,
+  pub hand: Vec<DominoType>,
+}
+
+impl Clone for Position {
+  fn clone(&self) -> Self {
+    Self {
+      board: self.board.clone(),
+      rules: self.rules.boxed_clone(),
+      hand: self.hand.clone(),
+    }
+  }
+}
+
+impl Position {
+  /** Every way of placing a domino from the hand, in both orientations and both ways round */
+  pub fn legal_moves(&self) -> Vec<Move> {
+    let mut moves = vec![];
+    for area in self.board.free_areas() {
+      for (index, domino) in self.hand.iter().enumerate() {
+        // A double reads the same both ways round, so it is only tried once
+        let readings = if domino.flipped() == *domino { 1 } else { 2 };
+        for d_type in [*domino, domino.flipped()].into_iter().take(readings) {
+          if self.rules.validate(&self.board, &area, d_type).is_ok() {
+            moves.push(Move { domino: index, area, d_type });
+          }
+        }
+      }
+    }
+    moves
+  }
+
+  /** Makes the move the same way `handle_drag_end` does */
+  pub fn play(&mut self, mv: Move) {
+    self.board.place(mv.area, mv.d_type);
+    self.rules.on_placed(&self.board, &mv.area, mv.d_type);
+    self.hand.remove(mv.domino);
+  }
+
+  pub fn after(&self, mv: Move) -> Self {
+    let mut next = self.clone();
+    next.play(mv);
+    next
+  }
+
+  /** How good the position is for the player to move, higher being better */
+  pub fn value(&self) -> f32 {
+    self.rules.evaluate(&self.board) as f32
+  }
+
+  /**
+    The legal moves with the positions they lead to, best first. Once the
+    budget is spent no more moves are tried, bar the first.
+   */
+  pub fn ranked_moves(&self, budget: &Budget) -> Vec<(f32, Move, Position)> {
+    let mut ranked: Vec<_> = self.legal_moves()
+      .into_iter()
+      .enumerate()
+      .take_while(|(index, _)| *index == 0 || !budget.is_spent())
+      .map(|(_, mv)| {
+        let next = self.after(mv);
+        (next.value(), mv, next)
+      })
+      .collect();
+    ranked.sort_by(|(a, ..), (b, ..)| b.total_cmp(a));
+    ranked
+  }
+}
+
+/** When a strategy has to stop thinking */
+#[derive(Clone, Copy, Debug)]
+pub struct Budget {
+  deadline: Instant,
+}
+
+impl Budget {
+  pub fn new(time: Duration) -> Self {
+    Self { deadline: Instant::now() + time }
+  }
+
+  pub fn is_spent(&self) -> bool {
+    Instant::now() >= self.deadline
+  }
+}
+
+/**
+ How a computer player picks its move.
+
+ Strategies should look at the budget and settle for what they have found
+ once it is spent. Anything random should come from `rng`, so the same seed
+ leads to the same choices (given the same time to think).
+ */
+pub trait Strategy: Send + Sync + 'static {
+  fn name(&self) -> &'static str;
+
+  /** One of the position's legal moves, or `None` to pass */
+  fn choose(&self, position: &Position, budget: &Budget, rng: &mut StreamRng) -> Option<Move>;
+}
+
+/** The move with the highest value, picking at random between equally good ones */
+pub fn pick_best(scored: &[(f32, Move)], rng: &mut StreamRng) -> Option<Move> {
+  let best = scored.iter().map(|(value, _)| *value).max_by(f32::total_cmp)?;
+  let tied: Vec<_> = scored.iter().filter(|(value, _)| *value == best).map(|(_, mv)| *mv).collect();
+  tied.choose(rng).copied()
+}
+
+/** A strategy thinking in the background, and the hand's dominoes its moves are numbered by */
+struct Choosing {
+  task: Task<(Option<Move>, StreamRng)>,
+  dominoes: Vec<Entity>,
+}
+
+/** What the computer is in the middle of */
+#[derive(Clone, Copy, Debug, Default)]
+enum AiStep {
+  #[default]
+  Thinking,
+  /** Has picked up the domino, and turns it next */
+  PickedUp(Entity, Move),
+  /** Has turned the domino, and drops it next */
+  Turned(Entity, Move),
+}
+
+#[derive(Resource)]
+pub struct AiPlayer {
+  rng: StreamRng,
+  step: AiStep,
+  choosing: Option<Choosing>,
+  waited: f32,
+  /** Actions pressed last frame, to be let go of again */
+  pressed: Vec<DominoControlAction>,
+}
+
+impl Default for AiPlayer {
+  fn default() -> Self {
+    Self::new(GameRng::new(0))
+  }
+}
+
+impl AiPlayer {
+  pub fn new(rng: GameRng) -> Self {
+    Self {
+      rng: rng.fork("ai"),
+      step: AiStep::Thinking,
+      choosing: None,
+      waited: 0.,
+      pressed: vec![],
+    }
+  }
+
+  fn press(&mut self, action_state: &mut ActionState<DominoControlAction>, action: DominoControlAction) {
+    action_state.press(&action);
+    self.pressed.push(action);
+  }
+}
+
+fn reset_ai_player(
+  mut level_started_evr: EventReader<LevelStarted>,
+  mut ai: ResMut<AiPlayer>,
+) {
+  for event in level_started_evr.read() {
+    *ai = AiPlayer::new(GameRng::new(event.seed));
+  }
+}
+
+/** Which of rotate and flip (applied in that order, as `handle_dragging` does) turn the domino into the one wanted */
+fn turns_needed(domino: &Domino, horizontal: bool, d_type: DominoType) -> (bool, bool) {
+  for rotate in [false, true] {
+    for flip in [false, true] {
+      let (mut turned_horizontal, mut turned) = (domino.horizontal, domino.d_type);
+      if rotate {
+        if !turned_horizontal {
+          turned = turned.flipped();
+        }
+        turned_horizontal = !turned_horizontal;
+      }
+      if flip {
+        turned = turned.flipped();
+      }
+      if turned_horizontal == horizontal && turned == d_type {
+        return (rotate, flip);
+      }
+    }
+  }
+
+  // Dropping turns the domino to fit anyway, if placement allows it
+  (false, false)
+}
+
+/**
+  Gives up the turn when the computer has nothing to place: it passes if the
+  rules allow it, and otherwise draws and thinks again. Should the rules
+  refuse both, the turn is passed on regardless so the game can't get stuck.
+ */
+fn give_up_turn(
+  ai: &mut AiPlayer,
+  action_state: &mut ActionState<DominoControlAction>,
+  passed_evw: &mut EventWriter<TurnPassed>,
+  rules: &dyn RuleSet,
+  board: &Board,
+) {
+  // Tried out on a copy, as pressing the action asks the rules for real
+  let mut trial = rules.boxed_clone();
+  if trial.pass(board, false) {
+    ai.press(action_state, DominoControlAction::Pass);
+  } else if trial.draw(board).is_some() {
+    ai.press(action_state, DominoControlAction::Draw);
+  } else {
+    warn!("The rules let the computer neither pass nor draw, so its turn is passed on");
+    passed_evw.send(TurnPassed);
+  }
+}
+
+/** Plays a step of the computer's move, before the game's systems see this frame's input */
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn play_ai_turn(
+  mut commands: Commands,
+  mut ai: ResMut<AiPlayer>,
+  mut action_state: ResMut<ActionState<DominoControlAction>>,
+  mut drag_end_evw: EventWriter<DragEndEvent>,
+  mut passed_evw: EventWriter<TurnPassed>,
+  turns: Res<Turns>,
+  replay: Res<ReplayState>,
+  net: Option<Res<NetSession>>,
+  board: Res<Board>,
+  rules: Res<ActiveRuleSet>,
+  time: Res<Time>,
+  mut hand: Query<
+    (Entity, &TraySlot, &Domino, &mut Transform, Option<&Owner>),
+    (With<Draggable>, Without<InHoldSlot>),
+  >,
+) {
+  for action in std::mem::take(&mut ai.pressed) {
+    action_state.release(&action);
+  }
+
+  // A replay already has the computer's moves in it, and so do the host's commands in a network game
+  let Some(difficulty) = turns.current_ai() else {
+    ai.step = AiStep::Thinking;
+    ai.choosing = None;
+    ai.waited = 0.;
+    return;
+  };
+  let client = net.is_some_and(|net| !net.is_host());
+  if replay.playback.is_some() || client || turns.is_over() || rules.0.is_game_over(&board) {
+    return;
+  }
+
+  // Checked on every frame until the strategy has made up its mind
+  if let Some(choosing) = ai.choosing.as_mut() {
+    let Some((chosen, rng)) = block_on(poll_once(&mut choosing.task)) else {
+      return;
+    };
+    let dominoes = std::mem::take(&mut choosing.dominoes);
+    ai.choosing = None;
+    ai.rng = rng;
+    debug!("{} chose {chosen:?}", turns.players[turns.current].name);
+
+    match chosen {
+      Some(mv) => {
+        let entity = dominoes[mv.domino];
+        commands.entity(entity).insert(IsDragging);
+        ai.step = AiStep::PickedUp(entity, mv);
+      },
+      None => give_up_turn(&mut ai, &mut action_state, &mut passed_evw, rules.0.as_ref(), &board),
+    }
+    return;
+  }
+
+  ai.waited += time.delta_secs();
+  if ai.waited < STEP_DELAY {
+    return;
+  }
+  ai.waited = 0.;
+
+  match ai.step {
+    AiStep::Thinking => {
+      // Tray slots keep the hand in the same order on every run, unlike the query
+      let mut dominoes: Vec<_> = hand
+        .iter()
+        .filter(|(.., owner)| turns.is_current(*owner))
+        .map(|(entity, slot, domino, ..)| (*slot, entity, domino.d_type))
+        .collect();
+      dominoes.sort_by_key(|(slot, ..)| slot.0);
+
+      let position = Position {
+        board: board.clone(),
+        rules: rules.0.boxed_clone(),
+        hand: dominoes.iter().map(|(.., d_type)| *d_type).collect(),
+      };
+      let strategy = difficulty.strategy();
+      let budget = difficulty.time_budget();
+      let mut rng = ai.rng.clone();
+      let task = AsyncComputeTaskPool::get().spawn(async move {
+        let chosen = info_span!("ai_choose", strategy = strategy.name(), hand = position.hand.len())
+          .in_scope(|| strategy.choose(&position, &Budget::new(budget), &mut rng));
+        (chosen, rng)
+      });
+      ai.choosing = Some(Choosing { task, dominoes: dominoes.iter().map(|(_, entity, _)| *entity).collect() });
+    },
+    AiStep::PickedUp(entity, mv) => {
+      if let Ok((.., domino, _, _)) = hand.get(entity) {
+        let (rotate, flip) = turns_needed(domino, mv.area.is_horizontal(), mv.d_type);
+        if rotate {
+          ai.press(&mut action_state, DominoControlAction::Rotate);
+        }
+        if flip {
+          ai.press(&mut action_state, DominoControlAction::Flip);
+        }
+      }
+      ai.step = AiStep::Turned(entity, mv);
+    },
+    AiStep::Turned(entity, mv) => {
+      if let Ok((.., mut transform, _)) = hand.get_mut(entity) {
+        transform.translation = mv.area.center().extend(transform.translation.z);
+        drag_end_evw.send(DragEndEvent { draggable: entity });
+      }
+      ai.step = AiStep::Thinking;
+    },
+  }
+}
+
+fn solve_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+  let difficulty = match args {
+    [] | ["hard"] => Difficulty::Hard,
+    ["normal"] => Difficulty::Normal,
+    ["easy"] => Difficulty::Easy,
+    _ => return Err("the computer plays easy, normal or hard".to_string()),
+  };
+
+  let mut hand = world.query_filtered::<(Entity, &TraySlot, &Domino, Option<&Owner>), (With<Draggable>, Without<InHoldSlot>)>();
+  let turns = world.resource::<Turns>();
+  let mut dominoes: Vec<_> = hand
+    .iter(world)
+    .filter(|(.., owner)| turns.is_current(*owner))
+    .map(|(entity, slot, domino, _)| (*slot, entity, domino.d_type))
+    .collect();
+  dominoes.sort_by_key(|(slot, ..)| slot.0);
+
+  let position = Position {
+    board: world.resource::<Board>().clone(),
+    rules: world.resource::<ActiveRuleSet>().0.boxed_clone(),
+    hand: dominoes.iter().map(|(.., d_type)| *d_type).collect(),
+  };
+  let mut rng = world.resource::<AiPlayer>().rng.clone();
+  let strategy = difficulty.strategy();
+  let chosen = info_span!("ai_choose", strategy = strategy.name(), hand = position.hand.len())
+    .in_scope(|| strategy.choose(&position, &Budget::new(difficulty.time_budget()), &mut rng));
+  let Some(mv) = chosen else {
+    return Err("there's nothing the hand can place".to_string());
+  };
+
+  let (_, entity, _) = dominoes[mv.domino];
+  drop_from_hand(world, entity, mv.area, mv.d_type);
+  Ok(format!("Placing {:?} at {:?}", mv.d_type, mv.area))
+}
+
+#[cfg(test)]
+mod tests {
+  use bevy::time::TimeUpdateStrategy;
+
+  use super::super::draw_pile::DrawPile;
+  use super::super::rules::color_match::ColorMatch;
+  use super::super::rules::free_form::FreeForm;
+
+  use super::*;
+
+  use DominoColor::*;
+
+  /** Refuses every placement, pass and draw */
+  #[derive(Clone)]
+  struct Stubborn;
+
+  impl RuleSet for Stubborn {
+    fn name(&self) -> &'static str {
+      "Stubborn"
+    }
+
+    fn validate(&self, _board: &Board, _area: &PlacementArea, _d_type: DominoType) -> Result<(), PlacementRejection> {
+      Err(PlacementRejection::Occupied)
+    }
+
+    fn on_placed(&mut self, _board: &Board, _area: &PlacementArea, _d_type: DominoType) {}
+
+    fn score(&self, _board: &Board) -> i32 {
+      0
+    }
+
+    fn is_game_over(&self, _board: &Board) -> bool {
+      false
+    }
+
+    fn pass(&mut self, _board: &Board, _can_move: bool) -> bool {
+      false
+    }
+
+    fn boxed_clone(&self) -> Box<dyn RuleSet> {
+      Box::new(self.clone())
+    }
+  }
+
+  fn color_rules() -> Box<dyn RuleSet> {
+    Box::new(RuleStack(vec![Box::new(FreeForm), Box::new(ColorMatch)]))
+  }
+
+  fn position(hand: Vec<DominoType>) -> Position {
+    let mut board = Board::new(4, 4);
+    board.place(PlacementArea::Horizontal { left_cell_col: 0, row: 0 }, DominoType::new(Blue, Blue));
+    board.place(PlacementArea::Vertical { bottom_cell_row: 1, column: 3 }, DominoType::new(Red, Green));
+    Position { board, rules: color_rules(), hand }
+  }
+
+  #[test]
+  fn every_strategy_only_picks_legal_moves() {
+    let held = position(vec![DominoType::new(Red, Blue), DominoType::new(Blue, Blue), DominoType::new(Green, Yellow)]);
+    let legal = held.legal_moves();
+
+    for difficulty in [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard] {
+      let strategy = difficulty.strategy();
+      for seed in 0..4 {
+        let mut rng = GameRng::new(seed).fork("ai");
+        for time in [difficulty.time_budget(), Duration::ZERO] {
+          let chosen = strategy.choose(&held, &Budget::new(time), &mut rng);
+          assert!(chosen.is_some_and(|mv| legal.contains(&mv)), "{} chose {chosen:?}", strategy.name());
+        }
+      }
+
+      let empty = position(vec![]);
+      assert_eq!(strategy.choose(&empty, &Budget::new(difficulty.time_budget()), &mut GameRng::new(0).fork("ai")), None);
+    }
+  }
+
+  /** Two computers, the first holding a domino it can't place */
+  fn app(rules: Box<dyn RuleSet>) -> App {
+    let mut board = Board::new(4, 1);
+    board.place(PlacementArea::Horizontal { left_cell_col: 0, row: 0 }, DominoType::new(Red, Red));
+
+    let mut app = App::new();
+    app
+      .add_plugins((MinimalPlugins, bevy::state::app::StatesPlugin))
+      .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(STEP_DELAY)))
+      .init_state::<GameState>()
+      .init_resource::<ActionState<DominoControlAction>>()
+      .init_resource::<AiPlayer>()
+      .init_resource::<ReplayState>()
+      .init_resource::<DrawPile>()
+      .insert_resource(ActiveRuleSet(rules))
+      .insert_resource(board)
+      .insert_resource(Turns::new(0, &[Difficulty::Easy, Difficulty::Easy]))
+      .add_event::<DragEndEvent>()
+      .add_event::<TurnPassed>()
+      .add_systems(PreUpdate, play_ai_turn.run_if(in_state(GameState::PlayerTurn)))
+      .add_systems(Update, (
+        handle_rule_actions,
+        end_turn_on_pass.run_if(in_state(GameState::PlayerTurn).and(on_event::<TurnPassed>)),
+      ).chain());
+
+    for (slot, seat) in [0, 1].into_iter().enumerate() {
+      app.world_mut().spawn((
+        Domino { d_type: DominoType::new(Blue, Blue), horizontal: true },
+        Draggable,
+        TraySlot(slot),
+        Transform::default(),
+        Owner(seat),
+      ));
+    }
+    app.world_mut().resource_mut::<NextState<GameState>>().set(GameState::PlayerTurn);
+    app
+  }
+
+  #[test]
+  fn a_computer_with_no_move_gives_up_its_turn() {
+    for rules in [color_rules(), Box::new(Stubborn)] {
+      let name = rules.name();
+      let mut app = app(rules);
+
+      // The strategy thinks on another thread, so this waits for it a frame at a time
+      for _ in 0..200 {
+        app.update();
+        if app.world().resource::<Turns>().current == 1 { break; }
+        std::thread::sleep(Duration::from_millis(1));
+      }
+      assert_eq!(app.world().resource::<Turns>().current, 1, "stuck with {name}");
+    }
+  }
+}
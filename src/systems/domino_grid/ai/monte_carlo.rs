@@ -0,0 +1,57 @@
+use rand::seq::SliceRandom;
+
+use crate::systems::random::StreamRng;
+
+use super::*;
+
+/** Playouts per candidate after which more would hardly change the averages */
+const MAX_PLAYOUTS: u32 = 64;
+
+/**
+ Judges the most promising moves by how random games carry on from them.
+ Each candidate is played out with random moves from the rest of the hand,
+ over and over until the budget is spent, and the best average wins. Every
+ candidate gets at least one playout.
+ */
+pub struct MonteCarlo {
+  /** How many of the best-looking moves are played out */
+  pub width: usize,
+  /** How many moves each playout goes on for */
+  pub playout_depth: usize,
+}
+
+impl MonteCarlo {
+  fn playout(&self, mut position: Position, rng: &mut StreamRng) -> f32 {
+    for _ in 0..self.playout_depth {
+      let Some(mv) = position.legal_moves().choose(rng).copied() else { break; };
+      position.play(mv);
+    }
+    position.value()
+  }
+}
+
+impl Strategy for MonteCarlo {
+  fn name(&self) -> &'static str {
+    "Monte Carlo"
+  }
+
+  fn choose(&self, position: &Position, budget: &Budget, rng: &mut StreamRng) -> Option<Move> {
+    let candidates: Vec<_> = position.ranked_moves(budget).into_iter().take(self.width.max(1)).collect();
+    let mut totals = vec![0.; candidates.len()];
+
+    let mut playouts = 0;
+    while playouts < MAX_PLAYOUTS && (playouts == 0 || !budget.is_spent()) {
+      for ((_, _, next), total) in candidates.iter().zip(totals.iter_mut()) {
+        *total += self.playout(next.clone(), rng);
+      }
+      playouts += 1;
+    }
+
+    let scored: Vec<_> = candidates
+      .iter()
+      .zip(totals)
+      .map(|((_, mv, _), total)| (total / playouts as f32, *mv))
+      .collect();
+    pick_best(&scored, rng)
+  }
+}
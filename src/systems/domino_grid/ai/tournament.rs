@@ -0,0 +1,521 @@
+use std::cmp::Ordering as Outcome;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rand::Rng;
+use serde::Serialize;
+
+use crate::systems::random::{GameRng, SeedSource};
+use crate::systems::settings::{settings_dir, write_text};
+
+use super::super::level::*;
+use super::game::SimulatedGame;
+use super::*;
+
+const DEFAULT_GAMES: usize = 100;
+const DEFAULT_ENTRANTS: [&str; 3] = ["easy", "normal", "hard"];
+const DEFAULT_LEVEL: &str = "Versus computer";
+const STARTING_ELO: f64 = 1500.;
+const ELO_K: f64 = 16.;
+
+/**
+ Plays computer players against each other without opening a window, to
+ compare strategies and tune the difficulties. Every pair of entrants plays
+ the same number of seeded games, taking turns to go first, spread over all
+ cores. The standings (win rates, average scores and Elo ratings) are
+ printed and written out as CSV and JSON.
+
+ Started with `--tournament`, optionally followed by:
+ - `--games <n>`: games per pair of entrants
+ - `--entrants <a,b,..>`: `easy`, `normal`, `hard`, `random`, `greedy[:blunder]`,
+   `lookahead[:depth[:width]]` or `montecarlo[:width[:depth]]`
+ - `--level <name or number>` and `--board <width>x<height>`
+ - `--budget <ms>`: thinking time for every entrant, instead of their own
+ - `--threads <n>`, `--out <path>` (without extension)
+ - `--seed <n>` or `--daily`, as for the game
+
+ Strategies with a time budget play differently on slower machines, so only
+ the seeds of a tournament can be relied on to repeat. Use a release build,
+ as strategies are slow without optimizations.
+ */
+#[derive(Clone, Debug)]
+pub struct TournamentConfig {
+  pub games: usize,
+  pub entrants: Vec<String>,
+  pub level: String,
+  pub board: Option<IVec2>,
+  pub seed: u64,
+  pub budget: Option<Duration>,
+  pub threads: usize,
+  pub out: Option<PathBuf>,
+}
+
+impl TournamentConfig {
+  /** The tournament asked for on the command line, if any */
+  pub fn from_args(args: impl IntoIterator<Item = String>) -> Option<Self> {
+    let args: Vec<_> = args.into_iter().collect();
+    if !args.iter().any(|arg| arg == "--tournament") {
+      return None;
+    }
+
+    let mut config = Self {
+      games: DEFAULT_GAMES,
+      entrants: DEFAULT_ENTRANTS.iter().map(|name| name.to_string()).collect(),
+      level: DEFAULT_LEVEL.to_string(),
+      board: None,
+      seed: SeedSource::from_args(args.iter().cloned()).seed(),
+      budget: None,
+      threads: std::thread::available_parallelism().map_or(1, |threads| threads.get()),
+      out: None,
+    };
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+      let mut value = |name: &str| {
+        let value = args.next();
+        if value.is_none() {
+          eprintln!("{name} needs a value");
+        }
+        value
+      };
+
+      match arg.as_str() {
+        "--games" => match value("--games").map(|games| games.parse()) {
+          Some(Ok(games)) => config.games = games,
+          _ => eprintln!("--games needs a whole number"),
+        },
+        "--entrants" => if let Some(entrants) = value("--entrants") {
+          config.entrants = entrants.split(',').map(|name| name.trim().to_string()).collect();
+        },
+        "--level" => if let Some(level) = value("--level") {
+          config.level = level;
+        },
+        "--board" => match value("--board").as_deref().and_then(parse_board) {
+          Some(board) => config.board = Some(board),
+          None => eprintln!("--board needs a size like 10x10"),
+        },
+        "--budget" => match value("--budget").map(|budget| budget.parse()) {
+          Some(Ok(millis)) => config.budget = Some(Duration::from_millis(millis)),
+          _ => eprintln!("--budget needs a whole number of milliseconds"),
+        },
+        "--threads" => match value("--threads").map(|threads| threads.parse::<usize>()) {
+          Some(Ok(threads)) => config.threads = threads.max(1),
+          _ => eprintln!("--threads needs a whole number"),
+        },
+        "--out" => if let Some(out) = value("--out") {
+          config.out = Some(PathBuf::from(out));
+        },
+        _ => {},
+      }
+    }
+
+    Some(config)
+  }
+
+  pub fn run(&self) {
+    let entrants: Vec<_> = self.entrants
+      .iter()
+      .filter_map(|spec| {
+        let entrant = Entrant::parse(spec, self.budget);
+        if let Err(error) = &entrant {
+          eprintln!("Skipping entrant {spec}: {error}");
+        }
+        entrant.ok()
+      })
+      .collect();
+    if entrants.len() < 2 {
+      eprintln!("A tournament needs at least two entrants");
+      return;
+    }
+
+    let Some(mut level) = Levels::default().find(&self.level).cloned() else {
+      eprintln!("No level called {}", self.level);
+      return;
+    };
+    if let Some(board) = self.board {
+      level.grid_width = board.x;
+      level.grid_height = board.y;
+    }
+
+    let jobs = self.schedule(entrants.len());
+    println!(
+      "Playing {} games of {} ({}x{}) between {} entrants on {} threads, seed {}",
+      jobs.len(), level.name, level.grid_width, level.grid_height, entrants.len(), self.threads, self.seed,
+    );
+
+    let results = play_all(&jobs, &entrants, &level, self.threads);
+    let report = Report::new(self, &level, &entrants, &results);
+    report.print();
+
+    let out = self.out.clone().or_else(|| {
+      let stamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+      settings_dir().map(|dir| dir.join("tournaments").join(format!("tournament-{stamp}")))
+    });
+    match out {
+      Some(out) => report.write(&out),
+      None => eprintln!("Nowhere to save the results to, pass --out"),
+    }
+  }
+
+  /** Every pair of entrants plays `games` games, swapping who goes first each time */
+  fn schedule(&self, entrants: usize) -> Vec<Job> {
+    let mut seeds = GameRng::new(self.seed).fork("tournament");
+    let mut jobs = vec![];
+    for first in 0..entrants {
+      for second in first + 1..entrants {
+        for game in 0..self.games {
+          let seats = if game % 2 == 0 { [first, second] } else { [second, first] };
+          jobs.push(Job { seats, seed: seeds.gen() });
+        }
+      }
+    }
+    jobs
+  }
+}
+
+fn parse_board(size: &str) -> Option<IVec2> {
+  let (width, height) = size.split_once('x')?;
+  let board = IVec2::new(width.parse().ok()?, height.parse().ok()?);
+  (board.x > 1 && board.y > 1).then_some(board)
+}
+
+/** A strategy taking part, and how long it may think per move */
+pub struct Entrant {
+  pub name: String,
+  pub strategy: Box<dyn Strategy>,
+  pub budget: Duration,
+}
+
+impl Entrant {
+  /** Reads an entrant like `hard` or `lookahead:3:6`. Left out parameters take their usual values */
+  pub fn parse(spec: &str, budget: Option<Duration>) -> Result<Self, String> {
+    let mut parts = spec.split(':');
+    let kind = parts.next().unwrap_or_default().to_ascii_lowercase();
+    let params = parts
+      .map(|param| param.parse::<f64>().map_err(|_| format!("{param} is not a number")))
+      .collect::<Result<Vec<_>, _>>()?;
+    let param = |index: usize, default: f64| params.get(index).copied().unwrap_or(default);
+
+    let difficulty = match kind.as_str() {
+      "easy" => Some(Difficulty::Easy),
+      "normal" => Some(Difficulty::Normal),
+      "hard" => Some(Difficulty::Hard),
+      _ => None,
+    };
+
+    let strategy: Box<dyn Strategy> = match (difficulty, kind.as_str()) {
+      (Some(difficulty), _) => difficulty.strategy(),
+      (None, "random") => Box::new(Greedy { blunder_chance: 1. }),
+      (None, "greedy") => Box::new(Greedy { blunder_chance: param(0, 0.).clamp(0., 1.) }),
+      (None, "lookahead") => Box::new(Lookahead { depth: param(0, 2.) as usize, width: param(1, 8.) as usize }),
+      (None, "montecarlo") => Box::new(MonteCarlo { width: param(0, 12.) as usize, playout_depth: param(1, 4.) as usize }),
+      _ => return Err("unknown strategy".to_string()),
+    };
+
+    Ok(Self {
+      name: spec.to_string(),
+      strategy,
+      budget: budget.unwrap_or_else(|| difficulty.unwrap_or(Difficulty::Normal).time_budget()),
+    })
+  }
+}
+
+/** One game to be played: which entrant sits where, and the seed it is dealt from */
+#[derive(Clone, Copy, Debug)]
+struct Job {
+  seats: [usize; 2],
+  seed: u64,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct GameResult {
+  seats: [usize; 2],
+  scores: [i32; 2],
+  /** How the first seat did against the second, by the rules' idea of a better score */
+  outcome: Outcome,
+}
+
+fn play_game(job: Job, entrants: &[Entrant], level: &Level, registry: &RuleSetRegistry) -> GameResult {
+  let mut game = SimulatedGame::new(level, registry, job.seats.len(), job.seed);
+  let rng = GameRng::new(job.seed);
+  let mut rngs: Vec<_> = (0..job.seats.len()).map(|seat| rng.fork(&format!("ai {seat}"))).collect();
+
+  while !game.is_over() {
+    let entrant = &entrants[job.seats[game.current]];
+    let chosen = entrant.strategy.choose(&game.position(), &Budget::new(entrant.budget), &mut rngs[game.current]);
+    game.play(chosen);
+  }
+
+  let outcome = game.rules.compare_scores(game.scores[0], game.scores[1]);
+  GameResult { seats: job.seats, scores: [game.scores[0], game.scores[1]], outcome }
+}
+
+/** Plays the jobs on `threads` threads, giving the results back in the jobs' order */
+fn play_all(jobs: &[Job], entrants: &[Entrant], level: &Level, threads: usize) -> Vec<GameResult> {
+  let registry = RuleSetRegistry::default();
+  let next = AtomicUsize::new(0);
+  let results = Mutex::new(Vec::with_capacity(jobs.len()));
+  let report_every = (jobs.len() / 10).max(1);
+
+  std::thread::scope(|scope| {
+    for _ in 0..threads.min(jobs.len()) {
+      scope.spawn(|| loop {
+        let index = next.fetch_add(1, Ordering::Relaxed);
+        let Some(job) = jobs.get(index) else { break; };
+        let result = play_game(*job, entrants, level, &registry);
+
+        let mut results = results.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        results.push((index, result));
+        if results.len() % report_every == 0 {
+          eprintln!("{}/{} games played", results.len(), jobs.len());
+        }
+      });
+    }
+  });
+
+  let mut results = results.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner());
+  results.sort_by_key(|(index, _)| *index);
+  results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+struct Standing {
+  entrant: String,
+  games: usize,
+  wins: usize,
+  draws: usize,
+  losses: usize,
+  win_rate: f64,
+  average_score: f64,
+  elo: f64,
+  #[serde(skip)]
+  total_score: i64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct Matchup {
+  first: String,
+  second: String,
+  games: usize,
+  first_wins: usize,
+  second_wins: usize,
+  draws: usize,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct Report {
+  level: String,
+  board: [i32; 2],
+  seed: u64,
+  games_per_pair: usize,
+  standings: Vec<Standing>,
+  matchups: Vec<Matchup>,
+}
+
+impl Report {
+  fn new(config: &TournamentConfig, level: &Level, entrants: &[Entrant], results: &[GameResult]) -> Self {
+    let mut standings: Vec<_> = entrants
+      .iter()
+      .map(|entrant| Standing { entrant: entrant.name.clone(), elo: STARTING_ELO, ..default() })
+      .collect();
+    let mut matchups = BTreeMap::new();
+
+    // Ratings are updated game by game in schedule order, so they come out the same however the games were spread
+    for result in results {
+      let [a, b] = result.seats;
+      // How the first seat did: 1 for a win, a half for a draw
+      let outcome = match result.outcome {
+        Outcome::Greater => 1.,
+        Outcome::Less => 0.,
+        Outcome::Equal => 0.5,
+      };
+
+      let expected = 1. / (1. + 10f64.powf((standings[b].elo - standings[a].elo) / 400.));
+      let change = ELO_K * (outcome - expected);
+      standings[a].elo += change;
+      standings[b].elo -= change;
+
+      for (seat, entrant) in result.seats.into_iter().enumerate() {
+        let standing = &mut standings[entrant];
+        standing.games += 1;
+        standing.total_score += result.scores[seat] as i64;
+        let seat_outcome = if seat == 0 { result.outcome } else { result.outcome.reverse() };
+        match seat_outcome {
+          Outcome::Greater => standing.wins += 1,
+          Outcome::Less => standing.losses += 1,
+          Outcome::Equal => standing.draws += 1,
+        }
+      }
+
+      let (first, second) = (a.min(b), a.max(b));
+      let first_seat = if first == a { 0 } else { 1 };
+      let matchup = matchups.entry((first, second)).or_insert_with(|| Matchup {
+        first: entrants[first].name.clone(),
+        second: entrants[second].name.clone(),
+        games: 0,
+        first_wins: 0,
+        second_wins: 0,
+        draws: 0,
+      });
+      matchup.games += 1;
+      let first_outcome = if first_seat == 0 { result.outcome } else { result.outcome.reverse() };
+      match first_outcome {
+        Outcome::Greater => matchup.first_wins += 1,
+        Outcome::Less => matchup.second_wins += 1,
+        Outcome::Equal => matchup.draws += 1,
+      }
+    }
+
+    for standing in standings.iter_mut() {
+      let games = standing.games.max(1) as f64;
+      standing.win_rate = standing.wins as f64 / games;
+      standing.average_score = standing.total_score as f64 / games;
+    }
+    standings.sort_by(|a, b| b.elo.total_cmp(&a.elo));
+
+    Self {
+      level: level.name.clone(),
+      board: [level.grid_width, level.grid_height],
+      seed: config.seed,
+      games_per_pair: config.games,
+      standings,
+      matchups: matchups.into_values().collect(),
+    }
+  }
+
+  fn to_csv(&self) -> String {
+    let mut csv = "entrant,games,wins,draws,losses,win_rate,average_score,elo\n".to_string();
+    for standing in &self.standings {
+      csv += &format!(
+        "{},{},{},{},{},{:.3},{:.2},{:.0}\n",
+        standing.entrant, standing.games, standing.wins, standing.draws, standing.losses,
+        standing.win_rate, standing.average_score, standing.elo,
+      );
+    }
+    csv
+  }
+
+  fn print(&self) {
+    println!("{:<20} {:>6} {:>6} {:>6} {:>6} {:>8} {:>9} {:>6}", "entrant", "games", "wins", "draws", "losses", "win rate", "avg score", "elo");
+    for standing in &self.standings {
+      println!(
+        "{:<20} {:>6} {:>6} {:>6} {:>6} {:>7.1}% {:>9.2} {:>6.0}",
+        standing.entrant, standing.games, standing.wins, standing.draws, standing.losses,
+        standing.win_rate * 100., standing.average_score, standing.elo,
+      );
+    }
+  }
+
+  fn write(&self, out: &std::path::Path) {
+    let json = match serde_json::to_string_pretty(self) {
+      Ok(json) => json,
+      Err(error) => {
+        eprintln!("Could not write the results as JSON: {error}");
+        return;
+      },
+    };
+
+    for (path, contents) in [(out.with_extension("csv"), self.to_csv()), (out.with_extension("json"), json)] {
+      match write_text(&path, &contents) {
+        Ok(()) => println!("Wrote {}", path.display()),
+        Err(error) => eprintln!("Could not write {}: {error}", path.display()),
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn config(games: usize) -> TournamentConfig {
+    TournamentConfig {
+      games,
+      entrants: vec!["easy".to_string(), "random".to_string()],
+      level: DEFAULT_LEVEL.to_string(),
+      board: None,
+      seed: 5,
+      budget: None,
+      threads: 1,
+      out: None,
+    }
+  }
+
+  #[test]
+  fn every_pair_plays_taking_turns_to_go_first() {
+    let jobs = config(4).schedule(3);
+    assert_eq!(jobs.len(), 3 * 4);
+
+    for pair in [[0, 1], [0, 2], [1, 2]] {
+      let firsts: Vec<_> = jobs.iter()
+        .filter(|job| job.seats == pair || job.seats == [pair[1], pair[0]])
+        .map(|job| job.seats[0])
+        .collect();
+      assert_eq!(firsts, [pair[0], pair[1], pair[0], pair[1]]);
+    }
+
+    let seeds: Vec<_> = jobs.iter().map(|job| job.seed).collect();
+    assert_eq!(seeds, config(4).schedule(3).iter().map(|job| job.seed).collect::<Vec<_>>());
+    assert!(seeds.iter().enumerate().all(|(index, seed)| !seeds[..index].contains(seed)));
+  }
+
+  fn report(results: &[GameResult]) -> Report {
+    let entrants: Vec<_> = ["easy", "random"].iter().map(|spec| Entrant::parse(spec, None).unwrap()).collect();
+    let level = Levels::default().find(DEFAULT_LEVEL).cloned().unwrap();
+    Report::new(&config(1), &level, &entrants, results)
+  }
+
+  #[test]
+  fn ratings_move_by_how_surprising_the_result_was() {
+    let win = GameResult { seats: [1, 0], scores: [6, 2], outcome: Outcome::Greater };
+    let report = report(&[win]);
+
+    // Evenly rated, so the winner takes half of K from the loser
+    assert_eq!(report.standings[0].entrant, "random");
+    assert_eq!(report.standings[0].elo, STARTING_ELO + ELO_K / 2.);
+    assert_eq!(report.standings[1].elo, STARTING_ELO - ELO_K / 2.);
+
+    // Winning again is expected now, so it is worth less
+    let report = self::report(&[win, win]);
+    let second_gain = report.standings[0].elo - (STARTING_ELO + ELO_K / 2.);
+    assert!(second_gain > 0. && second_gain < ELO_K / 2.);
+
+    let draw = GameResult { seats: [0, 1], scores: [3, 3], outcome: Outcome::Equal };
+    let report = self::report(&[draw]);
+    assert!(report.standings.iter().all(|standing| standing.elo == STARTING_ELO && standing.draws == 1));
+  }
+
+  #[test]
+  fn reports_standings_as_csv_and_json() {
+    let results = [
+      GameResult { seats: [0, 1], scores: [4, 1], outcome: Outcome::Greater },
+      GameResult { seats: [1, 0], scores: [2, 2], outcome: Outcome::Equal },
+    ];
+    let report = report(&results);
+
+    let csv = report.to_csv();
+    let lines: Vec<_> = csv.lines().collect();
+    assert_eq!(lines, [
+      "entrant,games,wins,draws,losses,win_rate,average_score,elo",
+      "easy,2,1,1,0,0.500,3.00,1508",
+      "random,2,0,1,1,0.000,1.50,1492",
+    ]);
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["level"], DEFAULT_LEVEL);
+    assert_eq!(json["standings"][0]["wins"], 1);
+    assert!(json["standings"][0].get("total_score").is_none());
+    assert_eq!(json["matchups"][0]["first_wins"], 1);
+    assert_eq!(json["matchups"][0]["draws"], 1);
+
+    let out = std::env::temp_dir().join(format!("protodominoes-tournament-{}", std::process::id())).join("report");
+    report.write(&out);
+    assert_eq!(std::fs::read_to_string(out.with_extension("csv")).unwrap(), csv);
+    let written: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(out.with_extension("json")).unwrap()).unwrap();
+    assert_eq!(written, json);
+    let _ = std::fs::remove_dir_all(out.parent().unwrap());
+  }
+}
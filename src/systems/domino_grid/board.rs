@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use crate::prelude::*;
+
+use super::components::*;
+
+pub const DEFAULT_GRID_WIDTH: i32 = 10;
+pub const DEFAULT_GRID_HEIGHT: i32 = 10;
+
+/** One half of a placed domino, as seen from the cell it sits in */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoardCell {
+  pub color: DominoColor,
+  /** The area of the domino this half belongs to */
+  pub placement: PlacementArea,
+}
+
+/**
+ The logical contents of the grid, kept separately from the sprites.
+ Every placed domino writes both of its halves here so that rules can
+ reason about colors and neighbours without querying entities.
+ */
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct Board {
+  width: i32,
+  height: i32,
+  cells: Vec<Option<BoardCell>>,
+}
+
+impl Default for Board {
+  fn default() -> Self {
+    Self::new(DEFAULT_GRID_WIDTH, DEFAULT_GRID_HEIGHT)
+  }
+}
+
+impl Board {
+  pub fn new(width: i32, height: i32) -> Self {
+    Self {
+      width,
+      height,
+      cells: vec![None; (width * height) as usize],
+    }
+  }
+
+  pub fn width(&self) -> i32 {
+    self.width
+  }
+
+  pub fn height(&self) -> i32 {
+    self.height
+  }
+
+  pub fn in_bounds(&self, cell: IVec2) -> bool {
+    cell.x >= 0 && cell.y >= 0 && cell.x < self.width && cell.y < self.height
+  }
+
+  fn index(&self, cell: IVec2) -> Option<usize> {
+    self.in_bounds(cell).then(|| (cell.y * self.width + cell.x) as usize)
+  }
+
+  pub fn get(&self, cell: IVec2) -> Option<&BoardCell> {
+    self.index(cell).and_then(|index| self.cells[index].as_ref())
+  }
+
+  pub fn is_free(&self, cell: IVec2) -> bool {
+    self.index(cell).is_some_and(|index| self.cells[index].is_none())
+  }
+
+  /** Every covered cell, along with what covers it */
+  pub fn cells(&self) -> impl Iterator<Item = (IVec2, &BoardCell)> + '_ {
+    self.cells.iter().enumerate().filter_map(|(index, cell)| {
+      let index = index as i32;
+      cell.as_ref().map(|cell| (IVec2::new(index % self.width, index / self.width), cell))
+    })
+  }
+
+  /** Every cell nothing covers yet */
+  pub fn free_cells(&self) -> impl Iterator<Item = IVec2> + '_ {
+    (0..self.width * self.height)
+      .map(|index| IVec2::new(index % self.width, index / self.width))
+      .filter(|cell| self.is_free(*cell))
+  }
+
+  /**
+    Numbers each group of edge-touching cells of the same color, counting
+    from the bottom left cell, so every covered cell maps to its group.
+   */
+  pub fn color_regions(&self) -> HashMap<IVec2, usize> {
+    let _span = info_span!("color_regions", width = self.width, height = self.height).entered();
+    let mut regions = HashMap::new();
+    let mut count = 0;
+
+    for (start, start_cell) in self.cells() {
+      if regions.contains_key(&start) { continue; }
+
+      regions.insert(start, count);
+      let mut open = vec![start];
+      while let Some(cell) = open.pop() {
+        for neighbour in neighbours(cell) {
+          let same = self.get(neighbour).is_some_and(|other| other.color == start_cell.color);
+          if same && !regions.contains_key(&neighbour) {
+            regions.insert(neighbour, count);
+            open.push(neighbour);
+          }
+        }
+      }
+      count += 1;
+    }
+
+    regions
+  }
+
+  /** How many dominoes have been placed */
+  pub fn placed_count(&self) -> usize {
+    self.cells.iter().filter(|cell| cell.is_some()).count() / 2
+  }
+
+  /** Whether both cells of the area are inside the grid */
+  pub fn contains(&self, area: &PlacementArea) -> bool {
+    area.cells().iter().all(|cell| self.in_bounds(*cell))
+  }
+
+  /** Whether both cells of the area are inside the grid and unoccupied */
+  pub fn can_fit(&self, area: &PlacementArea) -> bool {
+    area.cells().iter().all(|cell| self.is_free(*cell))
+  }
+
+  /**
+    Writes both halves of the domino into the grid. The caller is expected
+    to have checked `can_fit` first.
+   */
+  pub fn place(&mut self, area: PlacementArea, d_type: DominoType) {
+    for (cell, color) in area.halves(d_type) {
+      if let Some(index) = self.index(cell) {
+        self.cells[index] = Some(BoardCell { color, placement: area });
+      }
+    }
+  }
+
+  /** Every area on the grid that a domino could cover, occupied or not */
+  pub fn areas(&self) -> impl Iterator<Item = PlacementArea> + '_ {
+    (0..self.width).flat_map(move |col| {
+      (0..self.height).flat_map(move |row| {
+        let horizontal = (col < self.width - 1)
+          .then_some(PlacementArea::Horizontal { left_cell_col: col, row });
+        let vertical = (row < self.height - 1)
+          .then_some(PlacementArea::Vertical { bottom_cell_row: row, column: col });
+
+        horizontal.into_iter().chain(vertical)
+      })
+    })
+  }
+
+  /** Every area on the grid that a domino can currently be dropped into */
+  pub fn free_areas(&self) -> impl Iterator<Item = PlacementArea> + '_ {
+    self.areas().filter(|area| self.can_fit(area))
+  }
+}
+
+pub fn are_adjacent(a: IVec2, b: IVec2) -> bool {
+  (a - b).abs().element_sum() == 1
+}
+
+/** The four cells sharing an edge with this one. Some may be off the grid */
+pub fn neighbours(cell: IVec2) -> [IVec2; 4] {
+  [cell + IVec2::X, cell - IVec2::X, cell + IVec2::Y, cell - IVec2::Y]
+}
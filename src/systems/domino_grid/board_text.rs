@@ -0,0 +1,216 @@
+use crate::prelude::*;
+
+use super::board::*;
+use super::components::*;
+
+const FREE: char = '.';
+const HORIZONTAL_JOIN: char = '-';
+const VERTICAL_JOIN: char = '|';
+
+impl Board {
+  /**
+    The board written out as text, top row first, for logs, bug reports and
+    the console. Each cell is its color's letter, or `.` when free. Halves of
+    the same domino are joined by `-` beside each other, or by `|` on the
+    line between rows, which is left empty when nothing crosses it:
+
+    ```text
+    b-r g y
+        | |
+    . . r b
+
+    y-y . .
+    ```
+
+    The board has no cells that can never be covered, so there is nothing
+    to write for those.
+   */
+  pub fn to_text(&self) -> String {
+    self.render(|color| color.letter().to_string())
+  }
+
+  /** As `to_text`, with each letter in its color for terminals */
+  pub fn to_ansi(&self) -> String {
+    self.render(|color| format!("\x1b[1;{}m{}\x1b[0m", ansi_code(color), color.letter()))
+  }
+
+  fn render(&self, letter: impl Fn(DominoColor) -> String) -> String {
+    let mut lines = vec![];
+
+    for y in (0..self.height()).rev() {
+      let mut cells = String::new();
+      for x in 0..self.width() {
+        let cell = IVec2::new(x, y);
+        match self.get(cell) {
+          Some(board_cell) => cells.push_str(&letter(board_cell.color)),
+          None => cells.push(FREE),
+        }
+        if x < self.width() - 1 {
+          cells.push(if self.joined(cell, cell + IVec2::X) { HORIZONTAL_JOIN } else { ' ' });
+        }
+      }
+      lines.push(cells);
+
+      if y > 0 {
+        let joins: String = (0..self.width())
+          .map(|x| if self.joined(IVec2::new(x, y - 1), IVec2::new(x, y)) { VERTICAL_JOIN } else { ' ' })
+          .flat_map(|join| [join, ' '])
+          .collect();
+        lines.push(joins.trim_end().to_string());
+      }
+    }
+
+    lines.join("\n")
+  }
+
+  /** Whether both cells hold halves of the same domino */
+  fn joined(&self, a: IVec2, b: IVec2) -> bool {
+    self.get(a).zip(self.get(b)).is_some_and(|(a, b)| a.placement == b.placement)
+  }
+
+  /**
+    Reads a board written by `to_text` or `to_ansi`, so a printed board can
+    be pasted straight back in. Blank lines around it are left out.
+   */
+  pub fn from_text(text: &str) -> Result<Self, String> {
+    let text = strip_ansi(text);
+    let lines: Vec<Vec<char>> = text.trim_matches('\n').lines().map(|line| line.trim_end().chars().collect()).collect();
+    if lines.is_empty() || lines.len().is_multiple_of(2) {
+      return Err("expected rows of cells with a line of joins between each".to_string());
+    }
+
+    let width = lines[0].len().div_ceil(2) as i32;
+    let height = lines.len().div_ceil(2) as i32;
+    let at = |line: usize, column: usize| lines[line].get(column).copied().unwrap_or(' ');
+    let mut board = Board::new(width, height);
+
+    for (row, line) in lines.iter().enumerate().step_by(2) {
+      if line.len() != lines[0].len() {
+        return Err(format!("line {} isn't as wide as the first", row + 1));
+      }
+      let y = height - 1 - (row / 2) as i32;
+
+      for x in 0..width {
+        let column = 2 * x as usize;
+        let cell = at(row, column);
+        if cell == FREE { continue; }
+        let Some(color) = DominoColor::from_letter(cell) else {
+          return Err(format!("{cell} on line {} isn't a color or {FREE}", row + 1));
+        };
+        let joined_right = at(row, column + 1) == HORIZONTAL_JOIN;
+        let joined_below = row + 1 < lines.len() && at(row + 1, column) == VERTICAL_JOIN;
+
+        // Each domino is written from its left or bottom half, which comes last for vertical ones
+        let area = match (joined_right, joined_below) {
+          (true, false) => PlacementArea::Horizontal { left_cell_col: x, row: y },
+          (false, true) => PlacementArea::Vertical { bottom_cell_row: y - 1, column: x },
+          (false, false) => {
+            let covered = board.get(IVec2::new(x, y)).is_some();
+            if covered { continue; }
+            return Err(format!("the {color:?} half at {x} {y} isn't joined to another"));
+          },
+          (true, true) => return Err(format!("the {color:?} half at {x} {y} is joined twice")),
+        };
+
+        let [first, second] = area.cells();
+        let partner = if first == IVec2::new(x, y) { second } else { first };
+        let Some(partner_color) = lines
+          .get(row + if joined_below { 2 } else { 0 })
+          .and_then(|line| line.get(2 * partner.x as usize))
+          .and_then(|letter| DominoColor::from_letter(*letter))
+        else {
+          return Err(format!("the {color:?} half at {x} {y} is joined to nothing"));
+        };
+        if !board.can_fit(&area) {
+          return Err(format!("the domino at {x} {y} overlaps another"));
+        }
+
+        let d_type = if joined_below { DominoType::new(partner_color, color) } else { DominoType::new(color, partner_color) };
+        board.place(area, d_type);
+      }
+    }
+
+    Ok(board)
+  }
+}
+
+fn ansi_code(color: DominoColor) -> u8 {
+  match color {
+    DominoColor::Blue => 34,
+    DominoColor::Red => 31,
+    DominoColor::Green => 32,
+    DominoColor::Yellow => 33,
+  }
+}
+
+/** Drops escape sequences like `\x1b[1;34m` */
+fn strip_ansi(text: &str) -> String {
+  let mut stripped = String::with_capacity(text.len());
+  let mut chars = text.chars();
+  while let Some(char) = chars.next() {
+    if char == '\x1b' {
+      chars.by_ref().find(|char| char.is_ascii_alphabetic());
+    } else {
+      stripped.push(char);
+    }
+  }
+  stripped
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use DominoColor::*;
+
+  /** The example from `to_text`'s doc */
+  const SNAPSHOT: &str = "\
+b-r g y
+    | |
+. . r b
+
+y-y . .";
+
+  fn snapshot_board() -> Board {
+    let mut board = Board::new(4, 3);
+    board.place(PlacementArea::Horizontal { left_cell_col: 0, row: 2 }, DominoType::new(Blue, Red));
+    board.place(PlacementArea::Vertical { bottom_cell_row: 1, column: 2 }, DominoType::new(Red, Green));
+    board.place(PlacementArea::Vertical { bottom_cell_row: 1, column: 3 }, DominoType::new(Blue, Yellow));
+    board.place(PlacementArea::Horizontal { left_cell_col: 0, row: 0 }, DominoType::new(Yellow, Yellow));
+    board
+  }
+
+  #[test]
+  fn writes_the_snapshot() {
+    assert_eq!(snapshot_board().to_text(), SNAPSHOT);
+    assert_eq!(Board::from_text(SNAPSHOT), Ok(snapshot_board()));
+  }
+
+  #[test]
+  fn reads_back_what_it_writes() {
+    let mut board = Board::new(5, 4);
+    for (index, area) in [
+      PlacementArea::Horizontal { left_cell_col: 0, row: 0 },
+      PlacementArea::Vertical { bottom_cell_row: 1, column: 0 },
+      PlacementArea::Horizontal { left_cell_col: 1, row: 1 },
+      PlacementArea::Vertical { bottom_cell_row: 2, column: 4 },
+      PlacementArea::Horizontal { left_cell_col: 2, row: 3 },
+    ].into_iter().enumerate() {
+      board.place(area, DominoType::ALL_TYPES[index * 3]);
+    }
+
+    assert_eq!(Board::from_text(&board.to_text()), Ok(board.clone()));
+    assert_eq!(Board::from_text(&board.to_ansi()), Ok(board.clone()));
+    assert_eq!(Board::from_text(&Board::new(3, 2).to_text()), Ok(Board::new(3, 2)));
+  }
+
+  #[test]
+  fn refuses_boards_it_cannot_read() {
+    assert!(Board::from_text("").is_err());
+    assert!(Board::from_text("b . .\n\n. .").is_err());
+    assert!(Board::from_text("b . .").is_err());
+    assert!(Board::from_text("x-r").is_err());
+    assert!(Board::from_text("b-r-g").is_err());
+    assert!(Board::from_text("b-r\n|\n. .").is_err());
+  }
+}
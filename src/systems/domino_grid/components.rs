@@ -1,14 +1,30 @@
-use bevy::math::bounding::Aabb2d;
+use serde::{Deserialize, Serialize};
 
 use crate::prelude::*;
 
-#[derive(Component)]
+#[derive(Component, Reflect)]
+#[reflect(Component)]
 pub struct Domino {
   pub d_type: DominoType,
   pub horizontal: bool,
 }
 
-#[derive(Component, Clone, Copy)]
+impl Domino {
+  /**
+    How the sprite should be turned. Vertical dominoes are a quarter turn
+    anticlockwise, which puts the first color at the bottom.
+   */
+  pub fn rotation(&self) -> Quat {
+    if self.horizontal {
+      Quat::IDENTITY
+    } else {
+      Quat::from_rotation_z(core::f32::consts::FRAC_PI_2)
+    }
+  }
+}
+
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect)]
+#[reflect(Component)]
 pub enum DominoType {
   Blue(DominoColor),
   Red(DominoColor),
@@ -38,9 +54,58 @@ impl DominoType {
     DominoType::Yellow(DominoColor::Green),
     DominoType::Yellow(DominoColor::Red),
   ];
+
+  pub fn new(first: DominoColor, second: DominoColor) -> Self {
+    match first {
+      DominoColor::Blue => DominoType::Blue(second),
+      DominoColor::Red => DominoType::Red(second),
+      DominoColor::Green => DominoType::Green(second),
+      DominoColor::Yellow => DominoType::Yellow(second),
+    }
+  }
+
+  /**
+    The colors of the two halves. The first is the left half of a horizontal
+    domino (or the bottom half of a vertical one), the second is the other end.
+   */
+  pub fn colors(&self) -> (DominoColor, DominoColor) {
+    match *self {
+      DominoType::Blue(second) => (DominoColor::Blue, second),
+      DominoType::Red(second) => (DominoColor::Red, second),
+      DominoType::Green(second) => (DominoColor::Green, second),
+      DominoType::Yellow(second) => (DominoColor::Yellow, second),
+    }
+  }
+
+  /** The same physical domino, read from the other end */
+  pub fn flipped(&self) -> Self {
+    let (first, second) = self.colors();
+    Self::new(second, first)
+  }
+
+  pub fn pips(&self) -> u32 {
+    let (first, second) = self.colors();
+    first.pips() + second.pips()
+  }
+
+  /**
+    Every physically distinct domino, ignoring which way round it is read.
+    With four colors this is the equivalent of a double-three set.
+   */
+  pub fn classic_set() -> Vec<Self> {
+    Self::ALL_TYPES
+      .iter()
+      .filter(|domino| {
+        let (first, second) = domino.colors();
+        first.pips() <= second.pips()
+      })
+      .copied()
+      .collect()
+  }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum DominoColor {
   Blue,
   Red,
@@ -48,10 +113,57 @@ pub enum DominoColor {
   Yellow,
 }
 
-#[derive(Component)]
+impl DominoColor {
+  pub const ALL: [Self; 4] = [DominoColor::Blue, DominoColor::Red, DominoColor::Green, DominoColor::Yellow];
+
+  /** The color's place in `ALL` */
+  pub fn index(&self) -> usize {
+    *self as usize
+  }
+
+  /** The color's initial, for writing dominoes as text like `br` */
+  pub fn letter(&self) -> char {
+    match self {
+      DominoColor::Blue => 'b',
+      DominoColor::Red => 'r',
+      DominoColor::Green => 'g',
+      DominoColor::Yellow => 'y',
+    }
+  }
+
+  /** The color with this initial, in either case */
+  pub fn from_letter(letter: char) -> Option<Self> {
+    Self::ALL.into_iter().find(|color| color.letter() == letter.to_ascii_lowercase())
+  }
+
+  /**
+    Colors stand in for pips when playing classic dominoes, so each one
+    is given the value of a suit from a double-three set.
+   */
+  pub fn pips(&self) -> u32 {
+    match self {
+      DominoColor::Blue => 0,
+      DominoColor::Red => 1,
+      DominoColor::Green => 2,
+      DominoColor::Yellow => 3,
+    }
+  }
+}
+
+/** Marks a domino that has been dropped onto the grid, and where */
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Placed {
+  pub placement: PlacementArea,
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
 pub struct GridSquare;
 
-#[derive(Component)]
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
+#[reflect(Component)]
+#[serde(rename_all = "snake_case")]
 pub enum PlacementArea {
   Horizontal {
     left_cell_col: i32,
@@ -64,45 +176,53 @@ pub enum PlacementArea {
 }
 
 impl PlacementArea {
-  pub fn is_this_point_within(&self, point: Vec2) -> bool {
+  pub fn is_horizontal(&self) -> bool {
+    matches!(self, PlacementArea::Horizontal { .. })
+  }
+
+  /**
+    The two grid cells covered by this area. The first is the left cell of a
+    horizontal area or the bottom cell of a vertical one, matching the order
+    of `DominoType::colors`.
+   */
+  pub fn cells(&self) -> [IVec2; 2] {
     match *self {
-      PlacementArea::Horizontal { left_cell_col: left_cell, row } => {
-        let center = Vec2::new((16 + (32 * left_cell)) as f32, 32. * row as f32);
-        let aabb = Aabb2d::new(
-          center,
-          Vec2::splat(16.)
-        );
-        
-        let x_within_bb = point.x < aabb.max.x && point.x > aabb.min.x;
-        let y_within_bb = point.y < aabb.max.y && point.y > aabb.min.y;
-        let point_within_bb = x_within_bb && y_within_bb;
-
-        point_within_bb
-      }
-      PlacementArea::Vertical { bottom_cell_row: bottom_cell, column } => {
-        let center = Vec2::new((32 * column) as f32, 16. + (32. * bottom_cell as f32));
-        let aabb = Aabb2d::new(
-          center,
-          Vec2::splat(16.)
-        );
-        
-        let x_within_bb = point.x < aabb.max.x && point.x > aabb.min.x;
-        let y_within_bb = point.y < aabb.max.y && point.y > aabb.min.y;
-        let point_within_bb = x_within_bb && y_within_bb;
-
-        point_within_bb
-      }
+      PlacementArea::Horizontal { left_cell_col, row } => [
+        IVec2::new(left_cell_col, row),
+        IVec2::new(left_cell_col + 1, row),
+      ],
+      PlacementArea::Vertical { bottom_cell_row, column } => [
+        IVec2::new(column, bottom_cell_row),
+        IVec2::new(column, bottom_cell_row + 1),
+      ],
     }
   }
 
-  pub fn transform(&self) -> Transform {
-    match *self {
-      PlacementArea::Horizontal { left_cell_col: left_cell, row } => {
-        Transform::from_translation(Vec2::new((16 + (32 * left_cell)) as f32, 32. * row as f32).extend(1.))
-      },
-      PlacementArea::Vertical { bottom_cell_row: bottom_cell, column } => {
-        Transform::from_translation(Vec2::new((32 * column) as f32, 16. + (32. * bottom_cell as f32)).extend(1.))
-      }
+  /** Which color lands in which cell when the domino is put in this area */
+  pub fn halves(&self, d_type: DominoType) -> [(IVec2, DominoColor); 2] {
+    let (first, second) = d_type.colors();
+    let [first_cell, second_cell] = self.cells();
+    [(first_cell, first), (second_cell, second)]
+  }
+
+  /**
+    The area of the given orientation whose hitbox contains the point. This
+    is plain grid math, so the area may be off the board.
+   */
+  pub fn at_point(point: Vec2, horizontal: bool) -> Self {
+    let origin = grid::area_origin_at(point, horizontal);
+    if horizontal {
+      PlacementArea::Horizontal { left_cell_col: origin.x, row: origin.y }
+    } else {
+      PlacementArea::Vertical { bottom_cell_row: origin.y, column: origin.x }
     }
   }
-}
\ No newline at end of file
+
+  pub fn center(&self) -> Vec2 {
+    grid::area_center(self.cells()[0], self.is_horizontal())
+  }
+
+  pub fn transform(&self) -> Transform {
+    Transform::from_translation(self.center().extend(1.))
+  }
+}
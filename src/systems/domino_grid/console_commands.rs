@@ -0,0 +1,259 @@
+use crate::prelude::*;
+use crate::systems::console::AddConsoleCommand;
+use crate::systems::random::GameRng;
+
+use super::board::*;
+use super::components::*;
+use super::draw_pile::InHoldSlot;
+use super::events::*;
+use super::level::Levels;
+use super::rules::*;
+use super::tray::TraySlot;
+use super::turns::*;
+
+use dragging::Draggable;
+use dragging::IsDragging;
+
+/** Console commands for poking at the board and the level */
+pub struct DominoCommandsPlugin;
+
+impl Plugin for DominoCommandsPlugin {
+  fn build(&self, app: &mut App) {
+    app
+      .add_console_command("spawn", "<colors> <x> <y> [h|v]", "Puts a domino like `br` straight onto the board, ignoring the rules", spawn_command)
+      .add_console_command("place", "<slot> <x> <y> [h|v] [flip]", "Drops the domino in that tray slot onto the board, as if dragged there", place_command)
+      .add_console_command("board", "[file]", "Prints the board as text, or lays out the board written that way in a file", board_command)
+      .add_console_command("clear", "", "Takes every domino off the board", clear_command)
+      .add_console_command("seed", "[seed]", "Shows the seed, or restarts the level with another", seed_command)
+      .add_console_command("load", "<name or number>", "Starts another level", load_command)
+      .add_console_command("rules", "[name ..]", "Lists the rule sets, or restarts the level with these", rules_command)
+      .add_console_command("grid", "<width> <height>", "Restarts the level on a grid of another size", grid_command)
+      ;
+  }
+}
+
+/** Reads a domino written as its two colors' initials, like `br` */
+pub fn parse_domino(text: &str) -> Result<DominoType, String> {
+  let colors: Option<Vec<_>> = text.chars().map(DominoColor::from_letter).collect();
+  match colors.as_deref() {
+    Some([first, second]) => Ok(DominoType::new(*first, *second)),
+    _ => Err(format!("{text} isn't a domino, write it as two of b, r, g and y")),
+  }
+}
+
+/** The area with its first cell at `x`, `y`, reading `h` or `v` (flat if left out) for its orientation */
+pub fn parse_area(x: &str, y: &str, orientation: Option<&str>) -> Result<PlacementArea, String> {
+  let (Ok(x), Ok(y)) = (x.parse(), y.parse()) else {
+    return Err(format!("{x} {y} isn't a cell"));
+  };
+  match orientation {
+    None | Some("h") => Ok(PlacementArea::Horizontal { left_cell_col: x, row: y }),
+    Some("v") => Ok(PlacementArea::Vertical { bottom_cell_row: y, column: x }),
+    Some(other) => Err(format!("{other} isn't an orientation, use h or v")),
+  }
+}
+
+/**
+  Lets go of a domino from the hand over `area`, reading as `d_type`, so it
+  goes through the rules like any other drop
+ */
+pub fn drop_from_hand(world: &mut World, domino: Entity, area: PlacementArea, d_type: DominoType) {
+  let Ok(mut entity) = world.get_entity_mut(domino) else { return; };
+  if let Some(mut held) = entity.get_mut::<Domino>() {
+    held.d_type = d_type;
+    held.horizontal = area.is_horizontal();
+  }
+  let rotation = entity.get::<Domino>().map(Domino::rotation);
+  if let (Some(mut transform), Some(rotation)) = (entity.get_mut::<Transform>(), rotation) {
+    transform.rotation = rotation;
+    transform.translation = area.center().extend(transform.translation.z);
+  }
+  entity.insert(IsDragging);
+  world.send_event(DragEndEvent { draggable: domino });
+}
+
+fn restart(world: &mut World) {
+  world.send_event(RestartLevel);
+}
+
+fn spawn_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+  let [colors, x, y, rest @ ..] = args else {
+    return Err("needs the colors and a cell".to_string());
+  };
+  let d_type = parse_domino(colors)?;
+  let area = parse_area(x, y, rest.first().copied())?;
+
+  if !world.resource::<Board>().can_fit(&area) {
+    return Err("that's off the grid or already covered".to_string());
+  }
+  spawn_placed(world, area, d_type);
+
+  Ok(format!("Spawned {d_type:?} at {area:?}"))
+}
+
+/** Puts a domino straight onto the board, past the rules, as if it had been placed */
+fn spawn_placed(world: &mut World, area: PlacementArea, d_type: DominoType) {
+  world.resource_mut::<Board>().place(area, d_type);
+  world.resource_scope(|world, mut rules: Mut<ActiveRuleSet>| {
+    rules.0.on_placed(world.resource::<Board>(), &area, d_type);
+  });
+
+  let domino = Domino { d_type, horizontal: area.is_horizontal() };
+  let mut transform = area.transform();
+  transform.rotation = domino.rotation();
+  let entity = world.spawn((domino, Placed { placement: area }, transform)).id();
+  world.send_event(DominoPlacementEvent::new(entity, d_type, area));
+}
+
+fn place_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+  let [slot, x, y, rest @ ..] = args else {
+    return Err("needs a tray slot and a cell".to_string());
+  };
+  let slot: usize = slot.parse().map_err(|_| format!("{slot} isn't a tray slot"))?;
+  let orientation = rest.first().copied().filter(|word| *word != "flip");
+  let flip = rest.contains(&"flip");
+  let area = parse_area(x, y, orientation)?;
+
+  let mut hand = world.query_filtered::<(Entity, &TraySlot, &Domino), (With<Draggable>, Without<InHoldSlot>)>();
+  let (entity, held) = hand
+    .iter(world)
+    .find(|(_, tray_slot, _)| tray_slot.0 == slot)
+    .map(|(entity, _, domino)| (entity, domino.d_type))
+    .ok_or_else(|| format!("there's no domino in slot {slot}"))?;
+  let d_type = if flip { held.flipped() } else { held };
+
+  drop_from_hand(world, entity, area, d_type);
+  Ok(format!("Dropped {d_type:?} at {area:?}"))
+}
+
+fn board_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+  let path = match args {
+    [] => return Ok(world.resource::<Board>().to_text()),
+    [path] => path,
+    _ => return Err("takes one file".to_string()),
+  };
+  let text = std::fs::read_to_string(path).map_err(|error| format!("couldn't read {path}: {error}"))?;
+  let written = Board::from_text(&text)?;
+  let board = world.resource::<Board>();
+  if (written.width(), written.height()) != (board.width(), board.height()) {
+    return Err(format!("the board is {}x{}, that's {}x{}", board.width(), board.height(), written.width(), written.height()));
+  }
+
+  clear_command(world, &[])?;
+  let dominoes: Vec<_> = written
+    .cells()
+    .filter(|(cell, board_cell)| board_cell.placement.cells()[0] == *cell)
+    .filter_map(|(_, board_cell)| {
+      let [first, second] = board_cell.placement.cells().map(|cell| written.get(cell).map(|half| half.color));
+      Some((board_cell.placement, DominoType::new(first?, second?)))
+    })
+    .collect();
+  for (area, d_type) in &dominoes {
+    spawn_placed(world, *area, *d_type);
+  }
+  Ok(format!("Laid out {} dominoes", dominoes.len()))
+}
+
+fn clear_command(world: &mut World, _args: &[&str]) -> Result<String, String> {
+  let mut placed = world.query::<(Entity, &Domino, &Placed)>();
+  let removed: Vec<_> = placed
+    .iter(world)
+    .map(|(entity, domino, placed)| DominoRemoved::new(entity, domino.d_type, placed.placement))
+    .collect();
+  for event in &removed {
+    world.despawn(event.domino);
+  }
+  world.send_event_batch(removed.iter().copied());
+
+  let (width, height) = {
+    let board = world.resource::<Board>();
+    (board.width(), board.height())
+  };
+  world.insert_resource(Board::new(width, height));
+
+  // Rule sets keep their own state about the board, so they start over too
+  let levels = world.resource::<Levels>();
+  let level = levels.current();
+  let rng = level.seed.map_or(*world.resource::<GameRng>(), GameRng::new);
+  let seats = level.seats();
+  let mut rules = world.resource::<RuleSetRegistry>().build(&level.rules, &mut rng.fork("rules"))?;
+  rules.deal_hands(seats);
+  rules.begin_turn(world.resource::<Turns>().current);
+  let total = rules.score(&Board::new(width, height));
+  world.insert_resource(ActiveRuleSet(rules));
+  world.resource_mut::<Turns>().restart_scoring(total);
+
+  Ok(format!("Took {} dominoes off the board", removed.len()))
+}
+
+fn seed_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+  let level_seed = world.resource::<Levels>().current().seed;
+  match args {
+    [] => Ok(format!("Seed {}", level_seed.unwrap_or(world.resource::<GameRng>().seed()))),
+    [seed] => {
+      let seed: u64 = seed.parse().map_err(|_| format!("{seed} isn't a whole number"))?;
+      world.insert_resource(GameRng::new(seed));
+      // A level with its own seed would otherwise ignore the new one
+      let mut levels = world.resource_mut::<Levels>();
+      let current = levels.current;
+      levels.all[current].seed = None;
+      restart(world);
+      Ok(format!("Restarting with seed {seed}"))
+    },
+    _ => Err("takes one seed".to_string()),
+  }
+}
+
+fn load_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+  if args.is_empty() {
+    let levels = world.resource::<Levels>();
+    return Ok(levels.all
+      .iter()
+      .enumerate()
+      .map(|(index, level)| format!("{} {}", index + 1, level.name))
+      .collect::<Vec<_>>()
+      .join("\n"));
+  }
+
+  let name = args.join(" ");
+  let mut levels = world.resource_mut::<Levels>();
+  let index = levels.index_of(&name).ok_or_else(|| format!("no level called {name}"))?;
+  levels.current = index;
+  let loaded = format!("Loading {}", levels.current().name);
+  restart(world);
+  Ok(loaded)
+}
+
+fn rules_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+  let names = world.resource::<RuleSetRegistry>().names();
+  if args.is_empty() {
+    let current = world.resource::<ActiveRuleSet>().0.name();
+    return Ok(format!("Playing {current}. Rule sets: {}", names.join(", ")));
+  }
+
+  if let Some(unknown) = args.iter().find(|name| !names.contains(name)) {
+    return Err(format!("no rule set called {unknown}"));
+  }
+  let mut levels = world.resource_mut::<Levels>();
+  let current = levels.current;
+  levels.all[current].rules = args.iter().map(|name| name.to_string()).collect();
+  restart(world);
+  Ok(format!("Restarting with {}", args.join(" and ")))
+}
+
+fn grid_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+  let [width, height] = args else {
+    return Err("needs a width and a height".to_string());
+  };
+  let size = |text: &str| text.parse::<i32>().ok().filter(|size| (1..=256).contains(size));
+  let (Some(width), Some(height)) = (size(width), size(height)) else {
+    return Err("sizes go from 1 to 256".to_string());
+  };
+
+  let mut levels = world.resource_mut::<Levels>();
+  let current = levels.current;
+  levels.all[current].grid_width = width;
+  levels.all[current].grid_height = height;
+  restart(world);
+  Ok(format!("Restarting on a {width}x{height} grid"))
+}
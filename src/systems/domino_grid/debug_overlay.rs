@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+
+use bevy::color::palettes::css::{GRAY, ORANGE, WHITE};
+use bevy::diagnostic::{
+  DiagnosticPath,
+  DiagnosticsStore,
+  EntityCountDiagnosticsPlugin,
+  FrameTimeDiagnosticsPlugin,
+  SystemInfo,
+  SystemInformationDiagnosticsPlugin,
+};
+use leafwing_input_manager::common_conditions::action_just_pressed;
+
+use crate::prelude::*;
+use crate::systems::console::AddConsoleCommand;
+use crate::systems::DominoControlAction;
+
+use super::board::*;
+use super::components::*;
+use super::events::*;
+use super::tiling::TilingAnalysis;
+use super::turns::*;
+
+use grid::TILE_SIZE;
+
+const LABEL_Z: f32 = 50.;
+/** One color per seat, for telling whose dominoes are whose */
+const OWNER_COLORS: [Color; 4] = [
+  Color::srgb(0.3, 0.6, 1.),
+  Color::srgb(1., 0.4, 0.4),
+  Color::srgb(0.4, 0.9, 0.4),
+  Color::srgb(1., 0.85, 0.3),
+];
+
+/**
+ Overlays for working out what is going on with the board, shown and hidden
+ with F2. Each part is toggled from the console with `overlay <part>`:
+ - occupancy: covered cells, tinted by who placed them
+ - regions: the number of each group of touching same-colored cells
+ - tiling: how many more dominoes fit, crossing out cells nothing can cover
+ - diagnostics: frame time, entities and memory
+
+ The last rejected drop is outlined, with its reason, whenever it is shown.
+ */
+pub struct DebugOverlayPlugin;
+
+impl Plugin for DebugOverlayPlugin {
+  fn build(&self, app: &mut App) {
+    if !app.is_plugin_added::<FrameTimeDiagnosticsPlugin>() {
+      app.add_plugins(FrameTimeDiagnosticsPlugin);
+    }
+    if !app.is_plugin_added::<EntityCountDiagnosticsPlugin>() {
+      app.add_plugins(EntityCountDiagnosticsPlugin);
+    }
+    if !app.is_plugin_added::<SystemInformationDiagnosticsPlugin>() {
+      app.add_plugins(SystemInformationDiagnosticsPlugin);
+    }
+
+    app
+      .init_resource::<DebugOverlay>()
+      .add_console_command("overlay", "[occupancy|regions|tiling|diagnostics]", "Shows which overlays are on, or toggles one", overlay_command)
+      .add_systems(Startup, spawn_overlay_text)
+      .add_systems(Update, (
+        toggle_overlay.run_if(action_just_pressed(DominoControlAction::ToggleOverlay)),
+        remember_rejection,
+        forget_rejection.run_if(on_event::<LevelStarted>),
+        analyze_board,
+        draw_overlay,
+        label_regions,
+        update_overlay_text,
+      ).chain())
+      ;
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OverlayPart {
+  Occupancy,
+  Regions,
+  Tiling,
+  Diagnostics,
+}
+
+impl OverlayPart {
+  pub const ALL: [Self; 4] = [Self::Occupancy, Self::Regions, Self::Tiling, Self::Diagnostics];
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      Self::Occupancy => "occupancy",
+      Self::Regions => "regions",
+      Self::Tiling => "tiling",
+      Self::Diagnostics => "diagnostics",
+    }
+  }
+}
+
+#[derive(Resource)]
+pub struct DebugOverlay {
+  pub shown: bool,
+  pub parts: Vec<OverlayPart>,
+  pub last_rejection: Option<PlacementRejected>,
+  regions: HashMap<IVec2, usize>,
+  tiling: TilingAnalysis,
+}
+
+impl Default for DebugOverlay {
+  fn default() -> Self {
+    Self {
+      shown: false,
+      parts: OverlayPart::ALL.to_vec(),
+      last_rejection: None,
+      regions: HashMap::new(),
+      tiling: TilingAnalysis::default(),
+    }
+  }
+}
+
+impl DebugOverlay {
+  pub fn showing(&self, part: OverlayPart) -> bool {
+    self.shown && self.parts.contains(&part)
+  }
+}
+
+/** The number of a cell's color region, drawn over it */
+#[derive(Component)]
+pub struct RegionLabel;
+
+#[derive(Component)]
+pub struct DebugOverlayText;
+
+fn toggle_overlay(
+  mut overlay: ResMut<DebugOverlay>,
+) {
+  overlay.shown = !overlay.shown;
+}
+
+fn remember_rejection(
+  mut overlay: ResMut<DebugOverlay>,
+  mut rejected_evr: EventReader<PlacementRejected>,
+) {
+  if let Some(event) = rejected_evr.read().last() {
+    overlay.last_rejection = Some(*event);
+  }
+}
+
+fn forget_rejection(
+  mut overlay: ResMut<DebugOverlay>,
+) {
+  overlay.last_rejection = None;
+}
+
+fn analyze_board(
+  mut overlay: ResMut<DebugOverlay>,
+  board: Res<Board>,
+) {
+  if !overlay.shown || (!board.is_changed() && !overlay.is_changed()) { return; }
+
+  overlay.regions = if overlay.parts.contains(&OverlayPart::Regions) { board.color_regions() } else { HashMap::new() };
+  if overlay.parts.contains(&OverlayPart::Tiling) {
+    overlay.tiling = TilingAnalysis::of(&board);
+  }
+}
+
+fn cell_center(cell: IVec2) -> Vec2 {
+  cell.as_vec2() * TILE_SIZE
+}
+
+fn draw_overlay(
+  mut gizmos: Gizmos,
+  overlay: Res<DebugOverlay>,
+  board: Res<Board>,
+  placed: Query<(&Placed, Option<&Owner>)>,
+) {
+  if overlay.showing(OverlayPart::Occupancy) {
+    let owners: HashMap<_, _> = placed.iter().map(|(placed, owner)| (placed.placement, owner.map(|owner| owner.0))).collect();
+    for (cell, board_cell) in board.cells() {
+      let color = owners
+        .get(&board_cell.placement)
+        .copied()
+        .flatten()
+        .map_or(Color::from(WHITE), |owner| OWNER_COLORS[owner % OWNER_COLORS.len()]);
+      gizmos.rect_2d(Isometry2d::from_translation(cell_center(cell)), Vec2::splat(TILE_SIZE * 0.8), color);
+    }
+  }
+
+  if overlay.showing(OverlayPart::Tiling) {
+    let arm = TILE_SIZE * 0.3;
+    for cell in &overlay.tiling.isolated {
+      let center = cell_center(*cell);
+      gizmos.line_2d(center - Vec2::splat(arm), center + Vec2::splat(arm), GRAY);
+      gizmos.line_2d(center + Vec2::new(-arm, arm), center + Vec2::new(arm, -arm), GRAY);
+    }
+  }
+
+  if let Some(area) = overlay.last_rejection.and_then(|rejection| rejection.placement).filter(|_| overlay.shown) {
+    let size = if area.is_horizontal() { Vec2::new(2., 1.) } else { Vec2::new(1., 2.) };
+    gizmos.rect_2d(Isometry2d::from_translation(area.center()), size * TILE_SIZE, ORANGE);
+  }
+}
+
+fn label_regions(
+  mut commands: Commands,
+  overlay: Res<DebugOverlay>,
+  labels: Query<Entity, With<RegionLabel>>,
+) {
+  if !overlay.is_changed() { return; }
+
+  for label in labels.iter() {
+    commands.entity(label).despawn_recursive();
+  }
+  if !overlay.showing(OverlayPart::Regions) { return; }
+
+  for (cell, region) in &overlay.regions {
+    commands.spawn((
+      RegionLabel,
+      Text2d::new(region.to_string()),
+      TextFont::from_font_size(12.),
+      Transform::from_translation(cell_center(*cell).extend(LABEL_Z)),
+    ));
+  }
+}
+
+fn spawn_overlay_text(
+  mut commands: Commands,
+) {
+  commands.spawn((
+    Node {
+      position_type: PositionType::Absolute,
+      left: Val::Px(16.),
+      top: Val::Percent(30.),
+      ..default()
+    },
+    DebugOverlayText,
+    Text::default(),
+    TextFont::from_font_size(14.),
+  ));
+}
+
+fn update_overlay_text(
+  overlay: Res<DebugOverlay>,
+  board: Res<Board>,
+  turns: Res<Turns>,
+  diagnostics: Res<DiagnosticsStore>,
+  system_info: Option<Res<SystemInfo>>,
+  placed: Query<Option<&Owner>, With<Placed>>,
+  mut texts: Query<&mut Text, With<DebugOverlayText>>,
+) {
+  let mut lines = vec![];
+
+  if overlay.showing(OverlayPart::Occupancy) {
+    let total = board.width() * board.height();
+    lines.push(format!("Covered {} of {total} cells, {} dominoes", board.placed_count() * 2, board.placed_count()));
+    if turns.is_hotseat() {
+      for (seat, player) in turns.players.iter().enumerate() {
+        let count = placed.iter().filter(|owner| owner.is_some_and(|owner| owner.0 == seat)).count();
+        lines.push(format!("  {}: {count}", player.name));
+      }
+    }
+  }
+
+  if overlay.showing(OverlayPart::Regions) {
+    let mut sizes = vec![0; overlay.regions.values().max().map_or(0, |max| max + 1)];
+    for region in overlay.regions.values() {
+      sizes[*region] += 1;
+    }
+    lines.push(format!("{} color regions, the largest {} cells", sizes.len(), sizes.iter().max().unwrap_or(&0)));
+  }
+
+  if overlay.showing(OverlayPart::Tiling) {
+    let tiling = &overlay.tiling;
+    lines.push(format!("{} free cells, room for {} more dominoes", tiling.free_cells, tiling.max_dominoes));
+    if tiling.can_tile() {
+      lines.push("  The free cells can be tiled exactly".to_string());
+    } else {
+      lines.push(format!("  {} cells will be left over, {} of them cut off", tiling.left_over(), tiling.isolated.len()));
+    }
+  }
+
+  if overlay.showing(OverlayPart::Diagnostics) {
+    let value = |path: &DiagnosticPath| diagnostics.get(path).and_then(|diagnostic| diagnostic.smoothed());
+    lines.push(format!(
+      "{:.0} fps, {:.1} ms a frame, {} entities",
+      value(&FrameTimeDiagnosticsPlugin::FPS).unwrap_or_default(),
+      value(&FrameTimeDiagnosticsPlugin::FRAME_TIME).unwrap_or_default(),
+      value(&EntityCountDiagnosticsPlugin::ENTITY_COUNT).unwrap_or_default(),
+    ));
+    let memory = system_info.map_or_else(String::new, |info| format!(" of {}", info.memory));
+    lines.push(format!(
+      "Memory {:.0}% used{memory}, CPU {:.0}%",
+      value(&SystemInformationDiagnosticsPlugin::MEM_USAGE).unwrap_or_default(),
+      value(&SystemInformationDiagnosticsPlugin::CPU_USAGE).unwrap_or_default(),
+    ));
+  }
+
+  if let Some(rejection) = overlay.last_rejection.filter(|_| overlay.shown) {
+    let place = rejection.placement.map_or_else(|| "off the board".to_string(), |area| format!("at {area:?}"));
+    lines.push(format!("Last drop rejected {place}: {:?}", rejection.reason));
+  }
+
+  let shown = lines.join("\n");
+  for mut text in texts.iter_mut() {
+    if text.0 != shown {
+      text.0 = shown.clone();
+    }
+  }
+}
+
+fn overlay_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+  let mut overlay = world.resource_mut::<DebugOverlay>();
+  match args {
+    [] => {
+      let on: Vec<_> = overlay.parts.iter().map(OverlayPart::name).collect();
+      let shown = if overlay.shown { "shown" } else { "hidden (F2 shows it)" };
+      Ok(format!("Overlay {shown}, with {}", on.join(", ")))
+    },
+    [name] => {
+      let part = OverlayPart::ALL
+        .into_iter()
+        .find(|part| part.name() == *name)
+        .ok_or_else(|| format!("no overlay called {name}"))?;
+      let on = !overlay.parts.contains(&part);
+      if on {
+        overlay.parts.push(part);
+        overlay.shown = true;
+      } else {
+        overlay.parts.retain(|other| *other != part);
+      }
+      Ok(format!("Turned {name} {}", if on { "on" } else { "off" }))
+    },
+    _ => Err("toggles one overlay at a time".to_string()),
+  }
+}
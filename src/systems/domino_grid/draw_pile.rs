@@ -0,0 +1,302 @@
+use bevy::window::PrimaryWindow;
+use leafwing_input_manager::common_conditions::action_just_pressed;
+use rand::seq::SliceRandom;
+
+use crate::prelude::*;
+use crate::systems::camera::{screen_to_world, CameraControlSet, MainCamera};
+use crate::systems::random::StreamRng;
+use crate::systems::DominoControlAction;
+use crate::systems::LoadedAssets;
+
+use super::components::*;
+use super::events::*;
+use super::grid_cursor::HeldByCursor;
+use super::systems::handle_drag_end;
+use super::tray::*;
+use super::turns::*;
+
+use dragging::Draggable;
+use dragging::IsDragging;
+
+const PREVIEW_TINT: Color = Color::srgba(1., 1., 1., 0.6);
+
+pub struct DrawPilePlugin;
+
+impl Plugin for DrawPilePlugin {
+  fn build(&self, app: &mut App) {
+    app
+      .init_resource::<DrawPile>()
+      .add_systems(Update, (
+        hold_domino.run_if(action_just_pressed(DominoControlAction::Hold)),
+        refill_hand
+          .after(handle_drag_end)
+          .run_if(on_event::<DominoPlacementEvent>),
+        update_pile_previews,
+        layout_pile_panel.after(CameraControlSet),
+        update_pile_status_text,
+      ))
+      ;
+  }
+}
+
+/** How a level deals from its draw pile */
+#[derive(Clone, Copy, Debug)]
+pub struct HandSettings {
+  /** The tray is topped back up to this many dominoes after each placement */
+  pub hand_size: usize,
+  /** How many of the next dominoes in the pile are shown */
+  pub preview: usize,
+  /** Whether a domino can be set aside and swapped back in later */
+  pub hold_slot: bool,
+}
+
+impl Default for HandSettings {
+  fn default() -> Self {
+    Self {
+      hand_size: 5,
+      preview: 3,
+      hold_slot: true,
+    }
+  }
+}
+
+/**
+ The dominoes still to be dealt. Levels without hand settings deal
+ everything at once, as before.
+ */
+#[derive(Resource, Clone, Debug, Default)]
+pub struct DrawPile {
+  pub settings: Option<HandSettings>,
+  /** The next domino to be drawn is at the end */
+  pieces: Vec<DominoType>,
+}
+
+impl DrawPile {
+  pub fn new(settings: Option<HandSettings>, mut pieces: Vec<DominoType>, rng: &mut StreamRng) -> Self {
+    if settings.is_some() {
+      pieces.shuffle(rng);
+    }
+    pieces.reverse();
+
+    Self { settings, pieces }
+  }
+
+  /** The starting tray: a full hand, or the whole pile if there is no hand size */
+  pub fn deal_hand(&mut self) -> Vec<DominoType> {
+    let count = self.settings.map_or(self.pieces.len(), |settings| settings.hand_size);
+    std::iter::from_fn(|| self.draw()).take(count).collect()
+  }
+
+  pub fn draw(&mut self) -> Option<DominoType> {
+    self.pieces.pop()
+  }
+
+  /** The dominoes that will be drawn next, soonest first, as many as the level shows */
+  pub fn upcoming(&self) -> impl Iterator<Item = &DominoType> + '_ {
+    let preview = self.settings.map_or(0, |settings| settings.preview);
+    self.pieces.iter().rev().take(preview)
+  }
+
+  pub fn remaining(&self) -> usize {
+    self.pieces.len()
+  }
+
+  pub fn has_hold_slot(&self) -> bool {
+    self.settings.is_some_and(|settings| settings.hold_slot)
+  }
+}
+
+/** A domino set aside in the hold slot. It keeps its tray slot for when it is swapped back */
+#[derive(Component)]
+pub struct InHoldSlot;
+
+/** A look at one of the next dominoes in the pile, soonest first */
+#[derive(Component)]
+pub struct PilePreview(pub usize);
+
+#[derive(Component)]
+pub struct PileStatusText;
+
+/**
+  Sets the domino being dragged aside. Whatever was held before goes back to
+  the tray; if the slot was empty, a domino is drawn to keep the hand full.
+ */
+#[allow(clippy::type_complexity)]
+fn hold_domino(
+  mut commands: Commands,
+  mut pile: ResMut<DrawPile>,
+  dragging: Query<Entity, (With<IsDragging>, With<Draggable>, Without<InHoldSlot>)>,
+  held: Query<Entity, With<InHoldSlot>>,
+  slots: Query<&TraySlot>,
+  turns: Res<Turns>,
+) {
+  if !pile.has_hold_slot() { return; }
+  let Some(entity) = dragging.iter().next() else { return; };
+
+  commands.entity(entity)
+    .remove::<(IsDragging, HeldByCursor)>()
+    .insert(InHoldSlot);
+
+  if let Some(previous) = held.iter().next() {
+    commands.entity(previous).remove::<InHoldSlot>();
+  } else if let Some(d_type) = pile.draw() {
+    spawn_tray_domino(&mut commands, d_type, next_tray_slot(slots.iter().copied()), turns.current_owner());
+  }
+}
+
+/** Draws after each placement until the hand is full again or the pile runs out */
+pub fn refill_hand(
+  mut commands: Commands,
+  mut placement_evr: EventReader<DominoPlacementEvent>,
+  mut pile: ResMut<DrawPile>,
+  turns: Res<Turns>,
+  tray: Query<Option<&Owner>, (With<Draggable>, Without<InHoldSlot>)>,
+  slots: Query<&TraySlot>,
+) {
+  // Playing the held domino empties the hold slot
+  for event in placement_evr.read() {
+    commands.entity(event.domino).remove::<InHoldSlot>();
+  }
+
+  let Some(settings) = pile.settings else { return; };
+  let mut slot = next_tray_slot(slots.iter().copied());
+  // The new dominoes go to the player who just placed, so only their hand counts
+  let hand = tray.iter().filter(|owner| turns.is_current(*owner)).count();
+  for _ in hand..settings.hand_size {
+    let Some(d_type) = pile.draw() else { break; };
+    spawn_tray_domino(&mut commands, d_type, slot, turns.current_owner());
+    slot = TraySlot(slot.0 + 1);
+  }
+}
+
+fn update_pile_previews(
+  mut commands: Commands,
+  pile: Res<DrawPile>,
+  loaded_assets: Res<LoadedAssets>,
+  previews: Query<Entity, With<PilePreview>>,
+) {
+  if !pile.is_changed() { return; }
+
+  for preview in previews.iter() {
+    commands.entity(preview).despawn_recursive();
+  }
+
+  for (index, d_type) in pile.upcoming().enumerate() {
+    commands.spawn((
+      PilePreview(index),
+      Sprite {
+        color: PREVIEW_TINT,
+        ..loaded_assets.images.dominoes.get_domino(d_type)
+      },
+      Visibility::Hidden,
+    ));
+  }
+}
+
+/** Shows the hold slot at the left and the upcoming dominoes at the right, in a row above the tray */
+#[allow(clippy::type_complexity)]
+fn layout_pile_panel(
+  q_window: Query<&Window, With<PrimaryWindow>>,
+  q_camera: Query<(&Transform, &OrthographicProjection), (With<MainCamera>, Without<Domino>, Without<PilePreview>)>,
+  mut previews: Query<(&PilePreview, &mut Transform, &mut Visibility), Without<Domino>>,
+  mut held: Query<(&mut Domino, &mut Transform, &mut Visibility), (With<InHoldSlot>, Without<IsDragging>)>,
+) {
+  let (Ok(window), Ok((camera_transform, projection))) = (q_window.get_single(), q_camera.get_single()) else {
+    return;
+  };
+
+  let columns = tray_columns(window);
+  let preview_count = previews.iter().count();
+  let place = |column: usize, transform: &mut Transform, visibility: &mut Visibility| {
+    let screen = tray_cell_screen(window, column as f32, -1.);
+    let world = screen_to_world(window, camera_transform, projection.scale, screen);
+    transform.translation = world.extend(TRAY_Z);
+    transform.scale = Vec3::splat(projection.scale);
+    *visibility = Visibility::Inherited;
+  };
+
+  for (mut domino, mut transform, mut visibility) in held.iter_mut() {
+    if !domino.horizontal {
+      domino.horizontal = true;
+      transform.rotation = domino.rotation();
+    }
+    place(0, &mut transform, &mut visibility);
+  }
+
+  for (preview, mut transform, mut visibility) in previews.iter_mut() {
+    let column = (columns + preview.0).saturating_sub(preview_count);
+    place(column, &mut transform, &mut visibility);
+  }
+}
+
+fn update_pile_status_text(
+  pile: Res<DrawPile>,
+  mut texts: Query<&mut Text, With<PileStatusText>>,
+) {
+  for mut text in texts.iter_mut() {
+    if pile.is_changed() || text.is_added() {
+      text.0 = match pile.settings {
+        Some(settings) if settings.hold_slot => format!("Pile: {} left. [C] holds the dragged domino", pile.remaining()),
+        Some(_) => format!("Pile: {} left", pile.remaining()),
+        None => String::new(),
+      };
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::systems::random::GameRng;
+
+  use super::*;
+
+  fn settings(hand_size: usize, preview: usize) -> Option<HandSettings> {
+    Some(HandSettings { hand_size, preview, hold_slot: true })
+  }
+
+  #[test]
+  fn deals_a_hand_and_previews_what_comes_next() {
+    let mut pile = DrawPile::new(settings(5, 3), DominoType::ALL_TYPES.to_vec(), &mut GameRng::new(1).fork("draw_pile"));
+
+    let hand = pile.deal_hand();
+    assert_eq!(hand.len(), 5);
+    assert_eq!(pile.remaining(), DominoType::ALL_TYPES.len() - 5);
+
+    let upcoming: Vec<_> = pile.upcoming().copied().collect();
+    assert_eq!(upcoming.len(), 3);
+    let drawn: Vec<_> = std::iter::from_fn(|| pile.draw()).take(3).collect();
+    assert_eq!(drawn, upcoming);
+  }
+
+  #[test]
+  fn deals_everything_in_order_without_a_hand_size() {
+    let mut pile = DrawPile::new(None, DominoType::ALL_TYPES.to_vec(), &mut GameRng::new(1).fork("draw_pile"));
+
+    assert_eq!(pile.deal_hand(), DominoType::ALL_TYPES);
+    assert_eq!(pile.remaining(), 0);
+    assert_eq!(pile.upcoming().count(), 0);
+  }
+
+  fn shuffled(seed: u64) -> Vec<DominoType> {
+    let mut pile = DrawPile::new(settings(5, 3), DominoType::ALL_TYPES.to_vec(), &mut GameRng::new(seed).fork("draw_pile"));
+    std::iter::from_fn(|| pile.draw()).collect()
+  }
+
+  #[test]
+  fn the_same_seed_deals_the_same_pile() {
+    assert_eq!(shuffled(42), shuffled(42));
+    assert_ne!(shuffled(42), shuffled(43));
+  }
+
+  #[test]
+  fn other_streams_leave_the_pile_alone() {
+    let rng = GameRng::new(42);
+    // Rules drawing from their own stream first, as when a level starts
+    let _ = DrawPile::new(settings(5, 3), DominoType::ALL_TYPES.to_vec(), &mut rng.fork("rules"));
+
+    let mut pile = DrawPile::new(settings(5, 3), DominoType::ALL_TYPES.to_vec(), &mut rng.fork("draw_pile"));
+    let drawn: Vec<_> = std::iter::from_fn(|| pile.draw()).collect();
+    assert_eq!(drawn, shuffled(42));
+    assert_ne!(drawn, DominoType::ALL_TYPES.iter().rev().copied().collect::<Vec<_>>());
+  }
+}
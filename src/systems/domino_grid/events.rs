@@ -1,16 +1,41 @@
 use crate::prelude::*;
 
+use super::components::*;
+use super::rules::PlacementRejection;
+
 pub struct DominoGridEventsPlugin;
 
 impl Plugin for DominoGridEventsPlugin {
   fn build(&self, app: &mut App) {
     app
       .add_event::<DominoPlacementEvent>()
+      .add_event::<DominoRemoved>()
+      .add_event::<PlacementRejected>()
       .add_event::<DragEndEvent>()
+      .add_event::<LevelStarted>()
+      .add_event::<RestartLevel>()
+      .add_event::<TurnPassed>()
       ;
   }
 }
 
+/** A level has just been set up, fresh or after a reset */
+#[derive(Event, Clone, Copy, Debug)]
+pub struct LevelStarted {
+  /** Index into `Levels::all` */
+  pub level: usize,
+  /** The seed everything random in the level came from */
+  pub seed: u64,
+}
+
+/** Sets the current level up again, like the reset action, e.g. after it was changed from the console */
+#[derive(Event, Clone, Copy, Debug, Default)]
+pub struct RestartLevel;
+
+/** The rules let the player whose turn it is pass */
+#[derive(Event, Clone, Copy, Debug, Default)]
+pub struct TurnPassed;
+
 /**
  The event when anything is no longer being dragged.
  This can happen anywhere, and so it should handle the case of the domino
@@ -23,17 +48,62 @@ pub struct DragEndEvent {
 
 /**
  This is when the domino is placed in the grid's droppable area.
- The domino should then be added to the grid, and sprites and droppable areas should be
- updated.
+ By the time this is sent the domino is already on the `Board` and the rules
+ have been told about it, so listeners only need to react to it.
  */
-#[derive(Event)]
-pub enum DominoPlacementEvent {
-  Horizontal {
-    left_cell_col: i32,
-    row: i32,
-  },
-  Vertical {
-    bottom_cell_row: i32,
-    column: i32,
+#[derive(Event, Clone, Copy, Debug)]
+pub struct DominoPlacementEvent {
+  pub domino: Entity,
+  pub d_type: DominoType,
+  /** Where the domino went. This also gives its orientation */
+  pub placement: PlacementArea,
+  /** Which color landed in which cell, first half first */
+  pub cells: [(IVec2, DominoColor); 2],
+}
+
+impl DominoPlacementEvent {
+  pub fn new(domino: Entity, d_type: DominoType, placement: PlacementArea) -> Self {
+    Self {
+      domino,
+      d_type,
+      placement,
+      cells: placement.halves(d_type),
+    }
+  }
+}
+
+/**
+ A placed domino was taken off the grid. The entity may already be despawned,
+ so everything about it is carried in the event.
+ */
+#[derive(Event, Clone, Copy, Debug)]
+pub struct DominoRemoved {
+  pub domino: Entity,
+  pub d_type: DominoType,
+  pub placement: PlacementArea,
+  pub cells: [(IVec2, DominoColor); 2],
+}
+
+impl DominoRemoved {
+  pub fn new(domino: Entity, d_type: DominoType, placement: PlacementArea) -> Self {
+    Self {
+      domino,
+      d_type,
+      placement,
+      cells: placement.halves(d_type),
+    }
   }
-}
\ No newline at end of file
+}
+
+/**
+ A dragged domino was let go but could not be placed, and has gone back to
+ the tray.
+ */
+#[derive(Event, Clone, Copy, Debug)]
+pub struct PlacementRejected {
+  pub domino: Entity,
+  pub d_type: DominoType,
+  /** The area it was dropped on, if it was dropped on one at all */
+  pub placement: Option<PlacementArea>,
+  pub reason: PlacementRejection,
+}
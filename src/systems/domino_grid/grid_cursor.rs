@@ -0,0 +1,239 @@
+use leafwing_input_manager::common_conditions::action_just_pressed;
+use leafwing_input_manager::prelude::ActionState;
+
+use crate::prelude::*;
+use crate::systems::DominoControlAction;
+
+use super::board::*;
+use super::components::*;
+use super::events::*;
+use super::tray::Tray;
+
+use bevy::color::palettes::css::GOLD;
+use dragging::Draggable;
+use dragging::IsDragging;
+use grid::TILE_SIZE;
+
+/**
+ Lets dominoes be placed without a mouse. A tray domino is picked up with the
+ select actions and carried by a cursor that moves a cell at a time. Held
+ dominoes are given `IsDragging` like pointer-dragged ones, so rotating,
+ previewing and dropping all go through the same systems.
+ */
+pub struct GridCursorPlugin;
+
+impl Plugin for GridCursorPlugin {
+  fn build(&self, app: &mut App) {
+    app
+      .init_resource::<GridCursor>()
+      .add_systems(Update, (
+        (
+          select_tray_domino.run_if(
+            action_just_pressed(DominoControlAction::SelectNext)
+              .or(action_just_pressed(DominoControlAction::SelectPrevious))
+          ),
+          move_grid_cursor,
+          follow_grid_cursor,
+          place_held_domino.run_if(action_just_pressed(DominoControlAction::Place)),
+          cancel_held_domino.run_if(action_just_pressed(DominoControlAction::Cancel)),
+        ).chain(),
+        draw_grid_cursor.run_if(|cursor: Res<GridCursor>| cursor.visible),
+      ))
+      ;
+  }
+}
+
+#[derive(Resource, Default)]
+pub struct GridCursor {
+  pub cell: IVec2,
+  /** Only shown once the cursor has been used, so it stays out of the way of mouse players */
+  pub visible: bool,
+}
+
+/** A domino being carried by the grid cursor rather than the pointer */
+#[derive(Component)]
+pub struct HeldByCursor;
+
+/** Lets go of a held domino, which the tray then lays back out in its slot */
+fn put_back(commands: &mut Commands, entity: Entity) {
+  commands.entity(entity)
+    .remove::<IsDragging>()
+    .remove::<HeldByCursor>();
+}
+
+fn select_tray_domino(
+  mut commands: Commands,
+  mut cursor: ResMut<GridCursor>,
+  tray: Res<Tray>,
+  action_state: Res<ActionState<DominoControlAction>>,
+  dominoes: Query<(Has<IsDragging>, Has<HeldByCursor>), With<Draggable>>,
+) {
+  // The tray in the order it is laid out, leaving out any the pointer is dragging
+  let tray: Vec<_> = tray.order
+    .iter()
+    .filter_map(|entity| {
+      let (dragging, held) = dominoes.get(*entity).ok()?;
+      (!dragging || held).then_some((*entity, held))
+    })
+    .collect();
+
+  if tray.is_empty() { return; }
+
+  let current = tray.iter().position(|(_, held)| *held);
+  if let Some(index) = current {
+    put_back(&mut commands, tray[index].0);
+  }
+
+  let forwards = action_state.just_pressed(&DominoControlAction::SelectNext);
+  let count = tray.len();
+  let next = match current {
+    Some(index) if forwards => (index + 1) % count,
+    Some(index) => (index + count - 1) % count,
+    None if forwards => 0,
+    None => count - 1,
+  };
+
+  commands.entity(tray[next].0).insert((IsDragging, HeldByCursor));
+  cursor.visible = true;
+}
+
+fn move_grid_cursor(
+  mut cursor: ResMut<GridCursor>,
+  board: Res<Board>,
+  action_state: Res<ActionState<DominoControlAction>>,
+) {
+  let moves = [
+    (DominoControlAction::CursorUp, IVec2::Y),
+    (DominoControlAction::CursorDown, IVec2::NEG_Y),
+    (DominoControlAction::CursorLeft, IVec2::NEG_X),
+    (DominoControlAction::CursorRight, IVec2::X),
+  ];
+
+  for (action, step) in moves {
+    if action_state.just_pressed(&action) {
+      let moved = cursor.cell + step;
+      if board.in_bounds(moved) {
+        cursor.cell = moved;
+      }
+      cursor.visible = true;
+    }
+  }
+}
+
+/** Keeps held dominoes over the area starting at the cursor */
+fn follow_grid_cursor(
+  cursor: Res<GridCursor>,
+  mut held: Query<(&mut Transform, &Domino), With<HeldByCursor>>,
+) {
+  for (mut transform, domino) in held.iter_mut() {
+    let center = grid::area_center(cursor.cell, domino.horizontal);
+    transform.translation = center.extend(2.);
+  }
+}
+
+/** Drops held dominoes exactly like letting go of the mouse */
+fn place_held_domino(
+  mut commands: Commands,
+  mut drag_end_evw: EventWriter<DragEndEvent>,
+  held: Query<Entity, With<HeldByCursor>>,
+) {
+  for draggable in held.iter() {
+    drag_end_evw.send(DragEndEvent { draggable });
+    commands.entity(draggable).remove::<HeldByCursor>();
+  }
+}
+
+fn cancel_held_domino(
+  mut commands: Commands,
+  held: Query<Entity, With<HeldByCursor>>,
+) {
+  for entity in held.iter() {
+    put_back(&mut commands, entity);
+  }
+}
+
+fn draw_grid_cursor(
+  mut gizmos: Gizmos,
+  cursor: Res<GridCursor>,
+) {
+  gizmos.rect_2d(
+    Isometry2d::from_translation(cursor.cell.as_vec2() * TILE_SIZE),
+    Vec2::splat(TILE_SIZE),
+    GOLD,
+  );
+}
+
+#[cfg(test)]
+mod tests {
+  use bevy::input::gamepad::{GamepadConnection, GamepadConnectionEvent};
+  use bevy::input::InputPlugin;
+  use leafwing_input_manager::plugin::InputManagerPlugin;
+  use leafwing_input_manager::user_input::Buttonlike;
+
+  use crate::systems::actions::Controls;
+
+  use super::*;
+
+  /** The cursor systems, driven by the default controls and a connected pad */
+  fn pad_app() -> App {
+    let mut app = App::new();
+    app
+      .add_plugins((MinimalPlugins, InputPlugin, InputManagerPlugin::<DominoControlAction>::default()))
+      .init_resource::<ActionState<DominoControlAction>>()
+      .insert_resource(Controls::default().input_map())
+      .insert_resource(Board::new(5, 5))
+      .init_resource::<GridCursor>()
+      .add_event::<DragEndEvent>()
+      .add_systems(Update, (
+        move_grid_cursor,
+        follow_grid_cursor,
+        place_held_domino.run_if(action_just_pressed(DominoControlAction::Place)),
+      ).chain());
+
+    let gamepad = app.world_mut().spawn_empty().id();
+    app.world_mut().send_event(GamepadConnectionEvent::new(gamepad, GamepadConnection::Connected {
+      name: "Pad".to_string(),
+      vendor_id: None,
+      product_id: None,
+    }));
+    app.update();
+    app.update();
+    app
+  }
+
+  fn tap(app: &mut App, button: GamepadButton) {
+    button.press(app.world_mut());
+    app.update();
+    button.release(app.world_mut());
+    app.update();
+  }
+
+  #[test]
+  fn pad_moves_the_cursor_and_places() {
+    let mut app = pad_app();
+    let domino = app.world_mut().spawn((
+      Domino { d_type: DominoType::Blue(DominoColor::Red), horizontal: true },
+      Transform::default(),
+      IsDragging,
+      HeldByCursor,
+    )).id();
+
+    tap(&mut app, GamepadButton::DPadRight);
+    tap(&mut app, GamepadButton::DPadUp);
+    tap(&mut app, GamepadButton::DPadUp);
+    assert_eq!(app.world().resource::<GridCursor>().cell, IVec2::new(1, 2));
+    let held_at = app.world().get::<Transform>(domino).unwrap().translation.truncate();
+    assert_eq!(held_at, grid::area_center(IVec2::new(1, 2), true));
+
+    // Off the edge of the board does nothing
+    tap(&mut app, GamepadButton::DPadLeft);
+    tap(&mut app, GamepadButton::DPadLeft);
+    assert_eq!(app.world().resource::<GridCursor>().cell, IVec2::new(0, 2));
+
+    GamepadButton::South.press(app.world_mut());
+    app.update();
+    let dropped: Vec<_> = app.world_mut().resource_mut::<Events<DragEndEvent>>().drain().map(|event| event.draggable).collect();
+    assert_eq!(dropped, [domino]);
+    assert!(app.world().get::<HeldByCursor>(domino).is_none());
+  }
+}
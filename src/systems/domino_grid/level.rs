@@ -0,0 +1,130 @@
+use crate::prelude::*;
+
+use super::ai::Difficulty;
+use super::board::*;
+use super::draw_pile::HandSettings;
+
+/** Everything needed to set up a board: its size and the rules it is played with */
+#[derive(Clone, Debug)]
+pub struct Level {
+  pub name: String,
+  pub grid_width: i32,
+  pub grid_height: i32,
+  /** Names of rule sets from the `RuleSetRegistry`, applied together */
+  pub rules: Vec<String>,
+  /** Play from a hand drawn out of a shuffled pile, rather than with every domino in the tray */
+  pub hand: Option<HandSettings>,
+  /** Plays the level the same way every time, whatever the session's seed */
+  pub seed: Option<u64>,
+  /** How many people play. More than one seat means hotseat play, each player with their own hand */
+  pub players: usize,
+  /** Computer players, sitting after the people */
+  pub ai: Vec<Difficulty>,
+}
+
+impl Level {
+  pub fn new(name: &str, rules: &[&str]) -> Self {
+    Self {
+      name: name.to_string(),
+      grid_width: DEFAULT_GRID_WIDTH,
+      grid_height: DEFAULT_GRID_HEIGHT,
+      rules: rules.iter().map(|rule| rule.to_string()).collect(),
+      hand: None,
+      seed: None,
+      players: 1,
+      ai: vec![],
+    }
+  }
+
+  /** Everyone taking turns, people and computers */
+  pub fn seats(&self) -> usize {
+    self.players + self.ai.len()
+  }
+
+  pub fn with_seed(mut self, seed: u64) -> Self {
+    self.seed = Some(seed);
+    self
+  }
+
+  pub fn with_players(mut self, players: usize) -> Self {
+    self.players = players;
+    self
+  }
+
+  pub fn with_ai(mut self, difficulty: Difficulty) -> Self {
+    self.ai.push(difficulty);
+    self
+  }
+
+  pub fn with_hand(mut self, hand: HandSettings) -> Self {
+    self.hand = Some(hand);
+    self
+  }
+}
+
+/** The levels that can be played, and which one is being played */
+#[derive(Resource)]
+pub struct Levels {
+  pub all: Vec<Level>,
+  pub current: usize,
+}
+
+impl Default for Levels {
+  fn default() -> Self {
+    Self {
+      all: vec![
+        Level::new("Sandbox", &["free_form"]),
+        Level::new("Color sandbox", &["free_form", "color_match"]),
+        Level::new("Draw pile", &["free_form", "color_match"]).with_hand(HandSettings::default()),
+        Level::new("Draw pile (set deal)", &["free_form", "color_match"])
+          .with_hand(HandSettings::default())
+          .with_seed(7),
+        Level::new("Hotseat (2 players)", &["free_form", "color_match"])
+          .with_hand(HandSettings { hold_slot: false, ..default() })
+          .with_players(2),
+        Level::new("Hotseat (4 players)", &["free_form", "color_match"])
+          .with_hand(HandSettings { hand_size: 3, hold_slot: false, ..default() })
+          .with_players(4),
+        Level::new("Versus computer", &["free_form", "color_match"])
+          .with_hand(HandSettings { hold_slot: false, ..default() })
+          .with_ai(Difficulty::Normal),
+        Level::new("Versus computer (hard)", &["free_form", "color_match"])
+          .with_hand(HandSettings { hold_slot: false, ..default() })
+          .with_ai(Difficulty::Hard),
+        Level::new("Computer showdown", &["free_form", "color_match"])
+          .with_hand(HandSettings { hold_slot: false, ..default() })
+          .with_players(0)
+          .with_ai(Difficulty::Easy)
+          .with_ai(Difficulty::Normal)
+          .with_ai(Difficulty::Hard),
+        Level::new("Classic dominoes (block)", &["classic_block"]),
+        Level::new("Classic dominoes (draw)", &["classic_draw"]),
+        Level::new("Classic dominoes (2 players)", &["classic_block"]).with_players(2),
+      ],
+      current: 0,
+    }
+  }
+}
+
+impl Levels {
+  pub fn current(&self) -> &Level {
+    &self.all[self.current]
+  }
+
+  /** A level by its name (ignoring case) or its number, counting from one */
+  pub fn find(&self, name: &str) -> Option<&Level> {
+    self.index_of(name).map(|index| &self.all[index])
+  }
+
+  /** Where `find` would find the level in `all` */
+  pub fn index_of(&self, name: &str) -> Option<usize> {
+    match name.parse::<usize>() {
+      Ok(number) => number.checked_sub(1).filter(|index| *index < self.all.len()),
+      Err(_) => self.all.iter().position(|level| level.name.eq_ignore_ascii_case(name)),
+    }
+  }
+
+  pub fn advance(&mut self) {
+    self.current = (self.current + 1) % self.all.len();
+  }
+}
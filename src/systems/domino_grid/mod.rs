@@ -1,12 +1,48 @@
+pub mod ai;
+pub mod board;
+mod board_text;
 pub mod components;
+pub mod console_commands;
+pub mod debug_overlay;
+pub mod draw_pile;
 pub mod events;
+pub mod grid_cursor;
+pub mod level;
+pub mod net;
+pub mod placement;
+pub mod preview;
+pub mod replay;
+pub mod rpc;
+pub mod rules;
 mod systems;
+pub mod tiling;
+pub mod tray;
+pub mod turns;
+pub mod ui;
 
-use bevy::input::common_conditions::{input_just_pressed, input_toggle_active};
+use leafwing_input_manager::common_conditions::{action_just_pressed, action_toggle_active};
+use ai::*;
+use board::*;
+use components::*;
+use console_commands::*;
+use debug_overlay::*;
+use draw_pile::*;
+use grid_cursor::*;
+use level::*;
+use net::*;
+use placement::*;
+use preview::*;
+use replay::*;
+use rpc::*;
+use rules::*;
 use systems::*;
+use tray::*;
+use turns::*;
+use ui::*;
 use events::*;
 
 use crate::prelude::*;
+use crate::systems::DominoControlAction;
 
 pub struct DominoesPlugin;
 
@@ -15,25 +51,55 @@ impl Plugin for DominoesPlugin {
     app
       .add_plugins((
         DominoGridEventsPlugin,
+        RulesPlugin,
+        PlacementPreviewPlugin,
+        GridCursorPlugin,
+        TrayPlugin,
+        DrawPilePlugin,
+        ReplayPlugin,
+        TurnsPlugin,
+        TurnIndicatorPlugin,
+        AiPlugin,
+        NetPlugin,
+        RpcPlugin,
+        DominoCommandsPlugin,
+        DebugOverlayPlugin,
       ))
+      // Reflected so inspectors and the remote protocol can see and edit the board
+      .register_type::<Domino>()
+      .register_type::<DominoType>()
+      .register_type::<DominoColor>()
+      .register_type::<PlacementArea>()
+      .register_type::<Placed>()
+      .register_type::<GridSquare>()
+      .init_resource::<Board>()
+      .init_resource::<Levels>()
+      .init_resource::<PlacementSettings>()
       .add_systems(OnEnter(MenuState::InGame), (
         systems::init,
         init_grid,
       ))
       .add_systems(Update, (
-        on_domino_spawn,
+        sync_domino_sprite,
         on_draggable_spawn,
         handle_dragging,
         on_grid_square_spawn,
-        handle_horizontal_domino_placed,
-        handle_vertical_domino_placed,
-        debug_draw_placement_areas.run_if(input_toggle_active(false, KeyCode::Escape)),
+        log_domino_events,
+        update_camera_bounds,
+        debug_draw_placement_areas.run_if(action_toggle_active(false, DominoControlAction::ToggleHitboxes)),
         handle_drag_end.run_if(on_event::<DragEndEvent>),
+        next_level
+          .before(reset)
+          .run_if(action_just_pressed(DominoControlAction::NextLevel)),
         (
           reset,
           init,
           init_grid,
-        ).chain().run_if(input_just_pressed(KeyCode::Backspace))
+        ).chain().run_if(
+          action_just_pressed(DominoControlAction::Reset)
+            .or(action_just_pressed(DominoControlAction::NextLevel))
+            .or(on_event::<RestartLevel>)
+        )
       ))
       ;
   }
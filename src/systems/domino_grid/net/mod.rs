@@ -0,0 +1,681 @@
+pub mod protocol;
+
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use leafwing_input_manager::plugin::InputManagerSystem;
+use leafwing_input_manager::prelude::ActionState;
+
+use crate::prelude::*;
+use crate::systems::random::{stable_hash, GameRng};
+use crate::systems::DominoControlAction;
+
+use super::board::*;
+use super::components::*;
+use super::draw_pile::DrawPile;
+use super::events::*;
+use super::level::Levels;
+use super::systems::handle_drag_end;
+use super::tray::TraySlot;
+use super::turns::*;
+
+use protocol::*;
+
+use dragging::Draggable;
+use dragging::IsDragging;
+
+const DEFAULT_PORT: u16 = 7878;
+/** How long a client waits between attempts to reach the host */
+const RECONNECT_DELAY: f32 = 2.;
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(500);
+
+/** The protocol has no command for these, so they would split the games apart */
+const UNSHARED: [DominoControlAction; 2] = [
+  DominoControlAction::Draw,
+  DominoControlAction::Hold,
+];
+
+/** Only the host picks and restarts levels */
+const HOST_ONLY: [DominoControlAction; 2] = [
+  DominoControlAction::Reset,
+  DominoControlAction::NextLevel,
+];
+
+/**
+ Plays a hotseat level across several instances on a network. Started with
+ `--host [port]` on one instance and `--join <address:port>` on the others.
+
+ The host is in charge: every turn is a command made from the placement
+ event (or pass) of whoever's turn it was. Clients send theirs to the host,
+ which applies it and passes it on, and every instance applies the same
+ commands in the same order through the usual drop path, like a replay.
+ Each command carries a hash of the sender's game once it was applied, so
+ an instance that has drifted notices. Drifting, joining late and
+ reconnecting are all handled the same way: the host sends the level, its
+ seed and every command so far, and the client plays them through again.
+ */
+pub struct NetPlugin;
+
+impl Plugin for NetPlugin {
+  fn build(&self, app: &mut App) {
+    let Some(role) = NetRole::from_args(std::env::args().skip(1)) else { return; };
+    let session = match NetSession::open(role) {
+      Ok(session) => session,
+      Err(error) => {
+        warn!("Could not start the network game: {error}");
+        return;
+      },
+    };
+
+    // Everyone needs a seat, so start on a level with more than one
+    let mut levels = Levels::default();
+    if let Some(index) = levels.all.iter().position(|level| level.players > 1) {
+      levels.current = index;
+    }
+
+    app
+      .insert_resource(levels)
+      .insert_resource(session)
+      .add_systems(Startup, spawn_net_status_text)
+      .add_systems(PreUpdate, drive_network
+        .in_set(InputManagerSystem::ManualControl)
+        .run_if(in_state(MenuState::InGame)))
+      .add_systems(Update, (
+        start_network_level.run_if(on_event::<LevelStarted>),
+        capture_local_commands
+          .after(handle_drag_end)
+          .before(end_turn_on_placement),
+        update_net_status_text,
+      ))
+      ;
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NetRole {
+  Host { port: u16 },
+  Join { address: String },
+}
+
+impl NetRole {
+  pub fn from_args(args: impl IntoIterator<Item = String>) -> Option<Self> {
+    let mut args = args.into_iter().peekable();
+    let mut role = None;
+
+    while let Some(arg) = args.next() {
+      match arg.as_str() {
+        "--host" => {
+          let port = args.peek().and_then(|port| port.parse().ok());
+          if port.is_some() {
+            args.next();
+          }
+          role = Some(Self::Host { port: port.unwrap_or(DEFAULT_PORT) });
+        },
+        "--join" => match args.next() {
+          Some(address) => role = Some(Self::Join { address }),
+          None => warn!("--join needs an address, like localhost:{DEFAULT_PORT}"),
+        },
+        _ => {},
+      }
+    }
+
+    role
+  }
+}
+
+/** A TCP stream that is never waited on: sends are queued and whatever has arrived is read each frame */
+struct Connection {
+  stream: TcpStream,
+  inbox: Vec<u8>,
+  outbox: Vec<u8>,
+}
+
+impl Connection {
+  fn new(stream: TcpStream) -> std::io::Result<Self> {
+    stream.set_nonblocking(true)?;
+    stream.set_nodelay(true)?;
+    Ok(Self { stream, inbox: vec![], outbox: vec![] })
+  }
+
+  /** Messages that can't be encoded are refused rather than sent garbled */
+  fn send(&mut self, message: &Message) {
+    match encode(message) {
+      Ok(frame) => self.outbox.extend(frame),
+      Err(error) => warn!("Not sending {message:?}: {error}"),
+    }
+  }
+
+  /** Sends what it can and reads what has arrived. An error means the connection is gone */
+  fn pump(&mut self) -> Result<Vec<Message>, String> {
+    while !self.outbox.is_empty() {
+      match self.stream.write(&self.outbox) {
+        Ok(0) => return Err("connection closed".to_string()),
+        Ok(written) => { self.outbox.drain(..written); },
+        Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+        Err(error) if error.kind() == ErrorKind::Interrupted => {},
+        Err(error) => return Err(error.to_string()),
+      }
+    }
+
+    let mut chunk = [0; 4096];
+    loop {
+      match self.stream.read(&mut chunk) {
+        Ok(0) => return Err("connection closed".to_string()),
+        Ok(read) => self.inbox.extend(&chunk[..read]),
+        Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+        Err(error) if error.kind() == ErrorKind::Interrupted => {},
+        Err(error) => return Err(error.to_string()),
+      }
+    }
+
+    decode_frames(&mut self.inbox)
+  }
+}
+
+struct Peer {
+  connection: Connection,
+  seat: Option<usize>,
+}
+
+enum Link {
+  Host {
+    listener: TcpListener,
+    peers: Vec<Peer>,
+  },
+  Client {
+    address: String,
+    connection: Option<Connection>,
+    retry_in: f32,
+    /** Refused by the host, so there is no point trying again */
+    given_up: bool,
+  },
+}
+
+#[derive(Resource)]
+pub struct NetSession {
+  link: Link,
+  /** The seat played here. The host always has the first one */
+  pub seat: Option<usize>,
+  level: usize,
+  seed: u64,
+  /** Every command of the level so far, in order */
+  log: Vec<Sequenced>,
+  /** Commands from elsewhere still to be applied, one a frame */
+  incoming: VecDeque<Sequenced>,
+  /** The command applied last frame, and whether it was made here. Its hash is taken once it has settled */
+  settling: Option<(Sequenced, bool)>,
+  /** The level is being set up again, and commands wait until it has started */
+  restarting: bool,
+  /** What is being applied from elsewhere right now, so it isn't taken for a move made here */
+  applying_place: Option<Entity>,
+  applying_pass: bool,
+  /** Actions pressed for a command, to let go of next frame */
+  pressed: Vec<DominoControlAction>,
+  pub status: String,
+}
+
+impl NetSession {
+  pub fn open(role: NetRole) -> Result<Self, String> {
+    let (link, seat, status) = match role {
+      NetRole::Host { port } => {
+        let listener = TcpListener::bind(("0.0.0.0", port)).map_err(|error| error.to_string())?;
+        listener.set_nonblocking(true).map_err(|error| error.to_string())?;
+        let status = format!("Hosting on port {port}");
+        (Link::Host { listener, peers: vec![] }, Some(0), status)
+      },
+      NetRole::Join { address } => {
+        let status = format!("Joining {address}");
+        (Link::Client { address, connection: None, retry_in: 0., given_up: false }, None, status)
+      },
+    };
+    info!("{status}");
+
+    Ok(Self {
+      link,
+      seat,
+      level: 0,
+      seed: 0,
+      log: vec![],
+      incoming: VecDeque::new(),
+      settling: None,
+      restarting: false,
+      applying_place: None,
+      applying_pass: false,
+      pressed: vec![],
+      status,
+    })
+  }
+
+  pub fn is_host(&self) -> bool {
+    matches!(self.link, Link::Host { .. })
+  }
+
+  /** Sends to every client, bar the one playing `except` */
+  fn broadcast(&mut self, message: &Message, except: Option<usize>) {
+    if let Link::Host { peers, .. } = &mut self.link {
+      for peer in peers.iter_mut().filter(|peer| peer.seat.is_some() && peer.seat != except) {
+        peer.connection.send(message);
+      }
+    }
+  }
+
+  /**
+    Gets a command's sender back in step. The host's game is the real one,
+    so the host sends the whole game to the client again, and a client asks
+    the host for it.
+   */
+  fn report_desync(&mut self, sequenced: Sequenced) {
+    let welcome = welcome(sequenced.seat as usize, self.level, self.seed, &self.log);
+    match &mut self.link {
+      Link::Host { peers, .. } => {
+        for peer in peers.iter_mut().filter(|peer| peer.seat == Some(sequenced.seat as usize)) {
+          peer.connection.send(&welcome);
+        }
+      },
+      Link::Client { connection: Some(connection), .. } => connection.send(&Message::Desync { seq: sequenced.seq }),
+      Link::Client { .. } => {},
+    }
+  }
+
+  fn send_to_host(&mut self, message: &Message) {
+    if let Link::Client { connection: Some(connection), .. } = &mut self.link {
+      connection.send(message);
+    }
+  }
+}
+
+/** Everything a client needs to catch up: its seat, the level, its seed and the game so far */
+fn welcome(seat: usize, level: usize, seed: u64, log: &[Sequenced]) -> Message {
+  Message::Welcome { seat: seat as u8, level: level as u16, seed, log: log.to_vec() }
+}
+
+/** Everything the instances must agree on, boiled down to compare cheaply */
+pub fn state_hash(board: &Board, turns: &Turns, pile: &DrawPile) -> u64 {
+  let mut bytes = vec![];
+  for (cell, board_cell) in board.cells() {
+    bytes.extend(cell.x.to_le_bytes());
+    bytes.extend(cell.y.to_le_bytes());
+    bytes.push(board_cell.color.index() as u8);
+  }
+  bytes.extend((turns.current as u32).to_le_bytes());
+  for player in &turns.players {
+    bytes.extend(player.score.to_le_bytes());
+  }
+  bytes.extend((pile.remaining() as u32).to_le_bytes());
+  stable_hash(&bytes)
+}
+
+/** Exchanges messages, and applies a command from elsewhere, before the game's systems see this frame's input */
+#[allow(clippy::too_many_arguments)]
+fn drive_network(
+  mut commands: Commands,
+  mut session: ResMut<NetSession>,
+  mut action_state: ResMut<ActionState<DominoControlAction>>,
+  mut levels: ResMut<Levels>,
+  mut game_rng: ResMut<GameRng>,
+  mut turns: ResMut<Turns>,
+  mut drag_end_evw: EventWriter<DragEndEvent>,
+  board: Res<Board>,
+  pile: Res<DrawPile>,
+  time: Res<Time>,
+  mut dominoes: Query<(Entity, &TraySlot, &mut Domino, &mut Transform), With<Draggable>>,
+) {
+  let session = session.as_mut();
+
+  for action in std::mem::take(&mut session.pressed) {
+    action_state.release(&action);
+  }
+  // The host plays the computer's seats
+  let ours = session.seat == Some(turns.current) || (session.is_host() && turns.current_ai().is_some());
+  let my_turn = ours && !session.restarting && session.incoming.is_empty();
+  if !my_turn {
+    action_state.release(&DominoControlAction::Pass);
+  }
+  for action in UNSHARED {
+    action_state.release(&action);
+  }
+  if !session.is_host() {
+    for action in HOST_ONLY {
+      action_state.release(&action);
+    }
+  }
+
+  // The last command has been through every system by now, so its hash can be taken
+  if let Some((sequenced, made_here)) = session.settling.take() {
+    settle(session, sequenced, made_here, state_hash(&board, &turns, &pile));
+  }
+
+  let restart = if session.is_host() {
+    host_io(session, levels.current().players);
+    None
+  } else {
+    client_io(session, time.delta_secs())
+  };
+  turns.local = session.seat;
+
+  if let Some((level, seed)) = restart {
+    levels.current = (level as usize).min(levels.all.len() - 1);
+    *game_rng = GameRng::new(seed);
+    action_state.press(&DominoControlAction::Reset);
+    session.pressed.push(DominoControlAction::Reset);
+    session.restarting = true;
+    session.log.clear();
+    session.settling = None;
+    return;
+  }
+
+  if session.restarting || session.settling.is_some() {
+    return;
+  }
+  let Some(sequenced) = session.incoming.pop_front() else { return; };
+  let seat = sequenced.seat as usize;
+
+  if seat != turns.current {
+    warn!("Player {} moved out of turn", seat + 1);
+    session.report_desync(sequenced);
+    return;
+  }
+
+  match sequenced.command {
+    PlayerCommand::Place { slot, area, d_type } => {
+      let Some((entity, _, mut domino, mut transform)) = dominoes.iter_mut().find(|(_, tray_slot, ..)| tray_slot.0 == slot as usize) else {
+        warn!("There is no domino in slot {slot} to place");
+        session.report_desync(sequenced);
+        return;
+      };
+
+      domino.d_type = d_type;
+      domino.horizontal = area.is_horizontal();
+      transform.rotation = domino.rotation();
+      transform.translation = area.center().extend(transform.translation.z);
+      commands.entity(entity).insert(IsDragging);
+      drag_end_evw.send(DragEndEvent { draggable: entity });
+      session.applying_place = Some(entity);
+    },
+    PlayerCommand::Pass => {
+      action_state.press(&DominoControlAction::Pass);
+      session.pressed.push(DominoControlAction::Pass);
+      session.applying_pass = true;
+    },
+  }
+  session.settling = Some((sequenced, false));
+}
+
+/** Numbers and sends a command made here, or checks one from elsewhere against its sender's hash */
+fn settle(session: &mut NetSession, mut sequenced: Sequenced, made_here: bool, hash: u64) {
+  let seat = sequenced.seat as usize;
+
+  if made_here {
+    sequenced.seq = session.log.len() as u32;
+    sequenced.hash = hash;
+    session.broadcast(&Message::Command(sequenced), None);
+    session.send_to_host(&Message::Command(sequenced));
+  } else {
+    if hash != sequenced.hash {
+      warn!("Out of sync after command {}", sequenced.seq);
+      session.report_desync(sequenced);
+    }
+    // The host's hash is the one everyone checks against
+    sequenced.hash = hash;
+    if session.is_host() {
+      session.broadcast(&Message::Command(sequenced), Some(seat));
+    }
+  }
+
+  session.log.push(sequenced);
+}
+
+fn host_io(session: &mut NetSession, players: usize) {
+  let NetSession { link: Link::Host { listener, peers }, level, seed, log, incoming, status, .. } = session else {
+    return;
+  };
+
+  while let Ok((stream, address)) = listener.accept() {
+    match Connection::new(stream) {
+      Ok(connection) => {
+        info!("{address} connected");
+        peers.push(Peer { connection, seat: None });
+      },
+      Err(error) => warn!("Could not set up the connection from {address}: {error}"),
+    }
+  }
+
+  let mut lost = vec![];
+  for index in 0..peers.len() {
+    let received = match peers[index].connection.pump() {
+      Ok(received) => received,
+      Err(error) => {
+        info!("Lost a connection: {error}");
+        lost.push(index);
+        continue;
+      },
+    };
+
+    for message in received {
+      match message {
+        Message::Hello { version, .. } if version != PROTOCOL_VERSION => {
+          peers[index].connection.send(&Message::Refused(Refusal::Version));
+        },
+        Message::Hello { seat: wanted, .. } => {
+          // The host has the first seat, and a returning player gets theirs back if it is still free
+          let taken: Vec<_> = peers.iter().filter_map(|peer| peer.seat).collect();
+          let free = |seat: &usize| *seat > 0 && *seat < players && !taken.contains(seat);
+          let seat = wanted.map(usize::from).filter(free).or_else(|| (1..players).find(free));
+
+          let reply = match seat {
+            Some(seat) => {
+              info!("Seated Player {}", seat + 1);
+              peers[index].seat = Some(seat);
+              welcome(seat, *level, *seed, log)
+            },
+            None => Message::Refused(Refusal::Full),
+          };
+          peers[index].connection.send(&reply);
+        },
+        Message::Command(sequenced) if peers[index].seat == Some(sequenced.seat as usize) => {
+          incoming.push_back(sequenced);
+        },
+        Message::Desync { .. } => {
+          if let Some(seat) = peers[index].seat {
+            peers[index].connection.send(&welcome(seat, *level, *seed, log));
+          }
+        },
+        _ => {},
+      }
+    }
+  }
+  for index in lost.into_iter().rev() {
+    peers.remove(index);
+  }
+
+  let seated = peers.iter().filter(|peer| peer.seat.is_some()).count();
+  *status = format!("Hosting, {} of {players} players here", seated + 1);
+}
+
+/** Keeps in touch with the host. Returns the level and seed to start when the host says so */
+fn client_io(session: &mut NetSession, delta: f32) -> Option<(u16, u64)> {
+  let NetSession { link: Link::Client { address, connection, retry_in, given_up }, seat, incoming, status, .. } = session else {
+    return None;
+  };
+
+  if connection.is_none() && !*given_up {
+    *retry_in -= delta;
+    if *retry_in <= 0. {
+      *retry_in = RECONNECT_DELAY;
+      let stream = address
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addresses| addresses.next())
+        .and_then(|resolved| TcpStream::connect_timeout(&resolved, CONNECT_TIMEOUT).ok())
+        .and_then(|stream| Connection::new(stream).ok());
+      if let Some(mut stream) = stream {
+        stream.send(&Message::Hello { version: PROTOCOL_VERSION, seat: seat.map(|seat| seat as u8) });
+        *connection = Some(stream);
+      }
+    }
+  }
+
+  let received = match connection.as_mut().map(Connection::pump) {
+    Some(Ok(received)) => received,
+    Some(Err(error)) => {
+      warn!("Lost the host: {error}");
+      *connection = None;
+      vec![]
+    },
+    None => vec![],
+  };
+
+  if !*given_up {
+    *status = match (connection.is_some(), *seat) {
+      (true, Some(seat)) => format!("Playing as Player {}", seat + 1),
+      (true, None) => format!("Joining {address}"),
+      (false, _) => format!("Reconnecting to {address}"),
+    };
+  }
+
+  let mut restart = None;
+  for message in received {
+    match message {
+      Message::Welcome { seat: given, level, seed, log } => {
+        *seat = Some(given as usize);
+        *incoming = log.into();
+        restart = Some((level, seed));
+      },
+      Message::Start { level, seed } => {
+        incoming.clear();
+        restart = Some((level, seed));
+      },
+      // The host doesn't send our own commands back
+      Message::Command(sequenced) => incoming.push_back(sequenced),
+      Message::Refused(refusal) => {
+        *connection = None;
+        *given_up = true;
+        *status = match refusal {
+          Refusal::Full => "The game is full".to_string(),
+          Refusal::Version => "The host runs a different version".to_string(),
+        };
+      },
+      _ => {},
+    }
+  }
+
+  restart
+}
+
+fn start_network_level(
+  mut level_started_evr: EventReader<LevelStarted>,
+  mut session: ResMut<NetSession>,
+) {
+  for event in level_started_evr.read() {
+    session.level = event.level;
+    session.seed = event.seed;
+    session.restarting = false;
+
+    if session.is_host() {
+      session.log.clear();
+      session.incoming.clear();
+      session.settling = None;
+      session.broadcast(&Message::Start { level: event.level as u16, seed: event.seed }, None);
+    }
+  }
+}
+
+/** Turns the placements and passes made on this instance into commands for everyone else */
+fn capture_local_commands(
+  mut session: ResMut<NetSession>,
+  mut placement_evr: EventReader<DominoPlacementEvent>,
+  action_state: Res<ActionState<DominoControlAction>>,
+  turns: Res<Turns>,
+  game_state: Res<State<GameState>>,
+  slots: Query<&TraySlot>,
+) {
+  let seat = turns.current as u8;
+
+  for event in placement_evr.read() {
+    if session.applying_place.take_if(|entity| *entity == event.domino).is_some() {
+      continue;
+    }
+    let Ok(slot) = slots.get(event.domino) else { continue; };
+    let Ok(slot) = u32::try_from(slot.0) else {
+      warn!("Tray slot of {:?} is too large to send", event.domino);
+      continue;
+    };
+
+    let command = PlayerCommand::Place { slot, area: event.placement, d_type: event.d_type };
+    session.settling = Some((Sequenced { seq: 0, seat, command, hash: 0 }, true));
+  }
+
+  if action_state.just_pressed(&DominoControlAction::Pass) && *game_state.get() == GameState::PlayerTurn {
+    if session.applying_pass {
+      session.applying_pass = false;
+    } else {
+      session.settling = Some((Sequenced { seq: 0, seat, command: PlayerCommand::Pass, hash: 0 }, true));
+    }
+  }
+}
+
+#[derive(Component)]
+pub struct NetStatusText;
+
+fn spawn_net_status_text(
+  mut commands: Commands,
+) {
+  commands.spawn((
+    Node {
+      position_type: PositionType::Absolute,
+      left: Val::Percent(40.),
+      bottom: Val::Px(28.),
+      ..default()
+    },
+    NetStatusText,
+    Text::default(),
+  ));
+}
+
+fn update_net_status_text(
+  session: Res<NetSession>,
+  mut texts: Query<&mut Text, With<NetStatusText>>,
+) {
+  for mut text in texts.iter_mut() {
+    if text.0 != session.status {
+      text.0 = session.status.clone();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn board_with(area: PlacementArea, d_type: DominoType) -> Board {
+    let mut board = Board::new(6, 6);
+    board.place(area, d_type);
+    board
+  }
+
+  #[test]
+  fn state_hash_tells_states_apart() {
+    let area = PlacementArea::Horizontal { left_cell_col: 1, row: 2 };
+    let d_type = DominoType::Blue(DominoColor::Red);
+    let turns = Turns::new(2, &[]);
+    let pile = DrawPile::default();
+    let hash = state_hash(&board_with(area, d_type), &turns, &pile);
+
+    assert_eq!(hash, state_hash(&board_with(area, d_type), &Turns::new(2, &[]), &DrawPile::default()));
+
+    let moved = PlacementArea::Vertical { bottom_cell_row: 2, column: 1 };
+    assert_ne!(hash, state_hash(&board_with(moved, d_type), &turns, &pile));
+    assert_ne!(hash, state_hash(&board_with(area, d_type.flipped()), &turns, &pile));
+    assert_ne!(hash, state_hash(&Board::new(6, 6), &turns, &pile));
+
+    let mut next_turn = Turns::new(2, &[]);
+    next_turn.current = 1;
+    assert_ne!(hash, state_hash(&board_with(area, d_type), &next_turn, &pile));
+
+    let mut scored = Turns::new(2, &[]);
+    scored.players[0].score = 3;
+    assert_ne!(hash, state_hash(&board_with(area, d_type), &scored, &pile));
+  }
+}
@@ -0,0 +1,346 @@
+use super::super::components::*;
+
+/** Bumped whenever the messages change, so old and new builds refuse each other instead of misreading */
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/** Frames longer than this are refused, so a broken peer can't make us buffer forever */
+const MAX_FRAME: usize = 1024 * 1024;
+
+/**
+ Something a player did on their turn, which every instance applies in the
+ same order. Turning a domino isn't a command of its own, as the area says
+ which way it lies and `d_type` which way round it reads. There is no undo:
+ a placement ends the turn.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlayerCommand {
+  /** The domino in this tray slot went into `area`, reading as `d_type`. Its orientation comes with the area */
+  Place { slot: u32, area: PlacementArea, d_type: DominoType },
+  Pass,
+}
+
+/** A command in its place in the game */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sequenced {
+  pub seq: u32,
+  pub seat: u8,
+  pub command: PlayerCommand,
+  /** The sender's `state_hash` once the command had been applied */
+  pub hash: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Refusal {
+  /** Every seat is taken */
+  Full,
+  /** The two builds don't speak the same protocol */
+  Version,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+  /** A client asks to join, or to take its old seat back after losing the connection */
+  Hello { version: u8, seat: Option<u8> },
+  /** The host seats a client, with everything needed to catch up: the level, its seed and the game so far */
+  Welcome { seat: u8, level: u16, seed: u64, log: Vec<Sequenced> },
+  Refused(Refusal),
+  /** The host has started a (new) level */
+  Start { level: u16, seed: u64 },
+  Command(Sequenced),
+  /** A client's state no longer matches after this command, and it needs the game sent again */
+  Desync { seq: u32 },
+}
+
+const HELLO: u8 = 1;
+const WELCOME: u8 = 2;
+const REFUSED: u8 = 3;
+const START: u8 = 4;
+const COMMAND: u8 = 5;
+const DESYNC: u8 = 6;
+
+const PLACE: u8 = 1;
+const PASS: u8 = 2;
+
+/** No seat asked for, in `Hello` */
+const ANY_SEAT: u8 = u8::MAX;
+
+/** The message as a frame: its length, then the message itself. Fails for a placement too far off the board to send */
+pub fn encode(message: &Message) -> Result<Vec<u8>, String> {
+  let mut body = vec![];
+  match message {
+    Message::Hello { version, seat } => {
+      body.push(HELLO);
+      body.push(*version);
+      body.push(seat.unwrap_or(ANY_SEAT));
+    },
+    Message::Welcome { seat, level, seed, log } => {
+      body.push(WELCOME);
+      body.push(*seat);
+      body.extend(level.to_le_bytes());
+      body.extend(seed.to_le_bytes());
+      body.extend((log.len() as u32).to_le_bytes());
+      for sequenced in log {
+        put_sequenced(&mut body, sequenced)?;
+      }
+    },
+    Message::Refused(refusal) => {
+      body.push(REFUSED);
+      body.push(match refusal {
+        Refusal::Full => 0,
+        Refusal::Version => 1,
+      });
+    },
+    Message::Start { level, seed } => {
+      body.push(START);
+      body.extend(level.to_le_bytes());
+      body.extend(seed.to_le_bytes());
+    },
+    Message::Command(sequenced) => {
+      body.push(COMMAND);
+      put_sequenced(&mut body, sequenced)?;
+    },
+    Message::Desync { seq } => {
+      body.push(DESYNC);
+      body.extend(seq.to_le_bytes());
+    },
+  }
+
+  let mut frame = (body.len() as u32).to_le_bytes().to_vec();
+  frame.extend(body);
+  Ok(frame)
+}
+
+/**
+ Takes every whole frame off the front of the buffer, leaving any partial
+ one for when the rest arrives. An error means the peer can't be understood
+ and should be dropped.
+ */
+pub fn decode_frames(buffer: &mut Vec<u8>) -> Result<Vec<Message>, String> {
+  let mut messages = vec![];
+  let mut start = 0;
+
+  while let Some(header) = buffer.get(start..start + 4) {
+    let length = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    if length > MAX_FRAME {
+      return Err(format!("frame of {length} bytes is too long"));
+    }
+    let Some(body) = buffer.get(start + 4..start + 4 + length) else { break; };
+
+    let message = decode(body).ok_or("malformed message")?;
+    messages.push(message);
+    start += 4 + length;
+  }
+
+  buffer.drain(..start);
+  Ok(messages)
+}
+
+fn decode(body: &[u8]) -> Option<Message> {
+  let mut reader = Reader { bytes: body };
+  let message = match reader.u8()? {
+    HELLO => {
+      let version = reader.u8()?;
+      let seat = Some(reader.u8()?).filter(|seat| *seat != ANY_SEAT);
+      Message::Hello { version, seat }
+    },
+    WELCOME => {
+      let seat = reader.u8()?;
+      let level = reader.u16()?;
+      let seed = reader.u64()?;
+      let count = reader.u32()? as usize;
+      let log = (0..count).map(|_| reader.sequenced()).collect::<Option<_>>()?;
+      Message::Welcome { seat, level, seed, log }
+    },
+    REFUSED => Message::Refused(match reader.u8()? {
+      0 => Refusal::Full,
+      1 => Refusal::Version,
+      _ => return None,
+    }),
+    START => Message::Start { level: reader.u16()?, seed: reader.u64()? },
+    COMMAND => Message::Command(reader.sequenced()?),
+    DESYNC => Message::Desync { seq: reader.u32()? },
+    _ => return None,
+  };
+
+  reader.bytes.is_empty().then_some(message)
+}
+
+fn put_sequenced(body: &mut Vec<u8>, sequenced: &Sequenced) -> Result<(), String> {
+  body.extend(sequenced.seq.to_le_bytes());
+  body.push(sequenced.seat);
+  match sequenced.command {
+    PlayerCommand::Place { slot, area, d_type } => {
+      body.push(PLACE);
+      body.extend(slot.to_le_bytes());
+      let (horizontal, x, y) = match area {
+        PlacementArea::Horizontal { left_cell_col, row } => (1, left_cell_col, row),
+        PlacementArea::Vertical { bottom_cell_row, column } => (0, column, bottom_cell_row),
+      };
+      let out_of_range = |_| format!("cell ({x}, {y}) is too far off the board to send");
+      let (x, y) = (i16::try_from(x).map_err(out_of_range)?, i16::try_from(y).map_err(out_of_range)?);
+      body.push(horizontal);
+      body.extend(x.to_le_bytes());
+      body.extend(y.to_le_bytes());
+      // Both colors fit in one byte
+      let (first, second) = d_type.colors();
+      body.push((first.index() * DominoColor::ALL.len() + second.index()) as u8);
+    },
+    PlayerCommand::Pass => body.push(PASS),
+  }
+  body.extend(sequenced.hash.to_le_bytes());
+  Ok(())
+}
+
+struct Reader<'a> {
+  bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+  fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+    let (taken, rest) = self.bytes.split_first_chunk::<N>()?;
+    self.bytes = rest;
+    Some(*taken)
+  }
+
+  fn u8(&mut self) -> Option<u8> {
+    self.take::<1>().map(|[byte]| byte)
+  }
+
+  fn u16(&mut self) -> Option<u16> {
+    self.take().map(u16::from_le_bytes)
+  }
+
+  fn i16(&mut self) -> Option<i16> {
+    self.take().map(i16::from_le_bytes)
+  }
+
+  fn u32(&mut self) -> Option<u32> {
+    self.take().map(u32::from_le_bytes)
+  }
+
+  fn u64(&mut self) -> Option<u64> {
+    self.take().map(u64::from_le_bytes)
+  }
+
+  fn sequenced(&mut self) -> Option<Sequenced> {
+    let seq = self.u32()?;
+    let seat = self.u8()?;
+    let command = match self.u8()? {
+      PLACE => {
+        let slot = self.u32()?;
+        let horizontal = self.u8()? == 1;
+        let (x, y) = (self.i16()? as i32, self.i16()? as i32);
+        let area = if horizontal {
+          PlacementArea::Horizontal { left_cell_col: x, row: y }
+        } else {
+          PlacementArea::Vertical { bottom_cell_row: y, column: x }
+        };
+        let colors = self.u8()? as usize;
+        let first = *DominoColor::ALL.get(colors / DominoColor::ALL.len())?;
+        let second = DominoColor::ALL[colors % DominoColor::ALL.len()];
+        PlayerCommand::Place { slot, area, d_type: DominoType::new(first, second) }
+      },
+      PASS => PlayerCommand::Pass,
+      _ => return None,
+    };
+    let hash = self.u64()?;
+    Some(Sequenced { seq, seat, command, hash })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn place(slot: u32, area: PlacementArea, d_type: DominoType) -> Sequenced {
+    Sequenced { seq: slot.wrapping_mul(7), seat: 1, command: PlayerCommand::Place { slot, area, d_type }, hash: 0xdead_beef_0123_4567 }
+  }
+
+  /** Every kind of command: both orientations, every domino, and cells off either edge */
+  fn commands() -> Vec<Sequenced> {
+    let mut commands: Vec<_> = DominoType::ALL_TYPES
+      .into_iter()
+      .enumerate()
+      .flat_map(|(index, d_type)| {
+        let index = index as i32;
+        [
+          place(index as u32, PlacementArea::Horizontal { left_cell_col: index, row: -index }, d_type),
+          place(u32::MAX - index as u32, PlacementArea::Vertical { bottom_cell_row: 300 - index, column: -1 }, d_type),
+        ]
+      })
+      .collect();
+    commands.push(Sequenced { seq: u32::MAX, seat: 3, command: PlayerCommand::Pass, hash: u64::MAX });
+    commands
+  }
+
+  fn messages() -> Vec<Message> {
+    let mut messages = vec![
+      Message::Hello { version: PROTOCOL_VERSION, seat: None },
+      Message::Hello { version: 0, seat: Some(2) },
+      Message::Welcome { seat: 1, level: 3, seed: 42, log: vec![] },
+      Message::Welcome { seat: 0, level: u16::MAX, seed: u64::MAX, log: commands() },
+      Message::Refused(Refusal::Full),
+      Message::Refused(Refusal::Version),
+      Message::Start { level: 7, seed: 1234567890123 },
+      Message::Desync { seq: 99 },
+    ];
+    messages.extend(commands().into_iter().map(Message::Command));
+    messages
+  }
+
+  #[test]
+  fn every_message_round_trips() {
+    for message in messages() {
+      let mut buffer = encode(&message).unwrap();
+      assert_eq!(decode_frames(&mut buffer), Ok(vec![message]));
+      assert!(buffer.is_empty());
+    }
+  }
+
+  #[test]
+  fn partial_frames_wait_for_the_rest() {
+    let sent = messages();
+    let stream: Vec<u8> = sent.iter().flat_map(|message| encode(message).unwrap()).collect();
+
+    // Arriving a few bytes at a time, splitting frames anywhere
+    let mut buffer = vec![];
+    let mut received = vec![];
+    for chunk in stream.chunks(5) {
+      buffer.extend_from_slice(chunk);
+      received.extend(decode_frames(&mut buffer).unwrap());
+    }
+    assert_eq!(received, sent);
+    assert!(buffer.is_empty());
+  }
+
+  #[test]
+  fn refuses_what_it_cannot_read() {
+    let mut unknown = vec![1, 0, 0, 0, 0xff];
+    assert!(decode_frames(&mut unknown).is_err());
+
+    let mut trailing = encode(&Message::Desync { seq: 1 }).unwrap();
+    trailing[0] += 1;
+    trailing.push(0);
+    assert!(decode_frames(&mut trailing).is_err());
+
+    let mut huge = ((MAX_FRAME + 1) as u32).to_le_bytes().to_vec();
+    assert!(decode_frames(&mut huge).is_err());
+  }
+
+  #[test]
+  fn refuses_to_send_cells_out_of_range() {
+    let d_type = DominoType::ALL_TYPES[0];
+    for area in [
+      PlacementArea::Horizontal { left_cell_col: i16::MAX as i32 + 1, row: 0 },
+      PlacementArea::Vertical { bottom_cell_row: i16::MIN as i32 - 1, column: 0 },
+    ] {
+      let command = Message::Command(place(0, area, d_type));
+      assert!(encode(&command).is_err());
+      assert!(encode(&Message::Welcome { seat: 0, level: 0, seed: 0, log: vec![place(0, area, d_type)] }).is_err());
+    }
+
+    let edge = PlacementArea::Horizontal { left_cell_col: i16::MIN as i32, row: i16::MAX as i32 };
+    let mut buffer = encode(&Message::Command(place(0, edge, d_type))).unwrap();
+    assert_eq!(decode_frames(&mut buffer), Ok(vec![Message::Command(place(0, edge, d_type))]));
+  }
+}
@@ -0,0 +1,183 @@
+use crate::prelude::*;
+
+use grid::TILE_SIZE;
+
+use super::board::*;
+use super::components::*;
+use super::rules::*;
+
+/** How forgiving drops are */
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct PlacementSettings {
+  /**
+    How far (in pixels) from the domino's center a legal area may be and
+    still be snapped to. Zero only accepts the area right under it.
+   */
+  pub snap_radius: f32,
+  /**
+    Whether a domino dropped in the wrong orientation may be turned to fit
+    a legal area of the other orientation.
+   */
+  pub auto_rotate: bool,
+}
+
+impl Default for PlacementSettings {
+  fn default() -> Self {
+    Self {
+      snap_radius: TILE_SIZE,
+      auto_rotate: true,
+    }
+  }
+}
+
+/** Where a drop resolved to, and which way round the domino ends up */
+#[derive(Clone, Copy, Debug)]
+pub struct DropTarget {
+  pub area: PlacementArea,
+  /** The domino as read in `area`. Differs from the dragged one when it had to be turned */
+  pub d_type: DominoType,
+}
+
+/**
+ Works out where a dragged domino would land if it were let go at `point`.
+
+ The area right under the point wins if it is legal, otherwise the closest
+ legal area within the snap radius is used. Failing that, and if allowed,
+ the other orientation is tried (turning the domino either way).
+
+ Areas are found from their grid coordinates rather than by searching the
+ board, so the cost only depends on the snap radius.
+ */
+pub fn resolve_drop(
+  board: &Board,
+  rules: &dyn RuleSet,
+  settings: &PlacementSettings,
+  point: Vec2,
+  d_type: DominoType,
+  horizontal: bool,
+) -> Result<DropTarget, PlacementRejection> {
+  let _span = info_span!("resolve_drop", ?point, horizontal, rules = rules.name()).entered();
+  let hovered = PlacementArea::at_point(point, horizontal);
+  let mut rejection = if board.contains(&hovered) {
+    match rules.validate(board, &hovered, d_type) {
+      Ok(()) => return Ok(DropTarget { area: hovered, d_type }),
+      Err(reason) => reason,
+    }
+  } else {
+    PlacementRejection::NoPlacementArea
+  };
+
+  if let Some(target) = nearest_legal(board, rules, settings.snap_radius, point, &[d_type], horizontal) {
+    return Ok(target);
+  }
+
+  if settings.auto_rotate {
+    // A quarter turn either way keeps or swaps which half ends up first
+    let turned = [d_type, d_type.flipped()];
+    if let Some(target) = nearest_legal(board, rules, settings.snap_radius, point, &turned, !horizontal) {
+      return Ok(target);
+    }
+
+    let turned_hovered = PlacementArea::at_point(point, !horizontal);
+    if rejection == PlacementRejection::NoPlacementArea && board.contains(&turned_hovered) {
+      if let Err(reason) = rules.validate(board, &turned_hovered, d_type) {
+        rejection = reason;
+      }
+    }
+  }
+
+  Err(rejection)
+}
+
+/** The closest legal area of one orientation within `radius` of the point */
+fn nearest_legal(
+  board: &Board,
+  rules: &dyn RuleSet,
+  radius: f32,
+  point: Vec2,
+  d_types: &[DominoType],
+  horizontal: bool,
+) -> Option<DropTarget> {
+  if radius <= 0. { return None; }
+
+  // Any area whose center is in range starts within a tile of the radius
+  let min = ((point - radius) / TILE_SIZE).floor().as_ivec2() - IVec2::ONE;
+  let max = ((point + radius) / TILE_SIZE).ceil().as_ivec2() + IVec2::ONE;
+
+  let mut best: Option<(f32, DropTarget)> = None;
+  for x in min.x..=max.x {
+    for y in min.y..=max.y {
+      let area = if horizontal {
+        PlacementArea::Horizontal { left_cell_col: x, row: y }
+      } else {
+        PlacementArea::Vertical { bottom_cell_row: y, column: x }
+      };
+
+      let distance = area.center().distance(point);
+      if distance > radius || !board.contains(&area) { continue; }
+      if best.is_some_and(|(best_distance, _)| best_distance <= distance) { continue; }
+
+      for d_type in d_types {
+        if rules.validate(board, &area, *d_type).is_ok() {
+          best = Some((distance, DropTarget { area, d_type: *d_type }));
+          break;
+        }
+      }
+    }
+  }
+
+  best.map(|(_, target)| target)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use super::super::rules::free_form::FreeForm;
+
+  const BLUE_RED: DominoType = DominoType::Blue(DominoColor::Red);
+
+  fn horizontal(left_cell_col: i32, row: i32) -> PlacementArea {
+    PlacementArea::Horizontal { left_cell_col, row }
+  }
+
+  fn settings(snap_radius: f32, auto_rotate: bool) -> PlacementSettings {
+    PlacementSettings { snap_radius, auto_rotate }
+  }
+
+  #[test]
+  fn takes_the_area_under_the_drop() {
+    let board = Board::new(5, 5);
+    let area = horizontal(1, 2);
+
+    let target = resolve_drop(&board, &FreeForm, &PlacementSettings::default(), area.center(), BLUE_RED, true).unwrap();
+    assert_eq!((target.area, target.d_type), (area, BLUE_RED));
+  }
+
+  #[test]
+  fn snaps_to_the_nearest_free_area_within_the_radius() {
+    let mut board = Board::new(5, 5);
+    board.place(horizontal(1, 2), BLUE_RED);
+    let point = horizontal(1, 2).center() + Vec2::new(0., TILE_SIZE * 0.3);
+
+    let target = resolve_drop(&board, &FreeForm, &settings(TILE_SIZE, false), point, BLUE_RED, true).unwrap();
+    assert_eq!(target.area, horizontal(1, 3));
+
+    let exact = resolve_drop(&board, &FreeForm, &settings(0., false), point, BLUE_RED, true);
+    assert_eq!(exact.unwrap_err(), PlacementRejection::Occupied);
+  }
+
+  #[test]
+  fn turns_the_domino_when_only_the_other_orientation_fits() {
+    // One column wide, so nothing horizontal fits anywhere
+    let board = Board::new(1, 4);
+    let point = Vec2::new(0., TILE_SIZE * 1.5);
+
+    let target = resolve_drop(&board, &FreeForm, &settings(TILE_SIZE, true), point, BLUE_RED, true).unwrap();
+    assert_eq!(target.area, PlacementArea::Vertical { bottom_cell_row: 1, column: 0 });
+    assert!(!target.area.is_horizontal());
+
+    let unturned = resolve_drop(&board, &FreeForm, &settings(TILE_SIZE, false), point, BLUE_RED, true);
+    assert_eq!(unturned.unwrap_err(), PlacementRejection::NoPlacementArea);
+  }
+}
@@ -0,0 +1,139 @@
+use leafwing_input_manager::common_conditions::action_just_pressed;
+
+use crate::prelude::*;
+use crate::systems::DominoControlAction;
+use crate::systems::LoadedAssets;
+
+use super::board::*;
+use super::components::*;
+use super::placement::*;
+use super::rules::*;
+
+use bevy::color::palettes::css::LIME;
+use dragging::IsDragging;
+use grid::TILE_SIZE;
+
+const LEGAL_TINT: Color = Color::srgba(0.5, 1., 0.5, 0.6);
+const ILLEGAL_TINT: Color = Color::srgba(1., 0.4, 0.4, 0.6);
+
+pub struct PlacementPreviewPlugin;
+
+impl Plugin for PlacementPreviewPlugin {
+  fn build(&self, app: &mut App) {
+    app
+      .init_resource::<PreviewSettings>()
+      .add_systems(Update, (
+        toggle_legal_spot_highlights
+          .run_if(action_just_pressed(DominoControlAction::ToggleHighlights)),
+        update_drop_preview,
+        highlight_legal_spots.run_if(|settings: Res<PreviewSettings>| settings.highlight_legal_spots),
+      ))
+      ;
+  }
+}
+
+/** What to show while a domino is being dragged */
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct PreviewSettings {
+  /** Show a see-through copy of the domino where it would land */
+  pub show_ghost: bool,
+  /** Outline every area the dragged domino could legally go in, as it is currently turned */
+  pub highlight_legal_spots: bool,
+}
+
+impl Default for PreviewSettings {
+  fn default() -> Self {
+    Self {
+      show_ghost: true,
+      highlight_legal_spots: false,
+    }
+  }
+}
+
+/** The see-through domino showing where a drop would land */
+#[derive(Component)]
+pub struct DropPreview;
+
+fn toggle_legal_spot_highlights(
+  mut settings: ResMut<PreviewSettings>,
+) {
+  settings.highlight_legal_spots = !settings.highlight_legal_spots;
+}
+
+/**
+ Moves the ghost to wherever the dragged domino would end up, tinted green.
+ If it can't be placed the ghost sits in the area under it, tinted red.
+ */
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn update_drop_preview(
+  mut commands: Commands,
+  loaded_assets: Res<LoadedAssets>,
+  board: Res<Board>,
+  rules: Res<ActiveRuleSet>,
+  placement_settings: Res<PlacementSettings>,
+  settings: Res<PreviewSettings>,
+  dragging: Query<(&Transform, &Domino), With<IsDragging>>,
+  mut previews: Query<(Entity, &mut Sprite, &mut Transform, &mut Visibility), (With<DropPreview>, Without<Domino>)>,
+) {
+  let Some((dragged_transform, domino)) = dragging.iter().next().filter(|_| settings.show_ghost) else {
+    for (entity, ..) in previews.iter() {
+      commands.entity(entity).despawn();
+    }
+    return;
+  };
+
+  let point = dragged_transform.translation.truncate();
+  let (area, d_type, tint) = match resolve_drop(
+    &board,
+    rules.0.as_ref(),
+    &placement_settings,
+    point,
+    domino.d_type,
+    domino.horizontal,
+  ) {
+    Ok(target) => (target.area, target.d_type, LEGAL_TINT),
+    Err(_) => (PlacementArea::at_point(point, domino.horizontal), domino.d_type, ILLEGAL_TINT),
+  };
+
+  let mut sprite = loaded_assets.images.dominoes.get_domino(&d_type);
+  sprite.color = tint;
+
+  let preview = Domino { d_type, horizontal: area.is_horizontal() };
+  // Just under the dominoes, but above the grid
+  let transform = Transform::from_translation(area.center().extend(0.9))
+    .with_rotation(preview.rotation());
+  let visibility = if board.contains(&area) { Visibility::Inherited } else { Visibility::Hidden };
+
+  if let Ok((_, mut preview_sprite, mut preview_transform, mut preview_visibility)) = previews.get_single_mut() {
+    *preview_sprite = sprite;
+    *preview_transform = transform;
+    *preview_visibility = visibility;
+  } else {
+    commands.spawn((DropPreview, sprite, transform, visibility));
+  }
+}
+
+/** Outlines every legal area for the dragged domino, without turning it */
+fn highlight_legal_spots(
+  mut gizmos: Gizmos,
+  board: Res<Board>,
+  rules: Res<ActiveRuleSet>,
+  dragging: Query<&Domino, With<IsDragging>>,
+) {
+  for domino in dragging.iter() {
+    let legal_areas = board
+      .free_areas()
+      .filter(|area| area.is_horizontal() == domino.horizontal)
+      .filter(|area| rules.0.validate(&board, area, domino.d_type).is_ok());
+
+    for area in legal_areas {
+      let size = if domino.horizontal {
+        Vec2::new(TILE_SIZE * 2., TILE_SIZE)
+      } else {
+        Vec2::new(TILE_SIZE, TILE_SIZE * 2.)
+      };
+
+      gizmos.rect_2d(Isometry2d::from_translation(area.center()), size - 4., LIME);
+    }
+  }
+}
@@ -0,0 +1,564 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::picking::PickingPlugin;
+use leafwing_input_manager::common_conditions::action_just_pressed;
+use leafwing_input_manager::plugin::InputManagerSystem;
+use leafwing_input_manager::prelude::ActionState;
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+use crate::systems::random::GameRng;
+use crate::systems::settings::{settings_dir, write_ron};
+use crate::systems::DominoControlAction;
+
+use super::events::*;
+use super::grid_cursor::HeldByCursor;
+use super::level::Levels;
+use super::systems::handle_drag_end;
+use super::tray::TraySlot;
+
+use dragging::IsDragging;
+
+/** How far the replay back and forward actions jump, in seconds */
+const SCRUB_STEP: f32 = 5.;
+
+/** Actions that change the game, so they are recorded, and ignored from the player during playback */
+const RECORDED_ACTIONS: [DominoControlAction; 5] = [
+  DominoControlAction::Rotate,
+  DominoControlAction::Flip,
+  DominoControlAction::Hold,
+  DominoControlAction::Draw,
+  DominoControlAction::Pass,
+];
+
+/** Actions that would let the player interfere with a replay. The camera and the replay's own controls still work */
+const BLOCKED_DURING_PLAYBACK: [DominoControlAction; 11] = [
+  DominoControlAction::NextLevel,
+  DominoControlAction::Reset,
+  DominoControlAction::SelectNext,
+  DominoControlAction::SelectPrevious,
+  DominoControlAction::CursorUp,
+  DominoControlAction::CursorDown,
+  DominoControlAction::CursorLeft,
+  DominoControlAction::CursorRight,
+  DominoControlAction::Place,
+  DominoControlAction::Cancel,
+  DominoControlAction::SortTray,
+];
+
+/**
+ Records what the player does to the board so the session can be played
+ back exactly, e.g. from a tester's bug report. Playback feeds the recorded
+ inputs back in as the same components, events and actions the live game
+ uses, so it goes through all of the usual systems.
+ */
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+  fn build(&self, app: &mut App) {
+    let playback = replay_path_from_args(std::env::args().skip(1))
+      .and_then(|path| load_replay(&path))
+      .map(Playback::new);
+
+    app
+      .insert_resource(ReplayState { playback, ..default() })
+      .add_systems(Startup, spawn_replay_status_text)
+      .add_systems(PreUpdate, drive_playback
+        .in_set(InputManagerSystem::ManualControl)
+        .run_if(in_state(MenuState::InGame)))
+      .add_systems(Update, (
+        (
+          start_recording.run_if(on_event::<LevelStarted>),
+          record_inputs,
+        ).chain().before(handle_drag_end),
+        save_replay.run_if(action_just_pressed(DominoControlAction::SaveReplay)),
+        toggle_playback.run_if(action_just_pressed(DominoControlAction::PlayReplay)),
+        control_playback,
+        block_pointer_during_playback,
+        update_replay_status_text,
+      ))
+      ;
+  }
+}
+
+/** Something the player did, pointing at dominoes by their tray slot so it means the same thing in every run */
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ReplayInput {
+  /** Started dragging the domino, or picked it up with the grid cursor */
+  PickUp { slot: usize },
+  /** Stopped dragging without trying to drop it */
+  Release { slot: usize },
+  /** Let go of the domino at this point, whether or not it could be placed there */
+  Drop { slot: usize, point: Vec2 },
+  Action(DominoControlAction),
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ReplayEntry {
+  /** Frames since the level started. Entries of the same frame are played back together */
+  pub frame: u32,
+  /** Seconds since the level started */
+  pub time: f32,
+  pub input: ReplayInput,
+}
+
+/** One level's session, from its start */
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Replay {
+  pub level: usize,
+  pub seed: u64,
+  pub entries: Vec<ReplayEntry>,
+}
+
+impl Replay {
+  pub fn duration(&self) -> f32 {
+    self.entries.last().map_or(0., |entry| entry.time)
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaybackPhase {
+  /** The level is about to be set up again from the replay's seed */
+  Restarting,
+  WaitingForLevel,
+  Running,
+}
+
+#[derive(Debug)]
+pub struct Playback {
+  pub replay: Replay,
+  pub phase: PlaybackPhase,
+  /** The next entry to play */
+  pub next: usize,
+  pub clock: f32,
+  pub paused: bool,
+  /** Play the next frame's entries even though paused */
+  pub step: bool,
+  /** Play entries as fast as possible until this time is reached */
+  pub seek_to: Option<f32>,
+}
+
+impl Playback {
+  pub fn new(replay: Replay) -> Self {
+    Self {
+      replay,
+      phase: PlaybackPhase::Restarting,
+      next: 0,
+      clock: 0.,
+      paused: false,
+      step: false,
+      seek_to: None,
+    }
+  }
+
+  /** Goes to a time in the replay. Going back means playing it again from the start */
+  pub fn seek(&mut self, time: f32) {
+    let time = time.clamp(0., self.replay.duration());
+    if time < self.clock {
+      self.phase = PlaybackPhase::Restarting;
+    }
+    self.seek_to = Some(time);
+  }
+}
+
+#[derive(Resource, Default)]
+pub struct ReplayState {
+  /** What has happened since the level started. Playback is recorded too */
+  pub recording: Replay,
+  pub frame: u32,
+  pub started_at: f32,
+  pub playback: Option<Playback>,
+}
+
+#[derive(Component)]
+pub struct ReplayStatusText;
+
+fn replay_path_from_args(args: impl IntoIterator<Item = String>) -> Option<PathBuf> {
+  let mut args = args.into_iter();
+  args.find(|arg| arg == "--replay")?;
+  args.next().map(PathBuf::from)
+}
+
+pub fn load_replay(path: &Path) -> Option<Replay> {
+  let contents = fs::read_to_string(path)
+    .map_err(|error| warn!("Could not read replay {}: {error}", path.display()))
+    .ok()?;
+  ron::from_str(&contents)
+    .map_err(|error| warn!("Could not parse replay {}: {error}", path.display()))
+    .ok()
+}
+
+fn start_recording(
+  mut level_started_evr: EventReader<LevelStarted>,
+  mut state: ResMut<ReplayState>,
+  time: Res<Time>,
+) {
+  for event in level_started_evr.read() {
+    state.recording = Replay { level: event.level, seed: event.seed, entries: vec![] };
+    state.frame = 0;
+    state.started_at = time.elapsed_secs();
+
+    if let Some(playback) = state.playback.as_mut() {
+      if playback.phase == PlaybackPhase::WaitingForLevel {
+        playback.phase = PlaybackPhase::Running;
+        playback.next = 0;
+        playback.clock = 0.;
+      }
+    }
+  }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn record_inputs(
+  mut state: ResMut<ReplayState>,
+  mut dropped: Local<HashSet<Entity>>,
+  time: Res<Time>,
+  action_state: Res<ActionState<DominoControlAction>>,
+  mut drag_end_evr: EventReader<DragEndEvent>,
+  mut released: RemovedComponents<IsDragging>,
+  picked_up: Query<&TraySlot, Added<IsDragging>>,
+  dominoes: Query<(&TraySlot, &Transform)>,
+) {
+  let frame = state.frame;
+  let now = time.elapsed_secs() - state.started_at;
+  let mut inputs = vec![];
+
+  for slot in picked_up.iter() {
+    inputs.push(ReplayInput::PickUp { slot: slot.0 });
+  }
+
+  for action in RECORDED_ACTIONS {
+    if action_state.just_pressed(&action) {
+      inputs.push(ReplayInput::Action(action));
+    }
+  }
+
+  for event in drag_end_evr.read() {
+    if let Ok((slot, transform)) = dominoes.get(event.draggable) {
+      inputs.push(ReplayInput::Drop { slot: slot.0, point: transform.translation.truncate() });
+      dropped.insert(event.draggable);
+    }
+  }
+
+  // Dropping takes IsDragging away as well, which is already covered
+  for entity in released.read() {
+    if !dropped.remove(&entity) {
+      if let Ok((slot, _)) = dominoes.get(entity) {
+        inputs.push(ReplayInput::Release { slot: slot.0 });
+      }
+    }
+  }
+
+  let entries = inputs.into_iter().map(|input| ReplayEntry { frame, time: now, input });
+  state.recording.entries.extend(entries);
+  state.frame += 1;
+}
+
+fn save_replay(
+  state: Res<ReplayState>,
+) {
+  let Some(dir) = settings_dir() else {
+    warn!("Nowhere to save replays to");
+    return;
+  };
+
+  let stamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+  let path = dir.join("replays").join(format!("replay-{stamp}.ron"));
+  match write_ron(&path, &state.recording) {
+    Ok(()) => info!("Saved replay to {}, play it with --replay {}", path.display(), path.display()),
+    Err(error) => warn!("Could not save replay to {}: {error}", path.display()),
+  }
+}
+
+/** Plays back what has been recorded since the level started, or stops a replay that is playing */
+fn toggle_playback(
+  mut state: ResMut<ReplayState>,
+) {
+  state.playback = match state.playback.take() {
+    Some(_) => None,
+    None => Some(Playback::new(state.recording.clone())),
+  };
+}
+
+fn control_playback(
+  mut state: ResMut<ReplayState>,
+  action_state: Res<ActionState<DominoControlAction>>,
+) {
+  let Some(playback) = state.playback.as_mut() else { return; };
+
+  if action_state.just_pressed(&DominoControlAction::ReplayPause) {
+    playback.paused = !playback.paused;
+  }
+  if action_state.just_pressed(&DominoControlAction::ReplayStep) {
+    playback.paused = true;
+    playback.step = true;
+  }
+  if action_state.just_pressed(&DominoControlAction::ReplayBack) {
+    playback.seek(playback.clock - SCRUB_STEP);
+  }
+  if action_state.just_pressed(&DominoControlAction::ReplayForward) {
+    playback.seek(playback.clock + SCRUB_STEP);
+  }
+}
+
+/** Feeds the replay back in, before the game's systems see this frame's input */
+#[allow(clippy::too_many_arguments)]
+fn drive_playback(
+  mut commands: Commands,
+  mut state: ResMut<ReplayState>,
+  mut action_state: ResMut<ActionState<DominoControlAction>>,
+  mut levels: ResMut<Levels>,
+  mut game_rng: ResMut<GameRng>,
+  mut drag_end_evw: EventWriter<DragEndEvent>,
+  time: Res<Time>,
+  mut dominoes: Query<(Entity, &TraySlot, &mut Transform)>,
+) {
+  let Some(playback) = state.playback.as_mut() else { return; };
+
+  for action in RECORDED_ACTIONS.iter().chain(&BLOCKED_DURING_PLAYBACK) {
+    action_state.release(action);
+  }
+
+  match playback.phase {
+    PlaybackPhase::Restarting => {
+      // Resetting sets the level up again with the replay's seed
+      levels.current = playback.replay.level.min(levels.all.len() - 1);
+      *game_rng = GameRng::new(playback.replay.seed);
+      action_state.press(&DominoControlAction::Reset);
+      playback.phase = PlaybackPhase::WaitingForLevel;
+      return;
+    },
+    PlaybackPhase::WaitingForLevel => return,
+    PlaybackPhase::Running => {},
+  }
+
+  if !playback.paused && playback.seek_to.is_none() {
+    playback.clock += time.delta_secs();
+  }
+
+  let entries = &playback.replay.entries;
+  if let Some(first) = entries.get(playback.next).copied() {
+    let due = playback.step
+      || playback.seek_to.is_some_and(|target| first.time <= target)
+      || (!playback.paused && first.time <= playback.clock);
+
+    if due {
+      playback.step = false;
+      playback.clock = playback.clock.max(first.time);
+
+      while let Some(entry) = entries.get(playback.next).filter(|entry| entry.frame == first.frame) {
+        let domino = |slot: usize| dominoes.iter().find(|(_, tray_slot, _)| tray_slot.0 == slot).map(|(entity, ..)| entity);
+
+        match entry.input {
+          ReplayInput::PickUp { slot } => if let Some(entity) = domino(slot) {
+            commands.entity(entity).insert(IsDragging);
+          },
+          ReplayInput::Release { slot } => if let Some(entity) = domino(slot) {
+            commands.entity(entity).remove::<(IsDragging, HeldByCursor)>();
+          },
+          ReplayInput::Drop { slot, point } => if let Some(entity) = domino(slot) {
+            if let Ok((_, _, mut transform)) = dominoes.get_mut(entity) {
+              transform.translation.x = point.x;
+              transform.translation.y = point.y;
+            }
+            drag_end_evw.send(DragEndEvent { draggable: entity });
+          },
+          ReplayInput::Action(action) => action_state.press(&action),
+        }
+        playback.next += 1;
+      }
+    }
+  }
+
+  let upcoming = entries.get(playback.next).map(|entry| entry.time);
+  if let Some(target) = playback.seek_to {
+    if upcoming.is_none_or(|time| time > target) {
+      playback.clock = target;
+      playback.seek_to = None;
+    }
+  }
+
+  if upcoming.is_none() && playback.seek_to.is_none() && !playback.paused {
+    info!("Replay finished");
+    state.playback = None;
+  }
+}
+
+/** The pointer would fight the replay for the dominoes, so it is ignored while one plays */
+fn block_pointer_during_playback(
+  state: Res<ReplayState>,
+  mut picking: ResMut<PickingPlugin>,
+) {
+  let playing = state.playback.is_some();
+  if picking.is_input_enabled == playing {
+    picking.is_input_enabled = !playing;
+  }
+}
+
+fn spawn_replay_status_text(
+  mut commands: Commands,
+) {
+  commands.spawn((
+    Node {
+      position_type: PositionType::Absolute,
+      left: Val::Percent(40.),
+      top: Val::Px(0.),
+      ..default()
+    },
+    ReplayStatusText,
+    Text::default(),
+  ));
+}
+
+fn update_replay_status_text(
+  state: Res<ReplayState>,
+  mut texts: Query<&mut Text, With<ReplayStatusText>>,
+) {
+  if !state.is_changed() { return; }
+
+  for mut text in texts.iter_mut() {
+    let status = match &state.playback {
+      Some(playback) => format!(
+        "Replay {:.1}s / {:.1}s{}\n[F7] pause [F8] step [F9]/[F10] scrub [F6] stop",
+        playback.clock,
+        playback.replay.duration(),
+        if playback.paused { " (paused)" } else { "" },
+      ),
+      None => "[F5] saves a replay, [F6] plays it back".to_string(),
+    };
+    if text.0 != status {
+      text.0 = status;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::board::Board;
+  use super::super::components::*;
+  use super::super::draw_pile::DrawPile;
+  use super::super::placement::PlacementSettings;
+  use super::super::rules::free_form::FreeForm;
+  use super::super::rules::ActiveRuleSet;
+
+  use super::*;
+
+  /** Sets the board up again when Reset is pressed, with the tray dealt from the seed as the game does */
+  fn restart(
+    mut commands: Commands,
+    mut level_started_evw: EventWriter<LevelStarted>,
+    mut board: ResMut<Board>,
+    levels: Res<Levels>,
+    game_rng: Res<GameRng>,
+    dominoes: Query<Entity, With<Domino>>,
+  ) {
+    for entity in dominoes.iter() {
+      commands.entity(entity).despawn();
+    }
+    *board = Board::new(6, 6);
+
+    let mut pile = DrawPile::new(None, DominoType::ALL_TYPES.to_vec(), &mut game_rng.fork("draw_pile"));
+    for (slot, d_type) in pile.deal_hand().into_iter().enumerate() {
+      commands.spawn((Domino { d_type, horizontal: true }, TraySlot(slot), Transform::default()));
+    }
+    level_started_evw.send(LevelStarted { level: levels.current, seed: game_rng.seed() });
+  }
+
+  fn app(seed: u64) -> App {
+    let mut app = App::new();
+    app
+      .add_plugins(MinimalPlugins)
+      .init_resource::<ActionState<DominoControlAction>>()
+      .init_resource::<ReplayState>()
+      .init_resource::<PlacementSettings>()
+      .insert_resource(ActiveRuleSet(Box::new(FreeForm)))
+      .insert_resource(Board::new(6, 6))
+      .insert_resource(Levels::default())
+      .insert_resource(GameRng::new(seed))
+      .add_event::<DragEndEvent>()
+      .add_event::<DominoPlacementEvent>()
+      .add_event::<PlacementRejected>()
+      .add_event::<LevelStarted>()
+      .add_systems(PreUpdate, drive_playback)
+      .add_systems(Update, (
+        restart.run_if(action_just_pressed(DominoControlAction::Reset)),
+        start_recording.run_if(on_event::<LevelStarted>),
+        record_inputs,
+        handle_drag_end,
+      ).chain());
+    app
+  }
+
+  fn domino_in(app: &mut App, slot: usize) -> Entity {
+    let mut query = app.world_mut().query::<(Entity, &TraySlot)>();
+    query.iter(app.world()).find(|(_, tray_slot)| tray_slot.0 == slot).unwrap().0
+  }
+
+  /** Drags the domino in a tray slot onto the board */
+  fn play(app: &mut App, slot: usize, area: PlacementArea) {
+    let entity = domino_in(app, slot);
+    app.world_mut().entity_mut(entity).insert(IsDragging);
+    app.update();
+
+    let mut transform = app.world_mut().get_mut::<Transform>(entity).unwrap();
+    transform.translation = area.transform().translation;
+    app.world_mut().send_event(DragEndEvent { draggable: entity });
+    app.update();
+  }
+
+  /** Plays a short game, returning what was recorded and the board it left */
+  fn record(seed: u64) -> (Replay, Board) {
+    let mut app = app(seed);
+    app.world_mut().resource_mut::<ActionState<DominoControlAction>>().press(&DominoControlAction::Reset);
+    app.update();
+    app.world_mut().resource_mut::<ActionState<DominoControlAction>>().release(&DominoControlAction::Reset);
+    app.update();
+
+    play(&mut app, 2, PlacementArea::Horizontal { left_cell_col: 0, row: 0 });
+    play(&mut app, 5, PlacementArea::Vertical { bottom_cell_row: 1, column: 3 });
+
+    let board = app.world().resource::<Board>().clone();
+    assert_eq!(board.placed_count(), 2);
+    (app.world().resource::<ReplayState>().recording.clone(), board)
+  }
+
+  #[test]
+  fn a_saved_replay_rebuilds_the_same_board() {
+    let (replay, board) = record(11);
+    let saved = ron::to_string(&replay).unwrap();
+    let loaded: Replay = ron::from_str(&saved).unwrap();
+    assert_eq!(loaded.seed, 11);
+    assert_eq!(loaded.entries.len(), replay.entries.len());
+
+    // Played back from another session's seed, which the replay's own replaces
+    let mut app = app(99);
+    let mut playback = Playback::new(loaded);
+    playback.seek(playback.replay.duration());
+    app.insert_resource(ReplayState { playback: Some(playback), ..default() });
+    for _ in 0..10 {
+      app.update();
+    }
+
+    assert!(app.world().resource::<ReplayState>().playback.is_none());
+    assert_eq!(*app.world().resource::<Board>(), board);
+  }
+
+  #[test]
+  fn scrubbing_back_restarts_the_level() {
+    let entry = |frame, time| ReplayEntry { frame, time, input: ReplayInput::Action(DominoControlAction::Rotate) };
+    let mut playback = Playback::new(Replay { level: 0, seed: 3, entries: vec![entry(10, 1.), entry(400, 12.)] });
+    playback.phase = PlaybackPhase::Running;
+    playback.clock = 8.;
+
+    playback.seek(playback.clock + SCRUB_STEP);
+    assert_eq!(playback.phase, PlaybackPhase::Running);
+    assert_eq!(playback.seek_to, Some(12.));
+
+    playback.seek(playback.clock - SCRUB_STEP);
+    assert_eq!(playback.phase, PlaybackPhase::Restarting);
+    assert_eq!(playback.seek_to, Some(3.));
+  }
+}
@@ -0,0 +1,703 @@
+use std::io::{BufRead, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Mutex;
+
+use leafwing_input_manager::plugin::InputManagerSystem;
+use leafwing_input_manager::prelude::ActionState;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::prelude::*;
+use crate::systems::DominoControlAction;
+
+use super::ai::Position;
+use super::board::*;
+use super::components::*;
+use super::draw_pile::InHoldSlot;
+use super::events::*;
+use super::level::Levels;
+use super::net::NetSession;
+use super::replay::ReplayState;
+use super::rules::*;
+use super::systems::handle_drag_end;
+use super::tray::TraySlot;
+use super::turns::*;
+
+use dragging::Draggable;
+use dragging::IsDragging;
+
+const DEFAULT_PORT: u16 = 7879;
+/** Unread input a client may have waiting before it is dropped, so a broken one can't make us buffer forever */
+const MAX_INBOX: usize = 1024 * 1024;
+/** Lines read from standard input and not yet handled, beyond which the reading thread waits */
+const MAX_QUEUED_LINES: usize = 256;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/** The request was understood, but the game won't do it right now */
+const REFUSED: i64 = -32000;
+
+/**
+ A JSON-RPC 2.0 endpoint into the running game, so bots and test drivers
+ can be written in any language. Started with `--rpc [port]`, which listens
+ on localhost, and/or `--rpc-stdio`. Either way it is one request per line.
+
+ Methods:
+ - `board`: the grid (also drawn as `text`), the players and their scores,
+   and whose turn it is
+ - `hand`: the dominoes of the player whose turn it is, by tray slot
+ - `legal_moves`: every placement the rules allow from that hand, shaped as
+   `place` takes them
+ - `place` `{slot, area, colors?}`: drops the domino in that slot into the
+   area, read as `colors` (as held if left out). Answered once the game has
+   placed or rejected it
+ - `pass`: answered once the rules have allowed or refused it
+ - `reset`, `next_level`
+ - `subscribe`, `unsubscribe`: notifications of `placed`, `rejected` and
+   `level_started`
+
+ Moves go through the same drop path as the mouse, so they are recorded in
+ replays and sent to the other players of a network game.
+ */
+pub struct RpcPlugin;
+
+impl Plugin for RpcPlugin {
+  fn build(&self, app: &mut App) {
+    let Some(server) = RpcServer::from_args(std::env::args().skip(1)) else { return; };
+
+    app
+      .insert_resource(server)
+      .add_systems(PreUpdate, serve_rpc
+        .in_set(InputManagerSystem::ManualControl)
+        .run_if(in_state(MenuState::InGame)))
+      .add_systems(Update, notify_rpc_clients
+        .after(handle_drag_end)
+        .after(handle_rule_actions)
+        .before(end_turn_on_placement))
+      ;
+  }
+}
+
+enum Transport {
+  Tcp {
+    stream: TcpStream,
+    inbox: Vec<u8>,
+    outbox: Vec<u8>,
+  },
+  /** Standard input is read on its own thread, as it can't be polled */
+  Stdio(Mutex<Receiver<String>>),
+}
+
+struct RpcClient {
+  id: u32,
+  transport: Transport,
+  subscribed: bool,
+}
+
+impl RpcClient {
+  fn send(&mut self, message: &Value) {
+    let line = format!("{message}\n");
+    match &mut self.transport {
+      Transport::Tcp { outbox, .. } => outbox.extend(line.as_bytes()),
+      Transport::Stdio(_) => {
+        let mut stdout = std::io::stdout().lock();
+        if let Err(error) = stdout.write_all(line.as_bytes()).and_then(|_| stdout.flush()) {
+          warn!("Could not answer on standard output: {error}");
+        }
+      },
+    }
+  }
+
+  /** Sends what it can and returns the whole lines that have arrived. An error means the client is gone */
+  fn pump(&mut self) -> Result<Vec<String>, String> {
+    match &mut self.transport {
+      Transport::Tcp { stream, inbox, outbox } => {
+        while !outbox.is_empty() {
+          match stream.write(outbox) {
+            Ok(0) => return Err("connection closed".to_string()),
+            Ok(written) => { outbox.drain(..written); },
+            Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+            Err(error) => return Err(error.to_string()),
+          }
+        }
+
+        let mut chunk = [0; 4096];
+        loop {
+          match stream.read(&mut chunk) {
+            Ok(0) => return Err("connection closed".to_string()),
+            Ok(read) => {
+              inbox.extend(&chunk[..read]);
+              if inbox.len() > MAX_INBOX {
+                return Err(format!("more than {MAX_INBOX} bytes sent without being read"));
+              }
+            },
+            Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+            Err(error) => return Err(error.to_string()),
+          }
+        }
+
+        let Some(end) = inbox.iter().rposition(|byte| *byte == b'\n') else { return Ok(vec![]); };
+        let lines: Vec<u8> = inbox.drain(..=end).collect();
+        Ok(String::from_utf8_lossy(&lines).lines().map(str::to_string).collect())
+      },
+      Transport::Stdio(lines) => {
+        let lines = lines.get_mut().map_err(|error| error.to_string())?;
+        let mut received = vec![];
+        loop {
+          match lines.try_recv() {
+            Ok(line) => received.push(line),
+            Err(TryRecvError::Empty) => return Ok(received),
+            Err(TryRecvError::Disconnected) => return Err("standard input closed".to_string()),
+          }
+        }
+      },
+    }
+  }
+}
+
+#[derive(Resource)]
+pub struct RpcServer {
+  listener: Option<TcpListener>,
+  clients: Vec<RpcClient>,
+  next_id: u32,
+  /** Requests waiting on the game, as (client, request id, what they wait for) */
+  awaiting: Vec<(u32, Value, Awaited)>,
+  /** Actions pressed for a request, to let go of next frame */
+  pressed: Vec<DominoControlAction>,
+}
+
+impl RpcServer {
+  pub fn from_args(args: impl IntoIterator<Item = String>) -> Option<Self> {
+    let mut args = args.into_iter().peekable();
+    let mut port = None;
+    let mut stdio = false;
+
+    while let Some(arg) = args.next() {
+      match arg.as_str() {
+        "--rpc" => {
+          let given = args.peek().and_then(|port| port.parse().ok());
+          if given.is_some() {
+            args.next();
+          }
+          port = Some(given.unwrap_or(DEFAULT_PORT));
+        },
+        "--rpc-stdio" => stdio = true,
+        _ => {},
+      }
+    }
+    if port.is_none() && !stdio {
+      return None;
+    }
+
+    let mut server = Self { listener: None, clients: vec![], next_id: 0, awaiting: vec![], pressed: vec![] };
+    if let Some(port) = port {
+      match TcpListener::bind((Ipv4Addr::LOCALHOST, port)).and_then(|listener| listener.set_nonblocking(true).map(|_| listener)) {
+        Ok(listener) => {
+          info!("Serving JSON-RPC on localhost:{port}");
+          server.listener = Some(listener);
+        },
+        Err(error) => warn!("Could not serve JSON-RPC on port {port}: {error}"),
+      }
+    }
+    if stdio {
+      let (sender, receiver) = mpsc::sync_channel(MAX_QUEUED_LINES);
+      std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+          let Ok(line) = line else { break; };
+          if sender.send(line).is_err() { break; }
+        }
+      });
+      server.add_client(Transport::Stdio(Mutex::new(receiver)));
+    }
+
+    Some(server)
+  }
+
+  fn add_client(&mut self, transport: Transport) {
+    self.clients.push(RpcClient { id: self.next_id, transport, subscribed: false });
+    self.next_id += 1;
+  }
+
+  fn send_to(&mut self, client: u32, message: &Value) {
+    if let Some(client) = self.clients.iter_mut().find(|other| other.id == client) {
+      client.send(message);
+    }
+  }
+
+  fn notify(&mut self, method: &str, params: Value) {
+    let message = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+    for client in self.clients.iter_mut().filter(|client| client.subscribed) {
+      client.send(&message);
+    }
+  }
+}
+
+#[derive(Deserialize)]
+struct Request {
+  jsonrpc: String,
+  /** Left out for notifications, which get no answer. A null id is still answered */
+  #[serde(default, deserialize_with = "present")]
+  id: Option<Value>,
+  method: String,
+  #[serde(default)]
+  params: Value,
+}
+
+/** Tells an id of null apart from one left out, which `Option` alone reads the same */
+fn present<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+  Value::deserialize(deserializer).map(Some)
+}
+
+/** Reads a request line, or the error to answer it with (to a null id when it can't be told) */
+fn parse_request(line: &str) -> Result<Request, (Value, RpcError)> {
+  match serde_json::from_str::<Request>(line) {
+    Ok(request) if request.jsonrpc == "2.0" => Ok(request),
+    Ok(request) => Err((request.id.unwrap_or(Value::Null), RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\""))),
+    Err(error) => {
+      let code = if serde_json::from_str::<Value>(line).is_ok() { INVALID_REQUEST } else { PARSE_ERROR };
+      Err((Value::Null, RpcError::new(code, error.to_string())))
+    },
+  }
+}
+
+#[derive(Deserialize)]
+struct PlaceParams {
+  slot: usize,
+  area: PlacementArea,
+  #[serde(default)]
+  colors: Option<[DominoColor; 2]>,
+}
+
+struct RpcError {
+  code: i64,
+  message: String,
+}
+
+impl RpcError {
+  fn new(code: i64, message: impl Into<String>) -> Self {
+    Self { code, message: message.into() }
+  }
+}
+
+/** What a request is waiting on the game for */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Awaited {
+  /** The placement of this domino going through or being rejected */
+  Place(Entity),
+  /** The rules allowing or refusing a pass, in the same frame */
+  Pass,
+}
+
+/** What `serve_rpc` decided to do */
+enum Outcome {
+  Answer(Value),
+  Await(Awaited),
+}
+
+fn result(id: Value, result: Result<Value, RpcError>) -> Value {
+  match result {
+    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+    Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": error.code, "message": error.message } }),
+  }
+}
+
+fn colors(d_type: DominoType) -> [DominoColor; 2] {
+  let (first, second) = d_type.colors();
+  [first, second]
+}
+
+/** Reads requests and answers them, before the game's systems see this frame's input */
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn serve_rpc(
+  mut commands: Commands,
+  mut server: ResMut<RpcServer>,
+  mut action_state: ResMut<ActionState<DominoControlAction>>,
+  mut drag_end_evw: EventWriter<DragEndEvent>,
+  board: Res<Board>,
+  rules: Res<ActiveRuleSet>,
+  turns: Res<Turns>,
+  levels: Res<Levels>,
+  replay: Res<ReplayState>,
+  net: Option<Res<NetSession>>,
+  game_state: Res<State<GameState>>,
+  mut hand: Query<
+    (Entity, &TraySlot, &mut Domino, &mut Transform, Option<&Owner>),
+    (With<Draggable>, Without<InHoldSlot>),
+  >,
+) {
+  let server = server.as_mut();
+
+  for action in std::mem::take(&mut server.pressed) {
+    action_state.release(&action);
+  }
+
+  if let Some(listener) = &server.listener {
+    let mut accepted = vec![];
+    while let Ok((stream, address)) = listener.accept() {
+      match stream.set_nonblocking(true) {
+        Ok(()) => {
+          info!("JSON-RPC client {address} connected");
+          accepted.push(stream);
+        },
+        Err(error) => warn!("Could not set up the JSON-RPC connection from {address}: {error}"),
+      }
+    }
+    for stream in accepted {
+      server.add_client(Transport::Tcp { stream, inbox: vec![], outbox: vec![] });
+    }
+  }
+
+  let mut requests = vec![];
+  server.clients.retain_mut(|client| match client.pump() {
+    Ok(lines) => {
+      requests.extend(lines.into_iter().filter(|line| !line.trim().is_empty()).map(|line| (client.id, line)));
+      true
+    },
+    Err(error) => {
+      info!("JSON-RPC client left: {error}");
+      false
+    },
+  });
+
+  for (client, line) in requests {
+    let request = match parse_request(&line) {
+      Ok(request) => request,
+      Err((id, error)) => {
+        server.send_to(client, &result(id, Err(error)));
+        continue;
+      },
+    };
+
+    // The current player's hand, in tray order
+    let mut current_hand: Vec<_> = hand
+      .iter()
+      .filter(|(.., owner)| turns.is_current(*owner))
+      .map(|(entity, slot, domino, ..)| (slot.0, entity, domino.d_type))
+      .collect();
+    current_hand.sort_by_key(|(slot, ..)| *slot);
+
+    // Only moves by whoever sits at this screen, and only when nothing else is playing
+    let refusal = if replay.playback.is_some() {
+      Some("a replay is playing")
+    } else if turns.current_ai().is_some() {
+      Some("it is the computer's turn")
+    } else if net.as_ref().is_some_and(|net| net.seat != Some(turns.current)) {
+      Some("it is another instance's turn")
+    } else if *game_state.get() == GameState::HandOver {
+      Some("the next player has not taken their seat yet")
+    } else {
+      None
+    };
+
+    let outcome = match request.method.as_str() {
+      "board" => {
+        let level = levels.current();
+        let cells: Vec<_> = board
+          .cells()
+          .map(|(cell, board_cell)| json!({ "x": cell.x, "y": cell.y, "color": board_cell.color }))
+          .collect();
+        let players: Vec<_> = turns.players
+          .iter()
+          .map(|player| json!({ "name": player.name, "score": player.score, "computer": player.ai.is_some() }))
+          .collect();
+        Ok(Outcome::Answer(json!({
+          "level": levels.current,
+          "name": level.name,
+          "rules": rules.0.name(),
+          "width": board.width(),
+          "height": board.height(),
+          "cells": cells,
+          "text": board.to_text(),
+          "players": players,
+          "current": turns.current,
+          "game_over": turns.is_over() || rules.0.is_game_over(&board),
+        })))
+      },
+      "hand" => Ok(Outcome::Answer(current_hand
+        .iter()
+        .map(|(slot, _, d_type)| json!({ "slot": slot, "colors": colors(*d_type) }))
+        .collect())),
+      "legal_moves" => {
+        let position = Position {
+          board: board.clone(),
+          rules: rules.0.boxed_clone(),
+          hand: current_hand.iter().map(|(.., d_type)| *d_type).collect(),
+        };
+        Ok(Outcome::Answer(position
+          .legal_moves()
+          .into_iter()
+          .map(|mv| json!({ "slot": current_hand[mv.domino].0, "area": mv.area, "colors": colors(mv.d_type) }))
+          .collect()))
+      },
+      "place" => match (serde_json::from_value::<PlaceParams>(request.params), refusal) {
+        (Err(error), _) => Err(RpcError::new(INVALID_PARAMS, error.to_string())),
+        (_, Some(refusal)) => Err(RpcError::new(REFUSED, refusal)),
+        (Ok(params), None) => {
+          let held = current_hand.iter().find(|(slot, ..)| *slot == params.slot);
+          match held {
+            None => Err(RpcError::new(INVALID_PARAMS, format!("there is no domino in slot {} of the hand", params.slot))),
+            Some((_, entity, held)) => {
+              let d_type = match params.colors {
+                None => Some(*held),
+                Some([first, second]) => [*held, held.flipped()].into_iter().find(|d_type| colors(*d_type) == [first, second]),
+              };
+              match d_type {
+                None => Err(RpcError::new(INVALID_PARAMS, "the domino in that slot doesn't have those colors")),
+                Some(d_type) => {
+                  let (_, _, mut domino, mut transform, _) = hand.get_mut(*entity).expect("the hand was just read");
+                  domino.d_type = d_type;
+                  domino.horizontal = params.area.is_horizontal();
+                  transform.rotation = domino.rotation();
+                  transform.translation = params.area.center().extend(transform.translation.z);
+                  commands.entity(*entity).insert(IsDragging);
+                  drag_end_evw.send(DragEndEvent { draggable: *entity });
+                  Ok(Outcome::Await(Awaited::Place(*entity)))
+                },
+              }
+            },
+          }
+        },
+      },
+      "pass" | "reset" | "next_level" => {
+        let action = match request.method.as_str() {
+          "pass" => DominoControlAction::Pass,
+          "reset" => DominoControlAction::Reset,
+          _ => DominoControlAction::NextLevel,
+        };
+        let host_only = action != DominoControlAction::Pass && net.as_ref().is_some_and(|net| !net.is_host());
+        match refusal {
+          Some(refusal) if action == DominoControlAction::Pass => Err(RpcError::new(REFUSED, refusal)),
+          _ if host_only => Err(RpcError::new(REFUSED, "only the host can change the level")),
+          _ => {
+            action_state.press(&action);
+            server.pressed.push(action);
+            Ok(if action == DominoControlAction::Pass { Outcome::Await(Awaited::Pass) } else { Outcome::Answer(Value::Bool(true)) })
+          },
+        }
+      },
+      "subscribe" | "unsubscribe" => {
+        let subscribed = request.method == "subscribe";
+        if let Some(client) = server.clients.iter_mut().find(|other| other.id == client) {
+          client.subscribed = subscribed;
+        }
+        Ok(Outcome::Answer(Value::Bool(subscribed)))
+      },
+      method => Err(RpcError::new(METHOD_NOT_FOUND, format!("no method called {method}"))),
+    };
+
+    let Some(id) = request.id else { continue; };
+    match outcome {
+      Ok(Outcome::Answer(answer)) => server.send_to(client, &result(id, Ok(answer))),
+      Ok(Outcome::Await(awaited)) => server.awaiting.push((client, id, awaited)),
+      Err(error) => server.send_to(client, &result(id, Err(error))),
+    }
+  }
+}
+
+/** Answers `place` and `pass` requests and tells subscribers what happened, once the game has dealt with them */
+#[allow(clippy::too_many_arguments)]
+fn notify_rpc_clients(
+  mut server: ResMut<RpcServer>,
+  mut placement_evr: EventReader<DominoPlacementEvent>,
+  mut rejected_evr: EventReader<PlacementRejected>,
+  mut passed_evr: EventReader<TurnPassed>,
+  mut level_started_evr: EventReader<LevelStarted>,
+  turns: Res<Turns>,
+  levels: Res<Levels>,
+  slots: Query<&TraySlot>,
+) {
+  for event in placement_evr.read() {
+    let placed = json!({
+      "slot": slots.get(event.domino).ok().map(|slot| slot.0),
+      "area": event.placement,
+      "colors": colors(event.d_type),
+      "player": turns.current,
+    });
+
+    if let Some(index) = server.awaiting.iter().position(|(.., awaited)| *awaited == Awaited::Place(event.domino)) {
+      let (client, id, _) = server.awaiting.remove(index);
+      server.send_to(client, &result(id, Ok(placed.clone())));
+    }
+    server.notify("placed", placed);
+  }
+
+  for event in rejected_evr.read() {
+    let reason = format!("{:?}", event.reason);
+
+    if let Some(index) = server.awaiting.iter().position(|(.., awaited)| *awaited == Awaited::Place(event.domino)) {
+      let (client, id, _) = server.awaiting.remove(index);
+      server.send_to(client, &result(id, Err(RpcError::new(REFUSED, format!("rejected: {reason}")))));
+    }
+    server.notify("rejected", json!({
+      "slot": slots.get(event.domino).ok().map(|slot| slot.0),
+      "area": event.placement,
+      "colors": colors(event.d_type),
+      "reason": reason,
+    }));
+  }
+
+  // Passes are settled in the frame they were asked for: no pass means the rules refused it
+  let passed = passed_evr.read().count() > 0;
+  let (passes, others): (Vec<_>, Vec<_>) = std::mem::take(&mut server.awaiting)
+    .into_iter()
+    .partition(|(.., awaited)| *awaited == Awaited::Pass);
+  server.awaiting = others;
+  for (client, id, _) in passes {
+    let answer = if passed { Ok(Value::Bool(true)) } else { Err(RpcError::new(REFUSED, "the rules don't allow passing now")) };
+    server.send_to(client, &result(id, answer));
+  }
+
+  for event in level_started_evr.read() {
+    // Anything still waiting went with the old level
+    for (client, id, _) in std::mem::take(&mut server.awaiting) {
+      server.send_to(client, &result(id, Err(RpcError::new(REFUSED, "the level was restarted"))));
+    }
+    server.notify("level_started", json!({
+      "level": event.level,
+      "name": levels.all.get(event.level).map(|level| level.name.clone()),
+      "seed": event.seed,
+    }));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::BufReader;
+  use std::time::Duration;
+
+  use bevy::ecs::system::RunSystemOnce;
+
+  use super::super::rules::free_form::FreeForm;
+  use super::super::tray::spawn_tray_domino;
+
+  use super::*;
+
+  use DominoColor::*;
+
+  #[test]
+  fn parses_requests() {
+    let request = parse_request(r#"{"jsonrpc": "2.0", "id": 3, "method": "state"}"#).ok().unwrap();
+    assert_eq!(request.id, Some(json!(3)));
+    assert_eq!(request.method, "state");
+    assert_eq!(request.params, Value::Null);
+
+    let notification = parse_request(r#"{"jsonrpc": "2.0", "method": "subscribe"}"#).ok().unwrap();
+    assert_eq!(notification.id, None);
+
+    let null_id = parse_request(r#"{"jsonrpc": "2.0", "id": null, "method": "state"}"#).ok().unwrap();
+    assert_eq!(null_id.id, Some(Value::Null));
+  }
+
+  #[test]
+  fn tells_parse_errors_from_invalid_requests() {
+    let code = |line: &str| parse_request(line).err().map(|(id, error)| (id, error.code));
+
+    assert_eq!(code("{\"jsonrpc\": \"2.0\", \"id\": 1"), Some((Value::Null, PARSE_ERROR)));
+    assert_eq!(code(r#"{"jsonrpc": "2.0", "id": 1}"#), Some((Value::Null, INVALID_REQUEST)));
+    assert_eq!(code(r#"[1, 2]"#), Some((Value::Null, INVALID_REQUEST)));
+    assert_eq!(code(r#"{"jsonrpc": "1.0", "id": 1, "method": "state"}"#), Some((json!(1), INVALID_REQUEST)));
+  }
+
+  /** Connects to a fresh server on a free port */
+  fn connect() -> (RpcServer, TcpStream) {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    stream.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+    (RpcServer { listener: Some(listener), clients: vec![], next_id: 0, awaiting: vec![], pressed: vec![] }, stream)
+  }
+
+  fn app(server: RpcServer) -> App {
+    let mut app = App::new();
+    app
+      .add_plugins((MinimalPlugins, bevy::state::app::StatesPlugin))
+      .init_state::<GameState>()
+      .init_resource::<ActionState<DominoControlAction>>()
+      .init_resource::<Levels>()
+      .init_resource::<ReplayState>()
+      .insert_resource(ActiveRuleSet(Box::new(FreeForm)))
+      .insert_resource(Board::new(4, 4))
+      .insert_resource(Turns::new(1, &[]))
+      .insert_resource(server)
+      .add_event::<DragEndEvent>()
+      .add_event::<TurnPassed>()
+      .add_event::<DominoPlacementEvent>()
+      .add_event::<PlacementRejected>()
+      .add_event::<LevelStarted>()
+      .add_systems(PreUpdate, serve_rpc)
+      .add_systems(Update, (handle_rule_actions, notify_rpc_clients).chain());
+    app
+  }
+
+  /** Sends a line and runs the app until it is answered */
+  fn ask(app: &mut App, stream: &mut TcpStream, line: &str) -> Value {
+    stream.write_all(format!("{line}\n").as_bytes()).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut answer = String::new();
+    for _ in 0..100 {
+      app.update();
+      if reader.read_line(&mut answer).is_ok_and(|read| read > 0) {
+        return serde_json::from_str(&answer).unwrap();
+      }
+    }
+    panic!("no answer to {line}");
+  }
+
+  #[test]
+  fn answers_errors_and_null_ids() {
+    let (server, mut stream) = connect();
+    let mut app = app(server);
+
+    let answer = ask(&mut app, &mut stream, "not json");
+    assert_eq!(answer["error"]["code"], PARSE_ERROR);
+    assert_eq!(answer["id"], Value::Null);
+
+    let answer = ask(&mut app, &mut stream, r#"{"jsonrpc": "2.0", "id": 1, "method": "fly"}"#);
+    assert_eq!(answer["error"]["code"], METHOD_NOT_FOUND);
+    assert_eq!(answer["id"], 1);
+
+    let answer = ask(&mut app, &mut stream, r#"{"jsonrpc": "2.0", "id": 2, "method": "place", "params": {"slot": "first"}}"#);
+    assert_eq!(answer["error"]["code"], INVALID_PARAMS);
+
+    let answer = ask(&mut app, &mut stream, r#"{"jsonrpc": "2.0", "id": null, "method": "fly"}"#);
+    assert_eq!(answer["error"]["code"], METHOD_NOT_FOUND);
+    assert!(answer.as_object().unwrap().contains_key("id"));
+    assert_eq!(answer["id"], Value::Null);
+  }
+
+  #[test]
+  fn answers_a_pass_from_the_rules() {
+    let (server, mut stream) = connect();
+    let mut app = app(server);
+
+    // Nothing in hand, so there is no move to make
+    let answer = ask(&mut app, &mut stream, r#"{"jsonrpc": "2.0", "id": 1, "method": "pass"}"#);
+    assert_eq!(answer["result"], true);
+
+    app.world_mut().run_system_once(|mut commands: Commands| {
+      spawn_tray_domino(&mut commands, DominoType::new(Red, Blue), TraySlot(0), None);
+    }).unwrap();
+    let answer = ask(&mut app, &mut stream, r#"{"jsonrpc": "2.0", "id": 2, "method": "pass"}"#);
+    assert_eq!(answer["error"]["code"], REFUSED);
+    assert_eq!(answer["id"], 2);
+  }
+
+  #[test]
+  fn drops_a_client_that_sends_too_much() {
+    let (server, mut stream) = connect();
+    let mut app = app(server);
+    app.update();
+    assert_eq!(app.world().resource::<RpcServer>().clients.len(), 1);
+
+    let junk = vec![b'x'; 64 * 1024];
+    for _ in 0..100 {
+      if stream.write_all(&junk).is_err() { break; }
+      app.update();
+      if app.world().resource::<RpcServer>().clients.is_empty() { break; }
+    }
+    assert!(app.world().resource::<RpcServer>().clients.is_empty());
+  }
+}
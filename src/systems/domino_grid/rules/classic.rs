@@ -0,0 +1,455 @@
+use std::cmp::Ordering;
+
+use rand::seq::SliceRandom;
+
+use crate::prelude::*;
+use crate::systems::random::StreamRng;
+
+use super::super::board::*;
+use super::super::components::*;
+use super::*;
+
+pub const CLASSIC_HAND_SIZE: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClassicVariant {
+  /** A player with no move must pass */
+  Block,
+  /** A player with no move draws from the boneyard until they can play */
+  Draw,
+}
+
+/**
+ A cell at the end of the line of play that the next domino may be matched
+ against. Its color is read back from the board.
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct OpenEnd {
+  pub cell: IVec2,
+}
+
+/** How a placement joins the line of play */
+#[derive(Clone, Copy, Debug)]
+pub enum LineMatch {
+  /** The very first domino of the hand, which opens both of its ends */
+  Opening,
+  /** `half` of the placed domino (0 = first, 1 = second) matches `open_ends[end]` */
+  End { end: usize, half: usize },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HandOutcome {
+  /** Every domino in the hand was played */
+  Domino,
+  /** No legal move is left and there is nothing to draw */
+  Blocked,
+}
+
+/**
+ A hand of classic dominoes, with a hand dealt to each player. The line of
+ play is laid out on the grid: a domino is legal only when one of its halves
+ sits next to an open end of the same color.
+ */
+#[derive(Clone, Debug)]
+pub struct ClassicRules {
+  pub variant: ClassicVariant,
+  pub open_ends: Vec<OpenEnd>,
+  /** Each seat's hand, once they have been dealt */
+  pub hands: Vec<Vec<DominoType>>,
+  /** The seat whose turn it is */
+  pub seat: usize,
+  pub boneyard: Vec<DominoType>,
+  pub outcome: Option<HandOutcome>,
+}
+
+impl ClassicRules {
+  pub fn new(variant: ClassicVariant, rng: &mut StreamRng) -> Self {
+    let mut boneyard = DominoType::classic_set();
+    boneyard.shuffle(rng);
+
+    Self {
+      variant,
+      open_ends: vec![],
+      hands: vec![],
+      seat: 0,
+      boneyard,
+      outcome: None,
+    }
+  }
+
+  /** The hand of the player whose turn it is */
+  pub fn hand(&self) -> &[DominoType] {
+    self.hands.get(self.seat).map_or(&[], Vec::as_slice)
+  }
+
+  /** Whether the player whose turn it is holds the domino, either way round */
+  fn holds(&self, d_type: DominoType) -> bool {
+    self.hand().iter().any(|domino| *domino == d_type || *domino == d_type.flipped())
+  }
+
+  /**
+    Finds how the domino would join the line of play, without checking
+    whether its cells are free.
+   */
+  fn line_match(&self, board: &Board, area: &PlacementArea, d_type: DominoType) -> Option<LineMatch> {
+    if self.open_ends.is_empty() {
+      return Some(LineMatch::Opening);
+    }
+
+    for (half, (cell, color)) in area.halves(d_type).into_iter().enumerate() {
+      for (end, open_end) in self.open_ends.iter().enumerate() {
+        let end_color = board.get(open_end.cell).map(|end_cell| end_cell.color);
+        if end_color == Some(color) && are_adjacent(open_end.cell, cell) {
+          return Some(LineMatch::End { end, half });
+        }
+      }
+    }
+
+    None
+  }
+
+  /** Whether any domino of `hand` could join the line of play */
+  fn can_play(&self, board: &Board, hand: &[DominoType]) -> bool {
+    board.free_areas().any(|area| {
+      hand.iter().any(|domino| {
+        self.line_match(board, &area, *domino).is_some()
+          || self.line_match(board, &area, domino.flipped()).is_some()
+      })
+    })
+  }
+
+  pub fn has_legal_move(&self, board: &Board) -> bool {
+    self.outcome.is_none() && self.can_play(board, self.hand())
+  }
+
+  /** Whether the line is stuck for everyone, whoever's turn it is */
+  fn nobody_can_play(&self, board: &Board) -> bool {
+    !self.hands.iter().any(|hand| self.can_play(board, hand))
+  }
+
+  /** Records a domino joining the line of play */
+  fn play(&mut self, board: &Board, area: PlacementArea, d_type: DominoType, line_match: LineMatch) {
+    let seat = self.seat;
+    if let Some(hand) = self.hands.get_mut(seat) {
+      if let Some(index) = hand.iter().position(|domino| *domino == d_type || *domino == d_type.flipped()) {
+        hand.remove(index);
+      }
+    }
+
+    let [first_cell, second_cell] = area.cells();
+
+    match line_match {
+      LineMatch::Opening => {
+        self.open_ends = vec![
+          OpenEnd { cell: first_cell },
+          OpenEnd { cell: second_cell },
+        ];
+      },
+      LineMatch::End { end, half } => {
+        // The half that was matched is now joined; the other one is open
+        self.open_ends[end] = if half == 0 {
+          OpenEnd { cell: second_cell }
+        } else {
+          OpenEnd { cell: first_cell }
+        };
+      },
+    }
+
+    self.update_outcome(board);
+  }
+
+  pub fn can_draw(&self, board: &Board) -> bool {
+    self.outcome.is_none()
+      && self.variant == ClassicVariant::Draw
+      && !self.boneyard.is_empty()
+      && !self.has_legal_move(board)
+  }
+
+  /** Passing is only allowed when there is no move and nothing left to draw */
+  pub fn can_pass(&self, board: &Board) -> bool {
+    self.outcome.is_none() && !self.has_legal_move(board) && !self.can_draw(board)
+  }
+
+  fn update_outcome(&mut self, board: &Board) {
+    if self.hands.get(self.seat).is_some_and(Vec::is_empty) {
+      self.outcome = Some(HandOutcome::Domino);
+    } else if self.variant == ClassicVariant::Draw && self.boneyard.is_empty() && self.nobody_can_play(board) {
+      self.outcome = Some(HandOutcome::Blocked);
+    }
+  }
+
+  /** End-of-hand score: the pips left in the current player's hand, so lower is better */
+  pub fn pip_count(&self) -> u32 {
+    self.hand().iter().map(DominoType::pips).sum()
+  }
+}
+
+impl RuleSet for ClassicRules {
+  fn name(&self) -> &'static str {
+    match self.variant {
+      ClassicVariant::Block => "Classic (block)",
+      ClassicVariant::Draw => "Classic (draw)",
+    }
+  }
+
+  fn validate(&self, board: &Board, area: &PlacementArea, d_type: DominoType) -> Result<(), PlacementRejection> {
+    if self.outcome.is_some() {
+      return Err(PlacementRejection::GameOver);
+    }
+
+    if !self.holds(d_type) {
+      return Err(PlacementRejection::NotInHand);
+    }
+
+    if !board.can_fit(area) {
+      return Err(PlacementRejection::Occupied);
+    }
+
+    self.line_match(board, area, d_type)
+      .map(|_| ())
+      .ok_or(PlacementRejection::NoMatchingEnd)
+  }
+
+  fn on_placed(&mut self, board: &Board, area: &PlacementArea, d_type: DominoType) {
+    if let Some(line_match) = self.line_match(board, area, d_type) {
+      self.play(board, *area, d_type, line_match);
+    }
+  }
+
+  /** The pips left in the hand, so lower is better */
+  fn score(&self, _board: &Board) -> i32 {
+    self.pip_count() as i32
+  }
+
+  /** Fewer pips left in the hand is better */
+  fn evaluate(&self, _board: &Board) -> i32 {
+    -(self.pip_count() as i32)
+  }
+
+  fn is_game_over(&self, _board: &Board) -> bool {
+    self.outcome.is_some()
+  }
+
+  /** Fewer pips wins */
+  fn compare_scores(&self, a: i32, b: i32) -> Ordering {
+    b.cmp(&a)
+  }
+
+  fn boxed_clone(&self) -> Box<dyn RuleSet> {
+    Box::new(self.clone())
+  }
+
+  /** The hands come straight from the boneyard, so nothing goes in the draw pile */
+  fn deal(&mut self) -> Vec<DominoType> {
+    vec![]
+  }
+
+  /** Everyone gets the same number of dominoes, fewer than a full hand when there are too many players */
+  fn deal_hands(&mut self, seats: usize) -> Option<Vec<Vec<DominoType>>> {
+    let seats = seats.max(1);
+    let hand_size = CLASSIC_HAND_SIZE.min(self.boneyard.len() / seats);
+    self.hands = (0..seats)
+      .map(|_| self.boneyard.split_off(self.boneyard.len() - hand_size))
+      .collect();
+    self.seat = 0;
+    Some(self.hands.clone())
+  }
+
+  fn begin_turn(&mut self, seat: usize) {
+    self.seat = seat;
+  }
+
+  fn seat_score(&self, _board: &Board, seat: usize) -> Option<i32> {
+    self.hands.get(seat).map(|hand| hand.iter().map(DominoType::pips).sum::<u32>() as i32)
+  }
+
+  /** Takes the next domino from the boneyard, if drawing is allowed */
+  fn draw(&mut self, board: &Board) -> Option<DominoType> {
+    if !self.can_draw(board) { return None; }
+
+    let domino = self.boneyard.remove(0);
+    let seat = self.seat;
+    self.hands[seat].push(domino);
+    self.update_outcome(board);
+    Some(domino)
+  }
+
+  /** Classic rules know the hands themselves, and also make the player draw while they can */
+  fn pass(&mut self, board: &Board, _can_move: bool) -> bool {
+    if !self.can_pass(board) { return false; }
+
+    // The hand ends once nobody can join the line any more. Alone, that is
+    // as soon as the player has to pass.
+    if self.nobody_can_play(board) {
+      self.outcome = Some(HandOutcome::Blocked);
+    }
+    true
+  }
+
+  fn status(&self, board: &Board) -> String {
+    let prompt = match self.outcome {
+      Some(HandOutcome::Domino) => format!("Domino! Hand over, pips left: {}", self.pip_count()),
+      Some(HandOutcome::Blocked) => format!("Blocked! Hand over, pips left: {}", self.pip_count()),
+      None if self.can_draw(board) => "No move: press [D] to draw".to_string(),
+      None if self.can_pass(board) => "No move: press [P] to pass".to_string(),
+      None if self.open_ends.is_empty() => "Place any domino to start the line".to_string(),
+      None => "Match an open end".to_string(),
+    };
+
+    format!(
+      "Hand: {} Boneyard: {}\n{prompt}",
+      self.hand().len(),
+      self.boneyard.len(),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::systems::random::GameRng;
+
+  use super::super::color_match::ColorMatch;
+  use super::*;
+
+  use DominoColor::*;
+
+  fn rules(hand: Vec<DominoType>) -> ClassicRules {
+    ClassicRules {
+      variant: ClassicVariant::Block,
+      open_ends: vec![],
+      hands: vec![hand],
+      seat: 0,
+      boneyard: vec![],
+      outcome: None,
+    }
+  }
+
+  fn horizontal(left_cell_col: i32, row: i32) -> PlacementArea {
+    PlacementArea::Horizontal { left_cell_col, row }
+  }
+
+  fn play(rules: &mut ClassicRules, board: &mut Board, area: PlacementArea, d_type: DominoType) {
+    rules.validate(board, &area, d_type).unwrap();
+    board.place(area, d_type);
+    rules.on_placed(board, &area, d_type);
+  }
+
+  #[test]
+  fn opening_goes_anywhere_and_opens_both_ends() {
+    let mut board = Board::new(6, 3);
+    let mut rules = rules(vec![DominoType::new(Blue, Red), DominoType::new(Green, Green)]);
+
+    play(&mut rules, &mut board, horizontal(2, 1), DominoType::new(Blue, Red));
+
+    let ends: Vec<_> = rules.open_ends.iter().map(|end| end.cell).collect();
+    assert_eq!(ends, [IVec2::new(2, 1), IVec2::new(3, 1)]);
+  }
+
+  #[test]
+  fn later_dominoes_must_match_an_open_end() {
+    let mut board = Board::new(6, 3);
+    let mut rules = rules(vec![DominoType::new(Blue, Red), DominoType::new(Red, Green), DominoType::new(Yellow, Yellow)]);
+    play(&mut rules, &mut board, horizontal(0, 1), DominoType::new(Blue, Red));
+
+    assert_eq!(rules.validate(&board, &horizontal(2, 1), DominoType::new(Yellow, Yellow)), Err(PlacementRejection::NoMatchingEnd));
+    assert_eq!(rules.validate(&board, &horizontal(2, 1), DominoType::new(Green, Red)), Err(PlacementRejection::NoMatchingEnd));
+    assert_eq!(rules.validate(&board, &horizontal(1, 1), DominoType::new(Red, Green)), Err(PlacementRejection::Occupied));
+
+    play(&mut rules, &mut board, horizontal(2, 1), DominoType::new(Red, Green));
+
+    // The matched red end is joined, leaving the new green half open
+    let ends: Vec<_> = rules.open_ends.iter().map(|end| end.cell).collect();
+    assert_eq!(ends, [IVec2::new(0, 1), IVec2::new(3, 1)]);
+  }
+
+  #[test]
+  fn scores_the_pips_left_in_hand() {
+    let mut board = Board::new(6, 3);
+    let mut rules = rules(vec![DominoType::new(Green, Blue), DominoType::new(Yellow, Green)]);
+    assert_eq!(rules.score(&board), 7);
+    assert_eq!(rules.evaluate(&board), -7);
+
+    play(&mut rules, &mut board, horizontal(0, 0), DominoType::new(Yellow, Green));
+    assert_eq!(rules.score(&board), 2);
+    assert!(!rules.is_game_over(&board));
+
+    let above_green = PlacementArea::Vertical { bottom_cell_row: 1, column: 1 };
+    play(&mut rules, &mut board, above_green, DominoType::new(Green, Blue));
+    assert_eq!(rules.score(&board), 0);
+    assert_eq!(rules.outcome, Some(HandOutcome::Domino));
+    assert!(rules.is_game_over(&board));
+  }
+
+  #[test]
+  fn fewer_pips_is_the_better_score() {
+    let rules = rules(vec![]);
+    assert_eq!(rules.compare_scores(2, 5), Ordering::Greater);
+    assert_eq!(rules.compare_scores(5, 2), Ordering::Less);
+
+    let stacked = RuleStack(vec![Box::new(rules), Box::new(ColorMatch)]);
+    assert_eq!(stacked.compare_scores(2, 5), Ordering::Greater);
+  }
+
+  #[test]
+  fn every_seat_plays_from_their_own_hand() {
+    let mut rules = ClassicRules::new(ClassicVariant::Block, &mut GameRng::new(3).fork("rules"));
+    let hands = rules.deal_hands(2).unwrap();
+    assert_eq!(hands.iter().map(Vec::len).collect::<Vec<_>>(), [CLASSIC_HAND_SIZE, CLASSIC_HAND_SIZE]);
+    assert_eq!(rules.seat_score(&Board::new(1, 1), 1), Some(hands[1].iter().map(DominoType::pips).sum::<u32>() as i32));
+
+    // Seat 1's dominoes can't be played on seat 0's turn, and the other way round
+    let board = Board::new(6, 3);
+    let theirs = *hands[1].iter().find(|domino| !hands[0].contains(domino) && !hands[0].contains(&domino.flipped())).unwrap();
+    assert_eq!(rules.validate(&board, &horizontal(0, 0), theirs), Err(PlacementRejection::NotInHand));
+    rules.begin_turn(1);
+    assert_eq!(rules.validate(&board, &horizontal(0, 0), theirs), Ok(()));
+    assert_eq!(rules.validate(&board, &horizontal(0, 0), theirs.flipped()), Ok(()));
+  }
+
+  #[test]
+  fn only_held_dominoes_can_be_placed() {
+    let board = Board::new(6, 3);
+    let rules = rules(vec![DominoType::new(Blue, Red)]);
+    assert_eq!(rules.validate(&board, &horizontal(0, 0), DominoType::new(Green, Green)), Err(PlacementRejection::NotInHand));
+  }
+
+  #[test]
+  fn drawing_the_last_domino_without_a_move_blocks_the_hand() {
+    let mut board = Board::new(6, 3);
+    let mut rules = ClassicRules {
+      variant: ClassicVariant::Draw,
+      boneyard: vec![DominoType::new(Yellow, Yellow)],
+      ..rules(vec![DominoType::new(Blue, Blue), DominoType::new(Green, Green)])
+    };
+    play(&mut rules, &mut board, horizontal(0, 1), DominoType::new(Blue, Blue));
+    assert!(rules.can_draw(&board));
+
+    assert_eq!(rules.draw(&board), Some(DominoType::new(Yellow, Yellow)));
+    assert_eq!(rules.outcome, Some(HandOutcome::Blocked));
+    assert!(rules.is_game_over(&board));
+  }
+
+  #[test]
+  fn a_pass_only_ends_the_hand_once_nobody_can_play() {
+    let mut board = Board::new(6, 3);
+    let mut rules = ClassicRules {
+      hands: vec![vec![DominoType::new(Blue, Blue), DominoType::new(Green, Green)], vec![DominoType::new(Blue, Yellow), DominoType::new(Yellow, Yellow)]],
+      ..rules(vec![])
+    };
+    play(&mut rules, &mut board, horizontal(0, 1), DominoType::new(Blue, Blue));
+
+    // The second player can match a blue end, so the first only passes
+    assert!(rules.pass(&board, false));
+    assert_eq!(rules.outcome, None);
+
+    rules.begin_turn(1);
+    play(&mut rules, &mut board, horizontal(2, 1), DominoType::new(Blue, Yellow));
+    rules.begin_turn(0);
+    assert!(rules.pass(&board, false));
+    assert_eq!(rules.outcome, None);
+
+    rules.begin_turn(1);
+    play(&mut rules, &mut board, horizontal(4, 1), DominoType::new(Yellow, Yellow));
+    assert_eq!(rules.outcome, Some(HandOutcome::Domino));
+  }
+}
@@ -0,0 +1,63 @@
+use crate::prelude::*;
+
+use super::super::board::*;
+use super::super::components::*;
+use super::*;
+
+/**
+ Every half has to match the color of any placed half it touches. Meant to be
+ stacked on top of other rules rather than used on its own.
+ */
+#[derive(Clone)]
+pub struct ColorMatch;
+
+impl RuleSet for ColorMatch {
+  fn name(&self) -> &'static str {
+    "Color match"
+  }
+
+  fn validate(&self, board: &Board, area: &PlacementArea, d_type: DominoType) -> Result<(), PlacementRejection> {
+    if !board.can_fit(area) {
+      return Err(PlacementRejection::Occupied);
+    }
+
+    for (cell, color) in area.halves(d_type) {
+      let mismatched = neighbours(cell)
+        .iter()
+        .filter_map(|neighbour| board.get(*neighbour))
+        .any(|neighbour| neighbour.color != color);
+
+      if mismatched {
+        return Err(PlacementRejection::ColorMismatch);
+      }
+    }
+
+    Ok(())
+  }
+
+  fn on_placed(&mut self, _board: &Board, _area: &PlacementArea, _d_type: DominoType) {}
+
+  fn boxed_clone(&self) -> Box<dyn RuleSet> {
+    Box::new(self.clone())
+  }
+
+  /** One point for every pair of touching same-colored halves from different dominoes */
+  fn score(&self, board: &Board) -> i32 {
+    board
+      .cells()
+      .map(|(cell, board_cell)| {
+        [cell + IVec2::X, cell + IVec2::Y]
+          .into_iter()
+          .filter_map(|neighbour| board.get(neighbour))
+          .filter(|neighbour| {
+            neighbour.placement != board_cell.placement && neighbour.color == board_cell.color
+          })
+          .count() as i32
+      })
+      .sum()
+  }
+
+  fn is_game_over(&self, _board: &Board) -> bool {
+    false
+  }
+}
@@ -0,0 +1,36 @@
+use super::super::board::*;
+use super::super::components::*;
+use super::*;
+
+/** The original sandbox rules: a domino can go anywhere it fits */
+#[derive(Clone)]
+pub struct FreeForm;
+
+impl RuleSet for FreeForm {
+  fn name(&self) -> &'static str {
+    "Free form"
+  }
+
+  fn validate(&self, board: &Board, area: &PlacementArea, _d_type: DominoType) -> Result<(), PlacementRejection> {
+    if board.can_fit(area) {
+      Ok(())
+    } else {
+      Err(PlacementRejection::Occupied)
+    }
+  }
+
+  fn on_placed(&mut self, _board: &Board, _area: &PlacementArea, _d_type: DominoType) {}
+
+  fn boxed_clone(&self) -> Box<dyn RuleSet> {
+    Box::new(self.clone())
+  }
+
+  /** One point per domino on the board */
+  fn score(&self, board: &Board) -> i32 {
+    board.placed_count() as i32
+  }
+
+  fn is_game_over(&self, board: &Board) -> bool {
+    board.free_areas().next().is_none()
+  }
+}
@@ -0,0 +1,431 @@
+pub mod classic;
+pub mod color_match;
+pub mod free_form;
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use leafwing_input_manager::prelude::ActionState;
+
+use crate::prelude::*;
+use crate::systems::DominoControlAction;
+use crate::systems::random::StreamRng;
+
+use super::board::*;
+use super::components::*;
+use super::events::TurnPassed;
+use super::level::Levels;
+use super::tray::*;
+use super::turns::{Owner, Turns};
+
+use classic::*;
+use color_match::*;
+use free_form::*;
+
+use dragging::Draggable;
+
+pub struct RulesPlugin;
+
+impl Plugin for RulesPlugin {
+  fn build(&self, app: &mut App) {
+    app
+      .init_resource::<RuleSetRegistry>()
+      .insert_resource(ActiveRuleSet(Box::new(FreeForm)))
+      .add_systems(Update, (
+        handle_rule_actions,
+        update_rules_status_text,
+      ).chain())
+      ;
+  }
+}
+
+/** Why the rules refused a placement */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlacementRejection {
+  /** The domino was not dropped over an area it could go in */
+  NoPlacementArea,
+  /** One of the cells is off the grid or already covered */
+  Occupied,
+  /** The game has already finished */
+  GameOver,
+  /** The domino does not continue the line of play */
+  NoMatchingEnd,
+  /** The domino is not in the hand of the player whose turn it is */
+  NotInHand,
+  /** A half touches a placed half of a different color */
+  ColorMismatch,
+}
+
+/**
+ Decides where dominoes may go and how a game is scored.
+
+ The board is updated by the placement systems; rule sets only look at it,
+ keeping whatever extra state they need (open ends, hands) themselves.
+ */
+pub trait RuleSet: Send + Sync + 'static {
+  fn name(&self) -> &'static str;
+
+  /** Checks whether the domino may be dropped into the area */
+  fn validate(&self, board: &Board, area: &PlacementArea, d_type: DominoType) -> Result<(), PlacementRejection>;
+
+  /** Called after the domino has been written to the board */
+  fn on_placed(&mut self, board: &Board, area: &PlacementArea, d_type: DominoType);
+
+  fn score(&self, board: &Board) -> i32;
+
+  /**
+    How good the board is for the player, higher being better. Computer
+    players compare moves with this, so rules where a low score wins say so here.
+   */
+  fn evaluate(&self, board: &Board) -> i32 {
+    self.score(board)
+  }
+
+  fn is_game_over(&self, board: &Board) -> bool;
+
+  /** How score `a` compares with `b`, `Greater` meaning `a` is the better one. Higher wins unless the rules say otherwise */
+  fn compare_scores(&self, a: i32, b: i32) -> Ordering {
+    a.cmp(&b)
+  }
+
+  /** The dominoes that start in the tray, or in the draw pile for levels with a hand */
+  fn deal(&mut self) -> Vec<DominoType> {
+    DominoType::ALL_TYPES.to_vec()
+  }
+
+  /**
+    Each of `seats` players' starting hand, for rules that keep track of who
+    holds what. `None` deals every hand from the draw pile instead.
+   */
+  fn deal_hands(&mut self, _seats: usize) -> Option<Vec<Vec<DominoType>>> {
+    None
+  }
+
+  /** Called when it becomes `seat`'s turn, before they make their move */
+  fn begin_turn(&mut self, _seat: usize) {}
+
+  /**
+    A player's score as the rules keep it, for rules that score each seat by
+    its own hand. `None` credits players with what their turns add to `score`.
+   */
+  fn seat_score(&self, _board: &Board, _seat: usize) -> Option<i32> {
+    None
+  }
+
+  /** Takes another domino for the tray, if the rules allow it right now */
+  fn draw(&mut self, _board: &Board) -> Option<DominoType> {
+    None
+  }
+
+  /**
+    Gives up the turn, `can_move` saying whether the player could have placed
+    a domino instead. Returns false if passing is not allowed, which by
+    default is whenever they could.
+   */
+  fn pass(&mut self, _board: &Board, can_move: bool) -> bool {
+    !can_move
+  }
+
+  /** A copy to try moves out on without touching the game being played */
+  fn boxed_clone(&self) -> Box<dyn RuleSet>;
+
+  /** A line or two telling the player how the game is going */
+  fn status(&self, board: &Board) -> String {
+    if self.is_game_over(board) {
+      format!("Game over! Score: {}", self.score(board))
+    } else {
+      format!("Score: {}", self.score(board))
+    }
+  }
+}
+
+/**
+ Several rule sets applied together. A placement must satisfy all of them,
+ scores are added up and the game ends as soon as any of them says so.
+ Dealing, drawing, passing and comparing scores are left to the first rule
+ set, and every one of them is told whose turn it is.
+ */
+pub struct RuleStack(pub Vec<Box<dyn RuleSet>>);
+
+impl RuleSet for RuleStack {
+  fn name(&self) -> &'static str {
+    "Rule stack"
+  }
+
+  fn validate(&self, board: &Board, area: &PlacementArea, d_type: DominoType) -> Result<(), PlacementRejection> {
+    self.0.iter().try_for_each(|rules| rules.validate(board, area, d_type))
+  }
+
+  fn on_placed(&mut self, board: &Board, area: &PlacementArea, d_type: DominoType) {
+    for rules in self.0.iter_mut() {
+      rules.on_placed(board, area, d_type);
+    }
+  }
+
+  fn score(&self, board: &Board) -> i32 {
+    self.0.iter().map(|rules| rules.score(board)).sum()
+  }
+
+  fn evaluate(&self, board: &Board) -> i32 {
+    self.0.iter().map(|rules| rules.evaluate(board)).sum()
+  }
+
+  fn is_game_over(&self, board: &Board) -> bool {
+    self.0.iter().any(|rules| rules.is_game_over(board))
+  }
+
+  fn compare_scores(&self, a: i32, b: i32) -> Ordering {
+    self.0.first().map_or_else(|| a.cmp(&b), |rules| rules.compare_scores(a, b))
+  }
+
+  fn deal(&mut self) -> Vec<DominoType> {
+    self.0.first_mut().map_or_else(Vec::new, |rules| rules.deal())
+  }
+
+  fn deal_hands(&mut self, seats: usize) -> Option<Vec<Vec<DominoType>>> {
+    self.0.first_mut().and_then(|rules| rules.deal_hands(seats))
+  }
+
+  fn begin_turn(&mut self, seat: usize) {
+    for rules in self.0.iter_mut() {
+      rules.begin_turn(seat);
+    }
+  }
+
+  fn seat_score(&self, board: &Board, seat: usize) -> Option<i32> {
+    self.0.first().and_then(|rules| rules.seat_score(board, seat))
+  }
+
+  fn draw(&mut self, board: &Board) -> Option<DominoType> {
+    self.0.first_mut().and_then(|rules| rules.draw(board))
+  }
+
+  fn pass(&mut self, board: &Board, can_move: bool) -> bool {
+    self.0.first_mut().is_some_and(|rules| rules.pass(board, can_move))
+  }
+
+  fn boxed_clone(&self) -> Box<dyn RuleSet> {
+    Box::new(RuleStack(self.0.iter().map(|rules| rules.boxed_clone()).collect()))
+  }
+
+  fn status(&self, board: &Board) -> String {
+    match self.0.first() {
+      Some(rules) if !self.is_game_over(board) => rules.status(board),
+      _ => format!("Game over! Score: {}", self.score(board)),
+    }
+  }
+}
+
+/** The rules the current level is being played with */
+#[derive(Resource)]
+pub struct ActiveRuleSet(pub Box<dyn RuleSet>);
+
+/**
+ Rule sets that levels can refer to by name. Levels list one or more names,
+ so new combinations don't need any new systems.
+ */
+#[derive(Resource)]
+pub struct RuleSetRegistry(HashMap<&'static str, RuleSetBuilder>);
+
+/** Builds a rule set, drawing whatever it deals from the given stream */
+pub type RuleSetBuilder = fn(&mut StreamRng) -> Box<dyn RuleSet>;
+
+impl Default for RuleSetRegistry {
+  fn default() -> Self {
+    let mut registry = Self(HashMap::new());
+    registry.register("free_form", |_| Box::new(FreeForm));
+    registry.register("color_match", |_| Box::new(ColorMatch));
+    registry.register("classic_block", |rng| Box::new(ClassicRules::new(ClassicVariant::Block, rng)));
+    registry.register("classic_draw", |rng| Box::new(ClassicRules::new(ClassicVariant::Draw, rng)));
+    registry
+  }
+}
+
+impl RuleSetRegistry {
+  pub fn register(&mut self, name: &'static str, build: RuleSetBuilder) {
+    self.0.insert(name, build);
+  }
+
+  /** Every registered name, in alphabetical order */
+  pub fn names(&self) -> Vec<&'static str> {
+    let mut names: Vec<_> = self.0.keys().copied().collect();
+    names.sort();
+    names
+  }
+
+  /**
+    Builds the rule sets with the given names, stacking them if there is more
+    than one, or free-form rules if there are none. Fails on a name that
+    isn't registered. Rule sets that deal draw from `rng`.
+   */
+  pub fn build(&self, names: &[String], rng: &mut StreamRng) -> Result<Box<dyn RuleSet>, String> {
+    let mut rule_sets = names
+      .iter()
+      .map(|name| {
+        let build = self.0.get(name.as_str()).ok_or_else(|| format!("no rule set called {name}"))?;
+        Ok(build(rng))
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+
+    Ok(match rule_sets.len() {
+      0 => Box::new(FreeForm),
+      1 => rule_sets.remove(0),
+      _ => Box::new(RuleStack(rule_sets)),
+    })
+  }
+}
+
+/** Whether any domino in the hand can go anywhere on the board, either way round */
+pub fn can_move(rules: &dyn RuleSet, board: &Board, hand: &[DominoType]) -> bool {
+  board.free_areas().any(|area| {
+    hand.iter().any(|domino| {
+      rules.validate(board, &area, *domino).is_ok() || rules.validate(board, &area, domino.flipped()).is_ok()
+    })
+  })
+}
+
+#[derive(Component)]
+pub struct RulesStatusText;
+
+#[allow(clippy::too_many_arguments)]
+pub fn handle_rule_actions(
+  mut commands: Commands,
+  mut passed_evw: EventWriter<TurnPassed>,
+  mut rules: ResMut<ActiveRuleSet>,
+  board: Res<Board>,
+  action_state: Res<ActionState<DominoControlAction>>,
+  slots: Query<&TraySlot>,
+  turns: Res<Turns>,
+  tray: Query<(&Domino, Option<&Owner>), With<Draggable>>,
+) {
+  if action_state.just_pressed(&DominoControlAction::Draw) {
+    if let Some(domino) = rules.0.draw(&board) {
+      spawn_tray_domino(&mut commands, domino, next_tray_slot(slots.iter().copied()), turns.current_owner());
+    }
+  }
+
+  if action_state.just_pressed(&DominoControlAction::Pass) {
+    let hand: Vec<_> = tray.iter()
+      .filter(|(_, owner)| turns.is_current(*owner))
+      .map(|(domino, _)| domino.d_type)
+      .collect();
+    let can_move = can_move(rules.0.as_ref(), &board, &hand);
+    if rules.0.pass(&board, can_move) {
+      passed_evw.send(TurnPassed);
+    }
+  }
+}
+
+fn update_rules_status_text(
+  rules: Res<ActiveRuleSet>,
+  board: Res<Board>,
+  levels: Res<Levels>,
+  mut texts: Query<&mut Text, With<RulesStatusText>>,
+) {
+  for mut text in texts.iter_mut() {
+    if rules.is_changed() || board.is_changed() || text.0.is_empty() {
+      text.0 = format!("{}\n{}", levels.current().name, rules.0.status(&board));
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::systems::random::GameRng;
+
+  use super::*;
+
+  use DominoColor::*;
+
+  /** Deals, draws and passes in ways no other rule set does, so it shows who was asked */
+  #[derive(Clone)]
+  struct Marked;
+
+  fn mark() -> DominoType {
+    DominoType::new(Yellow, Blue)
+  }
+
+  impl RuleSet for Marked {
+    fn name(&self) -> &'static str {
+      "Marked"
+    }
+
+    fn validate(&self, _board: &Board, _area: &PlacementArea, _d_type: DominoType) -> Result<(), PlacementRejection> {
+      Ok(())
+    }
+
+    fn on_placed(&mut self, _board: &Board, _area: &PlacementArea, _d_type: DominoType) {}
+
+    fn score(&self, _board: &Board) -> i32 {
+      0
+    }
+
+    fn is_game_over(&self, _board: &Board) -> bool {
+      false
+    }
+
+    fn compare_scores(&self, a: i32, b: i32) -> Ordering {
+      b.cmp(&a)
+    }
+
+    fn deal(&mut self) -> Vec<DominoType> {
+      vec![mark()]
+    }
+
+    fn draw(&mut self, _board: &Board) -> Option<DominoType> {
+      Some(mark())
+    }
+
+    fn pass(&mut self, _board: &Board, _can_move: bool) -> bool {
+      true
+    }
+
+    fn boxed_clone(&self) -> Box<dyn RuleSet> {
+      Box::new(self.clone())
+    }
+  }
+
+  #[test]
+  fn stacks_leave_dealing_drawing_passing_and_scoring_to_the_first() {
+    let board = Board::new(4, 4);
+
+    let mut marked_first = RuleStack(vec![Box::new(Marked), Box::new(FreeForm)]);
+    assert_eq!(marked_first.deal(), [mark()]);
+    assert_eq!(marked_first.draw(&board), Some(mark()));
+    assert!(marked_first.pass(&board, true));
+    assert_eq!(marked_first.compare_scores(1, 2), Ordering::Greater);
+
+    let mut marked_second = RuleStack(vec![Box::new(FreeForm), Box::new(Marked)]);
+    assert_eq!(marked_second.deal(), DominoType::ALL_TYPES);
+    assert_eq!(marked_second.draw(&board), None);
+    assert!(!marked_second.pass(&board, true));
+    assert!(marked_second.pass(&board, false));
+    assert_eq!(marked_second.compare_scores(1, 2), Ordering::Less);
+  }
+
+  #[test]
+  fn stacks_need_every_rule_set_to_agree() {
+    let mut board = Board::new(4, 4);
+    board.place(PlacementArea::Horizontal { left_cell_col: 0, row: 0 }, DominoType::new(Blue, Blue));
+    let stack = RuleStack(vec![Box::new(FreeForm), Box::new(ColorMatch)]);
+
+    let above = PlacementArea::Horizontal { left_cell_col: 0, row: 1 };
+    assert_eq!(stack.validate(&board, &above, DominoType::new(Blue, Blue)), Ok(()));
+    assert_eq!(stack.validate(&board, &above, DominoType::new(Red, Blue)), Err(PlacementRejection::ColorMismatch));
+    assert_eq!(stack.score(&board), FreeForm.score(&board) + ColorMatch.score(&board));
+  }
+
+  #[test]
+  fn builds_rule_sets_by_name() {
+    let registry = RuleSetRegistry::default();
+    let mut rng = GameRng::new(1).fork("rules");
+    let build = |names: &[&str], rng: &mut StreamRng| {
+      registry.build(&names.iter().map(|name| name.to_string()).collect::<Vec<_>>(), rng)
+    };
+
+    assert_eq!(build(&[], &mut rng).unwrap().name(), "Free form");
+    assert_eq!(build(&["classic_draw"], &mut rng).unwrap().name(), "Classic (draw)");
+    assert_eq!(build(&["free_form", "color_match"], &mut rng).unwrap().name(), "Rule stack");
+    assert_eq!(build(&["free_form", "colour_match"], &mut rng).err(), Some("no rule set called colour_match".to_string()));
+  }
+}
@@ -1,12 +1,20 @@
-use core::f32;
-
 use crate::prelude::*;
+use crate::systems::camera::{CameraBounds, MainCamera};
 use crate::systems::cursor::CursorWorldCoords;
+use crate::systems::random::GameRng;
 use crate::systems::DominoControlAction;
 use crate::systems::LoadedAssets;
 
+use super::board::*;
 use super::components::*;
+use super::draw_pile::*;
 use super::events::*;
+use super::level::*;
+use super::placement::*;
+use super::rules::*;
+use super::rules::free_form::FreeForm;
+use super::tray::*;
+use super::turns::Owner;
 
 use bevy::color::palettes::css::GREEN;
 use bevy::color::palettes::css::RED;
@@ -14,23 +22,41 @@ use bevy::sprite::Anchor;
 use dragging::Draggable;
 use dragging::IsDragging;
 use leafwing_input_manager::prelude::ActionState;
+use grid::TILE_SIZE;
 use transform::world_to_transform;
 
 pub fn init(
   mut commands: Commands,
+  levels: Res<Levels>,
+  registry: Res<RuleSetRegistry>,
+  game_rng: Res<GameRng>,
+  mut level_started_evw: EventWriter<LevelStarted>,
 ) {
-  let spawn_area_y: f32 = 12.;
-  for (index, domino) in DominoType::ALL_TYPES.iter().enumerate() {
-    let x_offset = ((index % 8) * 3) as f32;
-    let y_offset = ((index / 8) * 2) as f32;
-    commands.spawn((
-      Domino {
-        d_type: domino.clone(),
-        horizontal: true,
-      },
-      Draggable,
-      world_to_transform(-4. + x_offset, spawn_area_y + y_offset, 1.),
-    ));
+  let level = levels.current();
+  let rng = level.seed.map_or(*game_rng, GameRng::new);
+  level_started_evw.send(LevelStarted { level: levels.current, seed: rng.seed() });
+  let mut rules = registry.build(&level.rules, &mut rng.fork("rules")).unwrap_or_else(|error| {
+    warn!("Playing free form, as the level's rules can't be built: {error}");
+    Box::new(FreeForm)
+  });
+  info!("Playing {} with {} rules, seed {}", level.name, rules.name(), rng.seed());
+
+  // Levels with a hand draw it from a shuffled pile of what the rules deal
+  let mut pile = DrawPile::new(level.hand, rules.deal(), &mut rng.fork("draw_pile"));
+  let hands = rules
+    .deal_hands(level.seats())
+    .unwrap_or_else(|| (0..level.seats()).map(|_| pile.deal_hand()).collect());
+  commands.insert_resource(ActiveRuleSet(rules));
+  commands.insert_resource(pile);
+
+  // Hotseat players each get their own hand, told apart by who owns them
+  let dealt = hands
+    .into_iter()
+    .enumerate()
+    .flat_map(|(player, hand)| hand.into_iter().map(move |domino| (player, domino)));
+  for (index, (player, domino)) in dealt.enumerate() {
+    let owner = (level.seats() > 1).then_some(Owner(player));
+    spawn_tray_domino(&mut commands, domino, TraySlot(index), owner);
   }
 
   commands.spawn((
@@ -41,18 +67,64 @@ pub fn init(
   )).with_children(|parent| {
     parent.spawn((
       Text::new("Press [ESC] to toggle (debug) hitboxes in the grid. [BACKSPACE] to reset the dominoes.
-Press [R] to rotate dominoes when dragging.
-Red hitboxes are for horizontal dominoes. Green hitboxes are for vertical dominoes"),
+Press [R] to rotate and [F] to flip dominoes when dragging. Press [M] to switch to the next level.
+Press [D] to draw and [P] to pass when the rules allow it. Press [H] to highlight legal spots while dragging.
+[PGUP]/[PGDN] scroll the tray and [T] sorts it. No mouse? [Q]/[E] pick a domino, arrows move it, [ENTER] places it and [X] puts it back.
+Red hitboxes are for horizontal dominoes. Green hitboxes are for vertical dominoes.
+Middle drag or [I]/[J]/[K]/[L] to pan, scroll or [+]/[-] to zoom, [HOME] to fit the board.
+Press [F1] to change the controls, [`] to open the console and [F2] for the debug overlay."),
       Anchor::TopLeft,
     ));
   });
+
+  commands.spawn((
+    Node {
+      position_type: PositionType::Absolute,
+      right: Val::Px(0.),
+      top: Val::Px(0.),
+      ..default()
+    },
+    RulesStatusText,
+    Text::default(),
+  ));
+
+  commands.spawn((
+    Node {
+      position_type: PositionType::Absolute,
+      left: Val::Px(16.),
+      bottom: Val::Px(4.),
+      ..default()
+    },
+    TrayStatusText,
+    Text::default(),
+  ));
+
+  commands.spawn((
+    Node {
+      position_type: PositionType::Absolute,
+      right: Val::Px(16.),
+      bottom: Val::Px(4.),
+      ..default()
+    },
+    PileStatusText,
+    Text::default(),
+  ));
+}
+
+pub fn next_level(
+  mut levels: ResMut<Levels>,
+) {
+  levels.advance();
 }
 
 pub fn init_grid(
   mut commands: Commands,
+  levels: Res<Levels>,
 ) {
-  let grid_width = 10;
-  let grid_height = 10;
+  let grid_width = levels.current().grid_width;
+  let grid_height = levels.current().grid_height;
+  commands.insert_resource(Board::new(grid_width, grid_height));
+
   for i in 0..grid_width {
     for j in 0..grid_height {
       let x_offset = 0.;
@@ -61,45 +133,23 @@ pub fn init_grid(
         GridSquare,
         world_to_transform(i as f32 + x_offset, j as f32 + y_offset, 0.5),
       ));
-
-      if i < grid_width - 1 {
-        commands.spawn((
-          PlacementArea::Horizontal { left_cell_col: i, row: j },
-        ));
-      }
-
-      if j < grid_height - 1 {
-        commands.spawn((
-          PlacementArea::Vertical { bottom_cell_row: j, column: i },
-        ));
-      }
     }
   }
 }
 
+/** Draws the hitbox of every area a domino could still be dropped into */
 pub fn debug_draw_placement_areas(
   mut gizmos: Gizmos,
-  placement_areas: Query<&PlacementArea>
+  board: Res<Board>,
 ) {
-  for placement in placement_areas.iter() {
-    match *placement {
-      PlacementArea::Horizontal { .. } => {
-        gizmos
-          .rect_2d(
-            Isometry2d::from_translation(placement.transform().translation.truncate()),
-            Vec2::new(32., 32.),
-            RED
-          );
-      },
-      PlacementArea::Vertical { .. } => {
-        gizmos
-          .rect_2d(
-            Isometry2d::from_translation(placement.transform().translation.truncate()),
-            Vec2::new(32., 32.),
-            GREEN
-          );
-      }
-    }
+  for placement in board.free_areas() {
+    let color = if placement.is_horizontal() { RED } else { GREEN };
+    gizmos
+      .rect_2d(
+        Isometry2d::from_translation(placement.center()),
+        Vec2::splat(TILE_SIZE),
+        color
+      );
   }
 }
 
@@ -125,12 +175,13 @@ pub fn on_draggable_spawn(
       .observe(|
         trigger: Trigger<Pointer<Down>>,
         mut commands: Commands,
-        draggable: Query<(Entity, &Transform), With<Draggable>>,
+        draggable: Query<Entity, With<Draggable>>,
       | {
-        if let Ok((entity, transform)) = draggable.get(trigger.entity()) {
-          commands.entity(entity).insert(IsDragging {
-            start_location: transform.translation.truncate()
-          });
+        if trigger.button != PointerButton::Primary {
+          return;
+        }
+        if let Ok(entity) = draggable.get(trigger.entity()) {
+          commands.entity(entity).insert(IsDragging);
         }
       })
       .observe(|
@@ -138,6 +189,9 @@ pub fn on_draggable_spawn(
         mut transforms: Query<&mut Transform, With<Draggable>>,
         mycoords: Res<CursorWorldCoords>,
       | {
+        if trigger.button != PointerButton::Primary {
+          return;
+        }
         if let Ok(mut transform) = transforms.get_mut(trigger.entity()) {
           transform.translation.x = mycoords.0.x;
           transform.translation.y = mycoords.0.y;
@@ -148,6 +202,9 @@ pub fn on_draggable_spawn(
         mut drag_end_evw: EventWriter<DragEndEvent>,
         draggables: Query<Entity, With<Draggable>>
       | {
+        if trigger.button != PointerButton::Primary {
+          return;
+        }
         if let Ok(draggable) = draggables.get(trigger.entity()) {
           drag_end_evw.send(DragEndEvent {
             draggable
@@ -157,6 +214,36 @@ pub fn on_draggable_spawn(
   }
 }
 
+/**
+ Lets the camera roam over the board, and far enough below it that the
+ bottom rows can be brought up above the tray covering the bottom of the window
+ */
+pub fn update_camera_bounds(
+  mut bounds: ResMut<CameraBounds>,
+  board: Res<Board>,
+  q_camera: Query<Ref<OrthographicProjection>, With<MainCamera>>,
+) {
+  let Ok(projection) = q_camera.get_single() else {
+    return;
+  };
+  if !board.is_changed() && !projection.is_changed() {
+    return;
+  }
+
+  // Cells are centered on their coordinates, so the board starts half a tile early
+  let board_rect = Rect::new(
+    -TILE_SIZE / 2.,
+    -TILE_SIZE / 2.,
+    (board.width() as f32 - 0.5) * TILE_SIZE,
+    (board.height() as f32 - 0.5) * TILE_SIZE,
+  );
+  let mut area = board_rect;
+  area.min.y -= tray_screen_height() * projection.scale;
+
+  bounds.board = board_rect;
+  bounds.area = area;
+}
+
 pub fn handle_dragging(
   mut dominoes: Query<(&mut Transform, &mut Domino)>,
   q_dragging: Query<Entity, With<IsDragging>>,
@@ -165,17 +252,29 @@ pub fn handle_dragging(
   for dragging in q_dragging.iter() {
     if let Ok((mut transform, mut domino)) = dominoes.get_mut(dragging) {
       if action_state.just_pressed(&DominoControlAction::Rotate) {
-        transform.rotate_local(Quat::from_rotation_z(f32::consts::FRAC_PI_2));
+        if !domino.horizontal {
+          // A further quarter turn would leave the domino upside down, so
+          // instead swap its halves and show it flat again. This keeps the
+          // first color on the left/bottom, which placement relies on.
+          domino.d_type = domino.d_type.flipped();
+        }
         domino.horizontal = !domino.horizontal;
+        transform.rotation = domino.rotation();
+      }
+
+      if action_state.just_pressed(&DominoControlAction::Flip) {
+        // Only the data changes; the sprite follows through the atlas mapping
+        domino.d_type = domino.d_type.flipped();
       }
     }
   }
 }
 
-pub fn on_domino_spawn(
+/** Shows the sprite for the domino's type, when it is spawned and whenever it is turned or flipped */
+pub fn sync_domino_sprite(
   mut commands: Commands,
   loaded_assets: Res<LoadedAssets>,
-  dominoes: Query<(Entity, &Domino), Added<Domino>>,
+  dominoes: Query<(Entity, &Domino), Changed<Domino>>,
 ) {
   for (entity, domino) in dominoes.iter() {
     commands.entity(entity).insert(
@@ -184,154 +283,110 @@ pub fn on_domino_spawn(
   }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn handle_drag_end(
   mut commands: Commands,
   mut drag_end_evr: EventReader<DragEndEvent>,
   mut placement_evw: EventWriter<DominoPlacementEvent>,
-  placement_areas: Query<&PlacementArea>,
-  mut domino_query: Query<(Entity, &mut Transform, &IsDragging, &Domino)>,
+  mut rejected_evw: EventWriter<PlacementRejected>,
+  mut board: ResMut<Board>,
+  mut rules: ResMut<ActiveRuleSet>,
+  settings: Res<PlacementSettings>,
+  mut domino_query: Query<(Entity, &mut Transform, &mut Domino), With<IsDragging>>,
 ) {
   for event in drag_end_evr.read() {
-    if let Ok((entity, mut transform, dragging_info, domino)) = domino_query.get_mut(event.draggable) {
-      for placement_area in placement_areas.iter() {
-        match *placement_area {
-          PlacementArea::Horizontal { left_cell_col: left_cell, row } => {
-            if !domino.horizontal { continue; }
-
-            if placement_area.is_this_point_within(transform.translation.truncate()) {
-              transform.translation = placement_area.transform().translation.xy().extend(transform.translation.z);
-              placement_evw.send(DominoPlacementEvent::Horizontal { left_cell_col: left_cell, row });
-              commands.entity(entity)
-                .remove::<IsDragging>()
-                .remove::<Draggable>();
-              return;
-            }
-          },
-          PlacementArea::Vertical { bottom_cell_row: bottom_cell, column } => {
-            if domino.horizontal { continue; }
-
-            if placement_area.is_this_point_within(transform.translation.truncate()) {
-              transform.translation = placement_area.transform().translation.xy().extend(transform.translation.z);
-              placement_evw.send(DominoPlacementEvent::Vertical { bottom_cell_row: bottom_cell, column });
-              commands.entity(entity)
-                .remove::<IsDragging>()
-                .remove::<Draggable>();
-              return;
-            }
+    let _span = info_span!("handle_drop", domino = ?event.draggable).entered();
+    if let Ok((entity, mut transform, mut domino)) = domino_query.get_mut(event.draggable) {
+      let point = transform.translation.truncate();
+
+      match resolve_drop(&board, rules.0.as_ref(), &settings, point, domino.d_type, domino.horizontal) {
+        Ok(DropTarget { area: placement_area, d_type }) => {
+          // Snapping may have turned the domino to make it fit
+          if placement_area.is_horizontal() != domino.horizontal || d_type != domino.d_type {
+            domino.horizontal = placement_area.is_horizontal();
+            domino.d_type = d_type;
+            transform.rotation = domino.rotation();
           }
-        }
-      }
 
-      transform.translation = dragging_info.start_location.extend(1.);
-      // Remove the IsDragging
-      commands.entity(entity)
-        .remove::<IsDragging>();
+          board.place(placement_area, domino.d_type);
+          info_span!("rules_on_placed", rules = rules.0.name())
+            .in_scope(|| rules.0.on_placed(&board, &placement_area, domino.d_type));
+
+          transform.translation = placement_area.transform().translation.xy().extend(transform.translation.z);
+          placement_evw.send(DominoPlacementEvent::new(entity, domino.d_type, placement_area));
+          commands.entity(entity)
+            .insert(Placed { placement: placement_area })
+            .remove::<IsDragging>()
+            .remove::<Draggable>();
+        },
+        Err(reason) => {
+          let hovered = PlacementArea::at_point(point, domino.horizontal);
+          rejected_evw.send(PlacementRejected {
+            domino: entity,
+            d_type: domino.d_type,
+            placement: board.contains(&hovered).then_some(hovered),
+            reason,
+          });
+
+          // Without IsDragging the tray lays it back out in its slot
+          commands.entity(entity)
+            .remove::<IsDragging>();
+        },
+      }
     }
   }
 }
 
-pub fn handle_horizontal_domino_placed(
-  mut commands: Commands,
-  mut domino_placed_evr: EventReader<DominoPlacementEvent>,
-  placement_areas: Query<(Entity, &PlacementArea)>,
+/** Keeps a trail of everything that happens to dominoes on the grid */
+pub fn log_domino_events(
+  mut placed_evr: EventReader<DominoPlacementEvent>,
+  mut removed_evr: EventReader<DominoRemoved>,
+  mut rejected_evr: EventReader<PlacementRejected>,
 ) {
-  for event in domino_placed_evr.read() {
-    // Identify the event
-    match *event {
-      // This system only cares about horizontal events
-      DominoPlacementEvent::Horizontal { left_cell_col: placed_left_cell_col, row: placed_row } => {
-        // Iterate over all placement areas
-        for (entity, placement_area) in placement_areas.iter() {
-          match placement_area {
-            // For horizontal placement areas, remove all PA's that are -1,0,+1 from the
-            // left cell, but only within this row
-            &PlacementArea::Horizontal { left_cell_col: left_cell, row } => {
-              let should_remove = row == placed_row && (
-                left_cell == placed_left_cell_col ||
-                left_cell == placed_left_cell_col - 1 ||
-                left_cell == placed_left_cell_col + 1 
-              );
-              if should_remove {
-                commands.entity(entity).despawn();
-              }
-            },
-            // For vertical placement areas, remove all PA's that are 0,+1 from the
-            // left cell, for this row and the row below
-            &PlacementArea::Vertical { bottom_cell_row, column } => {
-              let should_remove =
-                (bottom_cell_row == placed_row && placed_left_cell_col == column) ||
-                (bottom_cell_row == placed_row && placed_left_cell_col + 1 == column) ||
-                (bottom_cell_row == placed_row - 1 && placed_left_cell_col == column) ||
-                (bottom_cell_row == placed_row - 1 && placed_left_cell_col + 1 == column)
-                ;
-              if should_remove {
-                commands.entity(entity).despawn();
-              }
-            },
-          }
-        }
-      },
-      _ => {},
-    }
+  for event in placed_evr.read() {
+    let [(first_cell, first), (second_cell, second)] = event.cells;
+    debug!(
+      "Placed {:?} ({:?}) at {:?}: {first:?} in {first_cell}, {second:?} in {second_cell}",
+      event.domino, event.d_type, event.placement,
+    );
   }
-}
 
-pub fn handle_vertical_domino_placed(
-  mut commands: Commands,
-  mut domino_placed_evr: EventReader<DominoPlacementEvent>,
-  placement_areas: Query<(Entity, &PlacementArea)>,
-) {
-  for event in domino_placed_evr.read() {
-    // Identify the event
-    match *event {
-      // This system only cares about horizontal events
-      DominoPlacementEvent::Vertical { column: placed_col, bottom_cell_row: placed_row_bottom_cell } => {
-        // Iterate over all placement areas
-        for (entity, placement_area) in placement_areas.iter() {
-          match placement_area {
-            // For vertical placement areas, remove all PA's that are -1,0,+1 from the
-            // bottom cell, but only within this column
-            &PlacementArea::Vertical { bottom_cell_row, column } => {
-              let should_remove = column == placed_col && (
-                bottom_cell_row == placed_row_bottom_cell ||
-                bottom_cell_row == placed_row_bottom_cell - 1 ||
-                bottom_cell_row == placed_row_bottom_cell + 1 
-              );
-              if should_remove {
-                commands.entity(entity).despawn();
-              }
-            },
-            // For vertical placement areas, remove all PA's that are 0,+1 from the
-            // left cell, for this row and the row below
-            &PlacementArea::Horizontal { left_cell_col, row } => {
-              let should_remove =
-                (row == placed_row_bottom_cell && placed_col == left_cell_col) ||
-                (row == placed_row_bottom_cell && placed_col - 1 == left_cell_col) ||
-                (row == placed_row_bottom_cell + 1 && placed_col == left_cell_col) ||
-                (row == placed_row_bottom_cell + 1 && placed_col - 1 == left_cell_col)
-                ;
-              if should_remove {
-                commands.entity(entity).despawn();
-              }
-            },
-          }
-        }
-      },
-      _ => {},
-    }
+  for event in removed_evr.read() {
+    let [(first_cell, first), (second_cell, second)] = event.cells;
+    debug!(
+      "Removed {:?} ({:?}) from {:?}: {first:?} in {first_cell}, {second:?} in {second_cell}",
+      event.domino, event.d_type, event.placement,
+    );
+  }
+
+  for event in rejected_evr.read() {
+    debug!(
+      "Rejected {:?} ({:?}) at {:?}: {:?}",
+      event.domino, event.d_type, event.placement, event.reason,
+    );
   }
 }
 
+#[allow(clippy::type_complexity)]
 pub fn reset(
   mut commands: Commands,
-  dominoes: Query<Entity, With<Domino>>,
-  placement_areas: Query<Entity, With<PlacementArea>>,
+  mut removed_evw: EventWriter<DominoRemoved>,
+  dominoes: Query<(Entity, &Domino, Option<&Placed>)>,
+  grid_squares: Query<Entity, With<GridSquare>>,
+  status_texts: Query<Entity, Or<(With<RulesStatusText>, With<TrayStatusText>, With<PileStatusText>)>>,
 ) {
-  for domino in dominoes.iter() {
-    commands.entity(domino).despawn_recursive();
+  for (entity, domino, placed) in dominoes.iter() {
+    if let Some(placed) = placed {
+      removed_evw.send(DominoRemoved::new(entity, domino.d_type, placed.placement));
+    }
+    commands.entity(entity).despawn_recursive();
+  }
+
+  for status_text in status_texts.iter() {
+    commands.entity(status_text).despawn_recursive();
   }
 
-  for placement_area in placement_areas.iter() {
-    commands.entity(placement_area).despawn_recursive();
+  for grid_square in grid_squares.iter() {
+    commands.entity(grid_square).despawn_recursive();
   }
 }
\ No newline at end of file
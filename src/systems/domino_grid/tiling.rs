@@ -0,0 +1,122 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::prelude::*;
+
+use super::board::*;
+
+/**
+ How many more dominoes the free cells have room for, colors aside. Every
+ domino covers one cell of each checkerboard color, so this is the largest
+ matching between neighbouring free cells of the two colors.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct TilingAnalysis {
+  pub free_cells: usize,
+  /** The most dominoes that could still be placed together */
+  pub max_dominoes: usize,
+  /** Free cells with no free neighbour, which nothing can ever cover */
+  pub isolated: Vec<IVec2>,
+}
+
+impl TilingAnalysis {
+  pub fn of(board: &Board) -> Self {
+    let _span = info_span!("tiling_analysis", width = board.width(), height = board.height()).entered();
+    let free: Vec<_> = board.free_cells().collect();
+    let index: HashMap<_, _> = free.iter().enumerate().map(|(index, cell)| (*cell, index)).collect();
+    let free_neighbours = |cell: IVec2| neighbours(cell).into_iter().filter_map(|neighbour| index.get(&neighbour).copied());
+
+    let isolated = free.iter().copied().filter(|cell| free_neighbours(*cell).next().is_none()).collect();
+
+    // Matched from the even cells, whose neighbours are all odd
+    let even: Vec<_> = (0..free.len()).filter(|cell| (free[*cell].x + free[*cell].y) % 2 == 0).collect();
+    let mut partner: Vec<Option<usize>> = vec![None; free.len()];
+
+    for &start in &even {
+      // Looks for a path that swaps pairs along the way to free up a partner for `start`
+      let mut reached_from: Vec<Option<usize>> = vec![None; free.len()];
+      let mut open = VecDeque::from([start]);
+      let mut end = None;
+
+      'search: while let Some(cell) = open.pop_front() {
+        for odd in free_neighbours(free[cell]) {
+          if reached_from[odd].is_some() { continue; }
+          reached_from[odd] = Some(cell);
+          match partner[odd] {
+            None => {
+              end = Some(odd);
+              break 'search;
+            },
+            Some(next) => open.push_back(next),
+          }
+        }
+      }
+
+      let mut odd = end;
+      while let Some(current) = odd {
+        let cell = reached_from[current].expect("reached cells know where from");
+        odd = partner[cell];
+        partner[current] = Some(cell);
+        partner[cell] = Some(current);
+      }
+    }
+
+    Self {
+      free_cells: free.len(),
+      max_dominoes: even.iter().filter(|cell| partner[**cell].is_some()).count(),
+      isolated,
+    }
+  }
+
+  /** Cells that would be left uncovered even with the best possible placements */
+  pub fn left_over(&self) -> usize {
+    self.free_cells - 2 * self.max_dominoes
+  }
+
+  /** Whether the free cells could be covered exactly */
+  pub fn can_tile(&self) -> bool {
+    self.left_over() == 0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::components::*;
+
+  use super::*;
+
+  use DominoColor::*;
+
+  #[test]
+  fn tiles_an_empty_board_exactly() {
+    let analysis = TilingAnalysis::of(&Board::new(4, 3));
+    assert_eq!(analysis.free_cells, 12);
+    assert_eq!(analysis.max_dominoes, 6);
+    assert!(analysis.isolated.is_empty());
+    assert!(analysis.can_tile());
+  }
+
+  #[test]
+  fn finds_an_isolated_cell() {
+    // The domino in the middle of the top row cuts off both ends of it
+    let mut board = Board::new(4, 2);
+    board.place(PlacementArea::Horizontal { left_cell_col: 0, row: 0 }, DominoType::new(Red, Blue));
+    board.place(PlacementArea::Horizontal { left_cell_col: 2, row: 0 }, DominoType::new(Red, Blue));
+    board.place(PlacementArea::Horizontal { left_cell_col: 1, row: 1 }, DominoType::new(Red, Blue));
+
+    let analysis = TilingAnalysis::of(&board);
+    assert_eq!(analysis.free_cells, 2);
+    assert_eq!(analysis.isolated, vec![IVec2::new(0, 1), IVec2::new(3, 1)]);
+    assert_eq!(analysis.max_dominoes, 0);
+    assert_eq!(analysis.left_over(), 2);
+    assert!(!analysis.can_tile());
+  }
+
+  #[test]
+  fn counts_cells_no_matching_can_cover() {
+    // Three cells in a row: one domino, one cell left over, none isolated
+    let analysis = TilingAnalysis::of(&Board::new(3, 1));
+    assert_eq!(analysis.max_dominoes, 1);
+    assert_eq!(analysis.left_over(), 1);
+    assert!(analysis.isolated.is_empty());
+  }
+}
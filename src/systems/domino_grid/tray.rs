@@ -0,0 +1,282 @@
+use bevy::window::PrimaryWindow;
+use leafwing_input_manager::common_conditions::action_just_pressed;
+use leafwing_input_manager::prelude::ActionState;
+
+use crate::prelude::*;
+use crate::systems::camera::{screen_to_world, CameraControlSet, MainCamera};
+use crate::systems::DominoControlAction;
+
+use super::components::*;
+use super::draw_pile::InHoldSlot;
+use super::turns::*;
+
+use dragging::Draggable;
+use dragging::IsDragging;
+use grid::TILE_SIZE;
+
+/** Screen pixels given to each domino in the tray */
+const SLOT_WIDTH: f32 = 3. * TILE_SIZE;
+const ROW_HEIGHT: f32 = 2. * TILE_SIZE;
+/** How many rows of the tray are shown at once */
+const VISIBLE_ROWS: usize = 2;
+const SIDE_MARGIN: f32 = 16.;
+/** Leaves room for the tray status line under the dominoes */
+const BOTTOM_MARGIN: f32 = 32.;
+pub const TRAY_Z: f32 = 5.;
+
+/**
+ Lays the dominoes that haven't been placed out in a panel along the bottom
+ of the window. The panel follows the camera, so it stays put however the
+ board is panned or zoomed, and scrolls a row at a time when there are more
+ dominoes than fit.
+ */
+pub struct TrayPlugin;
+
+impl Plugin for TrayPlugin {
+  fn build(&self, app: &mut App) {
+    app
+      .init_resource::<Tray>()
+      .add_systems(Update, (
+        scroll_tray,
+        cycle_tray_sort.run_if(action_just_pressed(DominoControlAction::SortTray)),
+        unscale_picked_up,
+        layout_tray.after(CameraControlSet),
+        update_tray_status_text,
+      ).chain())
+      ;
+  }
+}
+
+/** Where a domino was dealt into the tray. Dominoes keep their slot while dragged */
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TraySlot(pub usize);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TraySort {
+  #[default]
+  Dealt,
+  /** Grouped by the color of the first half, with a gap between groups */
+  Color,
+  Pips,
+}
+
+impl TraySort {
+  pub fn next(self) -> Self {
+    match self {
+      Self::Dealt => Self::Color,
+      Self::Color => Self::Pips,
+      Self::Pips => Self::Dealt,
+    }
+  }
+
+  pub fn label(self) -> &'static str {
+    match self {
+      Self::Dealt => "as dealt",
+      Self::Color => "by color",
+      Self::Pips => "by pips",
+    }
+  }
+}
+
+#[derive(Resource, Default)]
+pub struct Tray {
+  pub sort: TraySort,
+  /** The topmost row being shown */
+  pub first_row: usize,
+  /** How many rows the tray currently takes up */
+  pub rows: usize,
+  /** The tray dominoes in the order they are laid out */
+  pub order: Vec<Entity>,
+}
+
+#[derive(Component)]
+pub struct TrayStatusText;
+
+/** Adds a domino to the tray. It is shown once the tray lays it out */
+pub fn spawn_tray_domino(commands: &mut Commands, d_type: DominoType, slot: TraySlot, owner: Option<Owner>) -> Entity {
+  let mut entity = commands.spawn((
+    Domino {
+      d_type,
+      horizontal: true,
+    },
+    Draggable,
+    slot,
+    Visibility::Hidden,
+  ));
+  if let Some(owner) = owner {
+    entity.insert(owner);
+  }
+  entity.id()
+}
+
+/** How many dominoes fit side by side in the tray */
+pub fn tray_columns(window: &Window) -> usize {
+  (((window.width() - 2. * SIDE_MARGIN) / SLOT_WIDTH) as usize).max(1)
+}
+
+/**
+  Where the middle of a tray cell is on the screen. Row 0 is the top row
+  being shown; negative rows are stacked above the tray.
+ */
+pub fn tray_cell_screen(window: &Window, column: f32, row: f32) -> Vec2 {
+  Vec2::new(
+    SIDE_MARGIN + SLOT_WIDTH * (column + 0.5),
+    window.height() - BOTTOM_MARGIN - ROW_HEIGHT * (VISIBLE_ROWS as f32 - row - 0.5),
+  )
+}
+
+/** Screen pixels the tray takes along the bottom of the window, with the row stacked above it */
+pub fn tray_screen_height() -> f32 {
+  BOTTOM_MARGIN + ROW_HEIGHT * (VISIBLE_ROWS as f32 + 1.)
+}
+
+/** Where the dealt slot for the next domino added to the tray is */
+pub fn next_tray_slot(slots: impl Iterator<Item = TraySlot>) -> TraySlot {
+  TraySlot(slots.map(|slot| slot.0 + 1).max().unwrap_or(0))
+}
+
+/**
+  Puts the dominoes in order and gives each a cell of the tray, counting
+  along the rows. Cells may be skipped to separate groups.
+ */
+pub fn arrange_tray(mut dominoes: Vec<(Entity, TraySlot, DominoType)>, sort: TraySort) -> Vec<(Entity, usize)> {
+  match sort {
+    TraySort::Dealt => dominoes.sort_by_key(|(_, slot, _)| *slot),
+    TraySort::Color => dominoes.sort_by_key(|(_, slot, d_type)| {
+      let (first, second) = d_type.colors();
+      (first.pips(), second.pips(), *slot)
+    }),
+    TraySort::Pips => dominoes.sort_by_key(|(_, slot, d_type)| (d_type.pips(), *slot)),
+  }
+
+  let mut cell = 0;
+  let mut previous_group = None;
+  dominoes
+    .into_iter()
+    .map(|(entity, _, d_type)| {
+      let group = d_type.colors().0;
+      if sort == TraySort::Color && previous_group.is_some_and(|previous| previous != group) {
+        cell += 1;
+      }
+      previous_group = Some(group);
+      cell += 1;
+      (entity, cell - 1)
+    })
+    .collect()
+}
+
+fn scroll_tray(
+  mut tray: ResMut<Tray>,
+  action_state: Res<ActionState<DominoControlAction>>,
+) {
+  if action_state.just_pressed(&DominoControlAction::ScrollTrayUp) {
+    tray.first_row = tray.first_row.saturating_sub(1);
+  }
+  if action_state.just_pressed(&DominoControlAction::ScrollTrayDown) {
+    tray.first_row += 1;
+  }
+}
+
+fn cycle_tray_sort(
+  mut tray: ResMut<Tray>,
+) {
+  tray.sort = tray.sort.next();
+}
+
+/** Dominoes are shown at the tray's scale; once picked up they go back to world size */
+fn unscale_picked_up(
+  mut picked_up: Query<&mut Transform, Added<IsDragging>>,
+) {
+  for mut transform in picked_up.iter_mut() {
+    transform.scale = Vec3::ONE;
+  }
+}
+
+#[allow(clippy::type_complexity)]
+fn layout_tray(
+  mut tray: ResMut<Tray>,
+  turns: Res<Turns>,
+  game_state: Res<State<GameState>>,
+  q_window: Query<&Window, With<PrimaryWindow>>,
+  q_camera: Query<(&Transform, &OrthographicProjection), (With<MainCamera>, Without<Domino>)>,
+  mut dominoes: Query<
+    (Entity, &TraySlot, &mut Domino, &mut Transform, &mut Visibility, Has<IsDragging>, Option<&Owner>),
+    (With<Draggable>, Without<InHoldSlot>),
+  >,
+) {
+  let (Ok(window), Ok((camera_transform, projection))) = (q_window.get_single(), q_camera.get_single()) else {
+    return;
+  };
+
+  // Only the hand of the player whose turn it is is shown, and none between
+  // turns or to other players over the network. A computer's hand stays
+  // hidden, bar the domino it is playing
+  let hand_over = *game_state.get() == GameState::HandOver;
+  let computer = turns.current_ai().is_some();
+  let mut hand = vec![];
+  for (entity, slot, domino, _, mut visibility, dragging, owner) in dominoes.iter_mut() {
+    if hand_over || !turns.is_current(owner) || !turns.is_local_turn() || (computer && !dragging) {
+      visibility.set_if_neq(Visibility::Hidden);
+    } else {
+      hand.push((entity, *slot, domino.d_type));
+    }
+  }
+
+  let per_row = tray_columns(window);
+  let arranged = arrange_tray(hand, tray.sort);
+
+  let rows = arranged.last().map_or(0, |(_, cell)| cell / per_row + 1);
+  let first_row = tray.first_row.min(rows.saturating_sub(VISIBLE_ROWS));
+  // Only flag the tray as changed when what the status line shows changes
+  if tray.rows != rows || tray.first_row != first_row {
+    tray.rows = rows;
+    tray.first_row = first_row;
+  }
+  tray.bypass_change_detection().order = arranged.iter().map(|(entity, _)| *entity).collect();
+
+  for (entity, cell) in arranged {
+    let Ok((_, _, mut domino, mut transform, mut visibility, dragging, _)) = dominoes.get_mut(entity) else {
+      continue;
+    };
+    if dragging {
+      visibility.set_if_neq(Visibility::Inherited);
+      continue;
+    }
+
+    // Dominoes come back from the board lying flat, first color on the left
+    if !domino.horizontal {
+      domino.horizontal = true;
+      transform.rotation = domino.rotation();
+    }
+
+    let row = cell / per_row;
+    if row < first_row || row >= first_row + VISIBLE_ROWS {
+      visibility.set_if_neq(Visibility::Hidden);
+      continue;
+    }
+    visibility.set_if_neq(Visibility::Inherited);
+
+    let screen = tray_cell_screen(window, (cell % per_row) as f32, (row - first_row) as f32);
+    let world = screen_to_world(window, camera_transform, projection.scale, screen);
+    transform.translation = world.extend(TRAY_Z);
+    transform.scale = Vec3::splat(projection.scale);
+  }
+}
+
+fn update_tray_status_text(
+  tray: Res<Tray>,
+  mut texts: Query<&mut Text, With<TrayStatusText>>,
+) {
+  for mut text in texts.iter_mut() {
+    if tray.is_changed() || text.0.is_empty() {
+      let last_row = tray.rows.min(tray.first_row + VISIBLE_ROWS);
+      text.0 = format!(
+        "Tray rows {}-{} of {} [PGUP]/[PGDN], sorted {} [T]",
+        (tray.first_row + 1).min(last_row),
+        last_row,
+        tray.rows,
+        tray.sort.label(),
+      );
+    }
+  }
+}
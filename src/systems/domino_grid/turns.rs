@@ -0,0 +1,383 @@
+use leafwing_input_manager::prelude::ActionState;
+
+use crate::prelude::*;
+use crate::systems::DominoControlAction;
+
+use super::ai::Difficulty;
+use super::board::*;
+use super::draw_pile::{refill_hand, DrawPile};
+use super::events::*;
+use super::level::Levels;
+use super::replay::ReplayState;
+use super::rules::*;
+use super::systems::handle_drag_end;
+use super::ui::HandOverButton;
+
+use dragging::Draggable;
+
+pub const MAX_PLAYERS: usize = 4;
+
+/**
+ Hotseat play: several players take turns on one board, each with their own
+ hand. Their dominoes are told apart by `Owner`, and only the current
+ player's hand is shown. Between turns the game waits in
+ `GameState::HandOver` so the next player can take their seat without
+ seeing the others' dominoes.
+ */
+pub struct TurnsPlugin;
+
+impl Plugin for TurnsPlugin {
+  fn build(&self, app: &mut App) {
+    app
+      .init_resource::<Turns>()
+      .add_systems(Update, (
+        start_turns.run_if(on_event::<LevelStarted>),
+        // The turn may move on straight after a placement, so the refill has to be dealt first
+        end_turn_on_placement
+          .after(handle_drag_end)
+          .after(refill_hand)
+          .run_if(in_state(GameState::PlayerTurn).and(on_event::<DominoPlacementEvent>)),
+        end_turn_on_pass
+          .after(handle_rule_actions)
+          .run_if(in_state(GameState::PlayerTurn).and(on_event::<TurnPassed>)),
+        begin_next_turn.run_if(in_state(GameState::HandOver)),
+        update_seat_scores,
+        update_game_over,
+      ).chain())
+      ;
+  }
+}
+
+/** Which player's hand a domino is in */
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Owner(pub usize);
+
+#[derive(Clone, Debug)]
+pub struct Player {
+  pub name: String,
+  pub score: i32,
+  /** How well the computer plays this seat, if it isn't a person */
+  pub ai: Option<Difficulty>,
+}
+
+#[derive(Resource, Default)]
+pub struct Turns {
+  pub players: Vec<Player>,
+  pub current: usize,
+  /** The seat played on this screen in a network game. On one screen, everyone's */
+  pub local: Option<usize>,
+  /** The rules' score when the current turn began, so the turn's points can be worked out */
+  score_before_turn: i32,
+  /** Turns given up since the last placement */
+  passes_in_a_row: usize,
+  over: bool,
+}
+
+impl Turns {
+  /** People take the first seats, and computer players the rest */
+  pub fn new(humans: usize, ai: &[Difficulty]) -> Self {
+    let seats = (0..humans).map(|_| None).chain(ai.iter().copied().map(Some));
+    Self {
+      players: seats
+        .take(MAX_PLAYERS)
+        .enumerate()
+        .map(|(index, ai)| {
+          let number = index + 1;
+          let name = match ai {
+            Some(difficulty) => format!("Computer {number} ({})", difficulty.label()),
+            None => format!("Player {number}"),
+          };
+          Player { name, score: 0, ai }
+        })
+        .collect(),
+      current: 0,
+      local: None,
+      score_before_turn: 0,
+      passes_in_a_row: 0,
+      over: false,
+    }
+  }
+
+  pub fn is_hotseat(&self) -> bool {
+    self.players.len() > 1
+  }
+
+  /** Whether a domino belongs to the player whose turn it is. Everything does in single player */
+  pub fn is_current(&self, owner: Option<&Owner>) -> bool {
+    !self.is_hotseat() || owner.is_none_or(|owner| owner.0 == self.current)
+  }
+
+  /** Who dominoes drawn now go to. Nobody in single player, where the tray is everyone's */
+  pub fn current_owner(&self) -> Option<Owner> {
+    self.is_hotseat().then_some(Owner(self.current))
+  }
+
+  /** Whether the player whose turn it is sits at this screen */
+  pub fn is_local_turn(&self) -> bool {
+    self.local.is_none_or(|seat| seat == self.current)
+  }
+
+  /** How well the computer plays the current seat, if it has it */
+  pub fn current_ai(&self) -> Option<Difficulty> {
+    self.players.get(self.current).and_then(|player| player.ai)
+  }
+
+  fn next(&self) -> usize {
+    (self.current + 1) % self.players.len()
+  }
+
+  /**
+    Whether the screen should be covered before the next player goes: only
+    when a person takes over from someone else, who may still be looking.
+   */
+  fn needs_hand_over(&self) -> bool {
+    if self.local.is_some() { return false; }
+    let humans = self.players.iter().filter(|player| player.ai.is_none()).count();
+    humans > 1 && self.players[self.next()].ai.is_none()
+  }
+
+  /** Whether the game has finished, by `is_game_over` */
+  pub fn is_over(&self) -> bool {
+    self.over
+  }
+
+  /** Scores the next turn from this total, for when the board was changed outside of play */
+  pub fn restart_scoring(&mut self, total: i32) {
+    self.score_before_turn = total;
+  }
+
+  /** The player with the best score by the rules. Ties go to whoever played first */
+  pub fn leader(&self, rules: &dyn RuleSet) -> Option<&Player> {
+    self.players.iter().rev().max_by(|a, b| rules.compare_scores(a.score, b.score))
+  }
+}
+
+fn start_turns(
+  mut commands: Commands,
+  turns: Res<Turns>,
+  mut next_state: ResMut<NextState<GameState>>,
+  levels: Res<Levels>,
+) {
+  let level = levels.current();
+  // A network game keeps its seat from one level to the next
+  let turns = Turns { local: turns.local, ..Turns::new(level.players, &level.ai) };
+  next_state.set(if turns.is_hotseat() { GameState::PlayerTurn } else { GameState::Build });
+  commands.insert_resource(turns);
+}
+
+/**
+ Whether a game played in turns has finished: when the rules say so, when
+ every seat has passed in a row, or when every domino has been played.
+ `SimulatedGame` ends the same way.
+ */
+pub fn is_game_over(rules: &dyn RuleSet, board: &Board, seats: usize, passes_in_a_row: usize, dominoes_left: usize) -> bool {
+  rules.is_game_over(board) || passes_in_a_row >= seats.max(1) || dominoes_left == 0
+}
+
+/** Credits the player with whatever the placement added to the score, then hands over */
+pub fn end_turn_on_placement(
+  mut turns: ResMut<Turns>,
+  mut next_state: ResMut<NextState<GameState>>,
+  mut rules: ResMut<ActiveRuleSet>,
+  board: Res<Board>,
+  pile: Res<DrawPile>,
+  tray: Query<(), With<Draggable>>,
+) {
+  let total = info_span!("rules_score", rules = rules.0.name()).in_scope(|| rules.0.score(&board));
+  let gained = total - turns.score_before_turn;
+  let current = turns.current;
+  turns.players[current].score += gained;
+  turns.score_before_turn = total;
+  turns.passes_in_a_row = 0;
+
+  end_turn(&mut turns, rules.0.as_mut(), &board, tray.iter().count() + pile.remaining(), &mut next_state);
+}
+
+/** Moves on once the rules have accepted a pass */
+pub fn end_turn_on_pass(
+  mut turns: ResMut<Turns>,
+  mut rules: ResMut<ActiveRuleSet>,
+  mut next_state: ResMut<NextState<GameState>>,
+  board: Res<Board>,
+  pile: Res<DrawPile>,
+  tray: Query<(), With<Draggable>>,
+) {
+  turns.passes_in_a_row += 1;
+  end_turn(&mut turns, rules.0.as_mut(), &board, tray.iter().count() + pile.remaining(), &mut next_state);
+}
+
+/**
+  Hands over between people, or goes straight on when the computer is next
+  or nobody else is watching. Nobody goes next once the game is over.
+ */
+fn end_turn(turns: &mut Turns, rules: &mut dyn RuleSet, board: &Board, dominoes_left: usize, next_state: &mut NextState<GameState>) {
+  turns.over = is_game_over(rules, board, turns.players.len(), turns.passes_in_a_row, dominoes_left);
+  if turns.over {
+    info!("Game over on\n{}", board.to_ansi());
+  } else if turns.needs_hand_over() {
+    next_state.set(GameState::HandOver);
+  } else {
+    turns.current = turns.next();
+    rules.begin_turn(turns.current);
+  }
+}
+
+/** Lets the next player go once they have taken the seat. Replays don't wait, as the hand-over isn't recorded */
+fn begin_next_turn(
+  mut turns: ResMut<Turns>,
+  mut rules: ResMut<ActiveRuleSet>,
+  mut next_state: ResMut<NextState<GameState>>,
+  action_state: Res<ActionState<DominoControlAction>>,
+  replay: Res<ReplayState>,
+  buttons: Query<&Interaction, (Changed<Interaction>, With<HandOverButton>)>,
+) {
+  let clicked = buttons.iter().any(|interaction| *interaction == Interaction::Pressed);
+  if !clicked && !action_state.just_pressed(&DominoControlAction::Place) && replay.playback.is_none() {
+    return;
+  }
+
+  turns.current = turns.next();
+  rules.0.begin_turn(turns.current);
+  next_state.set(GameState::PlayerTurn);
+}
+
+/** Draws and passes can end the game without a turn ending, as when the line is blocked */
+fn update_game_over(
+  mut turns: ResMut<Turns>,
+  rules: Res<ActiveRuleSet>,
+  board: Res<Board>,
+  pile: Res<DrawPile>,
+  tray: Query<(), With<Draggable>>,
+) {
+  if !turns.is_hotseat() || turns.over { return; }
+
+  if is_game_over(rules.0.as_ref(), &board, turns.players.len(), turns.passes_in_a_row, tray.iter().count() + pile.remaining()) {
+    turns.over = true;
+  }
+}
+
+/** Keeps the players' scores in step with rules that score each seat by its own hand */
+fn update_seat_scores(
+  mut turns: ResMut<Turns>,
+  rules: Res<ActiveRuleSet>,
+  board: Res<Board>,
+) {
+  if !rules.is_changed() && !turns.is_changed() { return; }
+
+  for seat in 0..turns.players.len() {
+    let Some(score) = rules.0.seat_score(&board, seat) else { continue; };
+    if turns.players[seat].score != score {
+      turns.players[seat].score = score;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use bevy::ecs::system::RunSystemOnce;
+
+  use crate::systems::random::GameRng;
+
+  use super::super::components::*;
+  use super::super::rules::color_match::ColorMatch;
+  use super::super::rules::free_form::FreeForm;
+
+  use super::*;
+
+  use DominoColor::*;
+
+  /** A person against the computer, so turns go straight on without a hand-over */
+  fn app(board: Board, hands: [&[DominoType]; 2]) -> App {
+    let mut app = App::new();
+    app
+      .add_plugins((MinimalPlugins, bevy::state::app::StatesPlugin))
+      .init_state::<GameState>()
+      .init_resource::<ActionState<DominoControlAction>>()
+      .init_resource::<DrawPile>()
+      .insert_resource(ActiveRuleSet(Box::new(RuleStack(vec![Box::new(FreeForm), Box::new(ColorMatch)]))))
+      .insert_resource(board)
+      .insert_resource(Turns::new(1, &[Difficulty::Easy]))
+      .add_event::<TurnPassed>()
+      .add_systems(Update, (
+        handle_rule_actions,
+        end_turn_on_pass.run_if(in_state(GameState::PlayerTurn).and(on_event::<TurnPassed>)),
+        update_game_over,
+      ).chain());
+
+    for (seat, hand) in hands.into_iter().enumerate() {
+      for d_type in hand {
+        app.world_mut().spawn((Domino { d_type: *d_type, horizontal: true }, Draggable, Owner(seat)));
+      }
+    }
+    app.world_mut().resource_mut::<NextState<GameState>>().set(GameState::PlayerTurn);
+    app.update();
+    app
+  }
+
+  fn pass(app: &mut App) {
+    app.world_mut().resource_mut::<ActionState<DominoControlAction>>().press(&DominoControlAction::Pass);
+    app.update();
+    app.world_mut().resource_mut::<ActionState<DominoControlAction>>().release(&DominoControlAction::Pass);
+    app.update();
+  }
+
+  #[test]
+  fn a_seat_that_cannot_move_passes_and_a_round_of_passes_ends_the_game() {
+    // The only space left is next to red, which neither hand has
+    let mut board = Board::new(4, 1);
+    board.place(PlacementArea::Horizontal { left_cell_col: 0, row: 0 }, DominoType::new(Red, Red));
+    let mut app = app(board, [&[DominoType::new(Blue, Blue)], &[DominoType::new(Green, Green)]]);
+
+    pass(&mut app);
+    let turns = app.world().resource::<Turns>();
+    assert_eq!(turns.current, 1);
+    assert!(!turns.is_over());
+
+    pass(&mut app);
+    let turns = app.world().resource::<Turns>();
+    assert_eq!(turns.current, 1);
+    assert!(turns.is_over());
+  }
+
+  #[test]
+  fn a_seat_that_can_move_may_not_pass() {
+    let mut app = app(Board::new(4, 4), [&[DominoType::new(Blue, Blue)], &[DominoType::new(Green, Green)]]);
+
+    pass(&mut app);
+    let turns = app.world().resource::<Turns>();
+    assert_eq!(turns.current, 0);
+    assert!(!turns.is_over());
+  }
+
+  #[test]
+  fn each_seat_is_credited_with_what_its_own_turn_scored() {
+    let mut app = app(Board::new(4, 4), [&[DominoType::new(Blue, Blue)], &[DominoType::new(Green, Green)]]);
+    let place = |app: &mut App, row| {
+      app.world_mut().resource_mut::<Board>().place(PlacementArea::Horizontal { left_cell_col: 0, row }, DominoType::new(Blue, Blue));
+      app.world_mut().run_system_once(end_turn_on_placement).unwrap();
+    };
+
+    place(&mut app, 0);
+    place(&mut app, 2);
+    place(&mut app, 3);
+
+    // A point a domino, and the last also matches both halves below it
+    let turns = app.world().resource::<Turns>();
+    let scores: Vec<_> = turns.players.iter().map(|player| player.score).collect();
+    assert_eq!(scores, [1 + 3, 1]);
+    assert_eq!(turns.current, 1);
+  }
+
+  #[test]
+  fn the_leader_is_whoever_the_rules_rank_best() {
+    let mut turns = Turns::new(3, &[]);
+    for (player, score) in turns.players.iter_mut().zip([5, 2, 5]) {
+      player.score = score;
+    }
+
+    assert_eq!(turns.leader(&FreeForm).unwrap().name, "Player 1");
+
+    let classic = RuleSetRegistry::default().build(&["classic_block".to_string()], &mut GameRng::new(1).fork("rules")).unwrap();
+    assert_eq!(turns.leader(classic.as_ref()).unwrap().name, "Player 2");
+  }
+}
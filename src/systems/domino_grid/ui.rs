@@ -0,0 +1,119 @@
+use crate::prelude::*;
+
+use super::board::*;
+use super::rules::*;
+use super::turns::*;
+
+pub struct TurnIndicatorPlugin;
+
+impl Plugin for TurnIndicatorPlugin {
+  fn build(&self, app: &mut App) {
+    app
+      .add_systems(Startup, spawn_turn_indicator)
+      .add_systems(OnEnter(GameState::HandOver), spawn_hand_over_screen)
+      .add_systems(OnExit(GameState::HandOver), despawn_hand_over_screen)
+      .add_systems(Update, update_turn_indicator)
+      ;
+  }
+}
+
+/** Lists the players and their scores, pointing at whose turn it is */
+#[derive(Component)]
+pub struct TurnIndicatorText;
+
+/** Covers the board between turns, so the next player can sit down without seeing the last one's hand */
+#[derive(Component)]
+pub struct HandOverScreen;
+
+#[derive(Component)]
+pub struct HandOverButton;
+
+fn spawn_turn_indicator(
+  mut commands: Commands,
+) {
+  commands.spawn((
+    Node {
+      position_type: PositionType::Absolute,
+      left: Val::Percent(40.),
+      top: Val::Px(48.),
+      ..default()
+    },
+    TurnIndicatorText,
+    Text::default(),
+  ));
+}
+
+fn update_turn_indicator(
+  turns: Res<Turns>,
+  rules: Res<ActiveRuleSet>,
+  board: Res<Board>,
+  mut texts: Query<&mut Text, With<TurnIndicatorText>>,
+) {
+  if !turns.is_changed() && !board.is_changed() { return; }
+
+  let mut lines: Vec<_> = turns.players
+    .iter()
+    .enumerate()
+    .map(|(index, player)| {
+      let marker = if index == turns.current { "> " } else { "  " };
+      format!("{marker}{}: {}", player.name, player.score)
+    })
+    .collect();
+
+  if turns.is_hotseat() && turns.is_over() {
+    if let Some(leader) = turns.leader(rules.0.as_ref()) {
+      lines.push(format!("{} wins!", leader.name));
+    }
+  }
+
+  let status = if turns.is_hotseat() { lines.join("\n") } else { String::new() };
+  for mut text in texts.iter_mut() {
+    if text.0 != status {
+      text.0 = status.clone();
+    }
+  }
+}
+
+fn spawn_hand_over_screen(
+  mut commands: Commands,
+  turns: Res<Turns>,
+) {
+  let next = (turns.current + 1) % turns.players.len().max(1);
+  let name = turns.players.get(next).map_or("Next player", |player| player.name.as_str());
+
+  commands.spawn((
+    HandOverScreen,
+    Node {
+      position_type: PositionType::Absolute,
+      width: Val::Percent(100.),
+      height: Val::Percent(100.),
+      flex_direction: FlexDirection::Column,
+      align_items: AlignItems::Center,
+      justify_content: JustifyContent::Center,
+      row_gap: Val::Px(8.),
+      ..default()
+    },
+    BackgroundColor(Color::srgb(0.05, 0.05, 0.08)),
+    GlobalZIndex(5),
+  )).with_children(|parent| {
+    parent.spawn(Text::new(format!("{name}, it's your turn")));
+    parent.spawn((
+      Button,
+      HandOverButton,
+      Node {
+        padding: UiRect::axes(Val::Px(12.), Val::Px(4.)),
+        ..default()
+      },
+      BackgroundColor(Color::srgb(0.2, 0.2, 0.25)),
+    )).with_child(Text::new("Ready [ENTER]"));
+  });
+}
+
+fn despawn_hand_over_screen(
+  mut commands: Commands,
+  screens: Query<Entity, With<HandOverScreen>>,
+) {
+  for screen in screens.iter() {
+    commands.entity(screen).despawn_recursive();
+  }
+}
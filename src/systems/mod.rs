@@ -1,16 +1,25 @@
 mod actions;
 mod assets;
 mod camera;
+mod console;
 mod cursor;
-mod domino_grid;
+pub mod domino_grid;
+mod random;
+mod rebinding;
+mod settings;
 
 use actions::*;
 use assets::*;
 use camera::*;
+use console::*;
 use cursor::*;
 use domino_grid::*;
+use random::*;
 use transform::world_to_transform;
 
+pub use domino_grid::ai::env::EnvServer;
+pub use domino_grid::ai::tournament::TournamentConfig;
+
 use crate::prelude::*;
 
 pub struct SystemsPlugin;
@@ -19,7 +28,10 @@ impl Plugin for SystemsPlugin {
   fn build(&self, app: &mut App) {
     app
       .add_plugins((
+        RandomPlugin,
         ActionsPlugin,
+        CameraPlugin,
+        ConsolePlugin,
         CursorPlugin,
         TempPlugin,
         DominoesPlugin,
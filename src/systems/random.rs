@@ -0,0 +1,102 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::prelude::*;
+
+/** The generator handed out for each stream of randomness */
+pub type StreamRng = ChaCha8Rng;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+pub struct RandomPlugin;
+
+impl Plugin for RandomPlugin {
+  fn build(&self, app: &mut App) {
+    let seed = SeedSource::from_args(std::env::args().skip(1)).seed();
+    info!("Game seed is {seed}, pass --seed {seed} to play it again");
+
+    app.insert_resource(GameRng::new(seed));
+  }
+}
+
+/** Where the session's seed comes from */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeedSource {
+  /** Given with `--seed <number>` */
+  Fixed(u64),
+  /** `--daily`: the same seed for everyone on the same (UTC) day */
+  Daily,
+  /** Nothing asked for, so a fresh seed every run */
+  Clock,
+}
+
+impl SeedSource {
+  pub fn from_args(args: impl IntoIterator<Item = String>) -> Self {
+    let mut args = args.into_iter();
+    let mut source = Self::Clock;
+
+    while let Some(arg) = args.next() {
+      match arg.as_str() {
+        "--daily" => source = Self::Daily,
+        "--seed" => match args.next().map(|seed| seed.parse::<u64>()) {
+          Some(Ok(seed)) => source = Self::Fixed(seed),
+          _ => warn!("--seed needs a whole number"),
+        },
+        _ => {},
+      }
+    }
+
+    source
+  }
+
+  pub fn seed(self) -> u64 {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    match self {
+      Self::Fixed(seed) => seed,
+      Self::Daily => daily_seed(since_epoch.as_secs() / SECONDS_PER_DAY),
+      Self::Clock => since_epoch.as_nanos() as u64,
+    }
+  }
+}
+
+/** Spreads consecutive day numbers out so neighbouring days don't start alike */
+pub fn daily_seed(day: u64) -> u64 {
+  stable_hash(&day.to_le_bytes())
+}
+
+/**
+ A stable FNV-1a hash, so stream names map to the same streams (and game
+ states to the same hashes) on every platform and build
+ */
+pub fn stable_hash(bytes: &[u8]) -> u64 {
+  bytes.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/**
+ All the game's randomness comes from this seed. Each subsystem draws from
+ its own named stream, so adding a random call in one place doesn't change
+ what any other place gets, and the same seed always replays the same game.
+ */
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct GameRng {
+  seed: u64,
+}
+
+impl GameRng {
+  pub fn new(seed: u64) -> Self {
+    Self { seed }
+  }
+
+  pub fn seed(&self) -> u64 {
+    self.seed
+  }
+
+  /** The generator for a stream, starting from its beginning each time */
+  pub fn fork(&self, stream: &str) -> StreamRng {
+    let mut rng = StreamRng::seed_from_u64(self.seed);
+    rng.set_stream(stable_hash(stream.as_bytes()));
+    rng
+  }
+}
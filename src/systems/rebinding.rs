@@ -0,0 +1,326 @@
+use std::path::PathBuf;
+
+use leafwing_input_manager::{common_conditions::action_just_pressed, prelude::ActionState};
+
+use crate::prelude::*;
+
+use super::actions::*;
+use super::settings::{controls_path, save_controls};
+
+const ROW_COLOR: Color = Color::srgb(0.2, 0.2, 0.25);
+const WAITING_COLOR: Color = Color::srgb(0.45, 0.4, 0.1);
+const CONFLICT_COLOR: Color = Color::srgb(0.5, 0.15, 0.15);
+
+pub struct RebindingPlugin;
+
+impl Plugin for RebindingPlugin {
+  fn build(&self, app: &mut App) {
+    app
+      .init_resource::<RebindScreen>()
+      .add_systems(Update, (
+        toggle_rebind_screen
+          .run_if(action_just_pressed(DominoControlAction::OpenControls))
+          .run_if(|screen: Res<RebindScreen>| screen.waiting_for.is_none()),
+        handle_rebind_buttons,
+        capture_binding,
+        update_rebind_rows,
+      ).chain())
+      ;
+  }
+}
+
+/** State of the screen where the controls are changed */
+#[derive(Resource)]
+pub struct RebindScreen {
+  pub open: bool,
+  /** The action whose next key or gamepad button press becomes its binding */
+  pub waiting_for: Option<DominoControlAction>,
+  pub message: String,
+  /** Where changed controls are saved */
+  pub save_to: Option<PathBuf>,
+}
+
+impl Default for RebindScreen {
+  fn default() -> Self {
+    Self {
+      open: false,
+      waiting_for: None,
+      message: String::new(),
+      save_to: controls_path(),
+    }
+  }
+}
+
+#[derive(Component)]
+pub struct RebindMenu;
+
+#[derive(Component)]
+pub struct RebindRow(pub DominoControlAction);
+
+#[derive(Component)]
+pub struct ResetControlsButton;
+
+#[derive(Component)]
+pub struct RebindMessage;
+
+fn toggle_rebind_screen(
+  mut commands: Commands,
+  mut screen: ResMut<RebindScreen>,
+  mut action_state: ResMut<ActionState<DominoControlAction>>,
+  menus: Query<Entity, With<RebindMenu>>,
+) {
+  screen.open = !screen.open;
+
+  // Nothing but closing the screen should reach the game while it is open
+  for action in DominoControlAction::ALL {
+    if screen.open && action != DominoControlAction::OpenControls {
+      action_state.disable_action(&action);
+    } else {
+      action_state.enable_action(&action);
+    }
+  }
+
+  for menu in menus.iter() {
+    commands.entity(menu).despawn_recursive();
+  }
+  if !screen.open {
+    return;
+  }
+
+  screen.message = "Click an action, then press the key or gamepad button to use for it".to_string();
+  commands.spawn((
+    RebindMenu,
+    Node {
+      position_type: PositionType::Absolute,
+      width: Val::Percent(100.),
+      height: Val::Percent(100.),
+      flex_direction: FlexDirection::Column,
+      align_items: AlignItems::Center,
+      justify_content: JustifyContent::Center,
+      row_gap: Val::Px(2.),
+      ..default()
+    },
+    BackgroundColor(Color::srgba(0., 0., 0., 0.85)),
+    GlobalZIndex(10),
+  )).with_children(|parent| {
+    parent.spawn((RebindMessage, Text::default()));
+
+    for action in DominoControlAction::ALL {
+      parent.spawn((
+        Button,
+        RebindRow(action),
+        Node {
+          width: Val::Px(480.),
+          padding: UiRect::horizontal(Val::Px(8.)),
+          ..default()
+        },
+        BackgroundColor(ROW_COLOR),
+      )).with_child(Text::default());
+    }
+
+    parent.spawn((
+      Button,
+      ResetControlsButton,
+      Node {
+        margin: UiRect::top(Val::Px(8.)),
+        padding: UiRect::horizontal(Val::Px(8.)),
+        ..default()
+      },
+      BackgroundColor(ROW_COLOR),
+    )).with_child(Text::new("Reset to defaults"));
+  });
+}
+
+fn handle_rebind_buttons(
+  mut screen: ResMut<RebindScreen>,
+  mut controls: ResMut<Controls>,
+  rows: Query<(&Interaction, &RebindRow), Changed<Interaction>>,
+  resets: Query<&Interaction, (Changed<Interaction>, With<ResetControlsButton>)>,
+) {
+  for (interaction, row) in rows.iter() {
+    if *interaction != Interaction::Pressed {
+      continue;
+    }
+
+    if screen.waiting_for == Some(row.0) {
+      screen.waiting_for = None;
+      screen.message = "Cancelled".to_string();
+    } else {
+      screen.waiting_for = Some(row.0);
+      screen.message = format!("Press a key or gamepad button for {} (click it again or press [ESC] to cancel)", row.0.label());
+    }
+  }
+
+  if resets.iter().any(|interaction| *interaction == Interaction::Pressed) {
+    *controls = Controls::default();
+    save_controls(screen.save_to.as_deref(), &controls);
+    screen.waiting_for = None;
+    screen.message = "Controls reset to defaults".to_string();
+  }
+}
+
+fn capture_binding(
+  mut screen: ResMut<RebindScreen>,
+  mut controls: ResMut<Controls>,
+  keys: Res<ButtonInput<KeyCode>>,
+  gamepads: Query<&Gamepad>,
+) {
+  let Some(action) = screen.waiting_for else {
+    return;
+  };
+
+  // Escape, and whatever opens this screen, back out rather than being bound
+  let opens_controls = controls.get(DominoControlAction::OpenControls).map_or(&[][..], |bindings| &bindings.keys[..]);
+  if keys.just_pressed(KeyCode::Escape) || opens_controls.iter().any(|key| keys.just_pressed(*key)) {
+    screen.waiting_for = None;
+    screen.message = "Cancelled".to_string();
+    return;
+  }
+
+  let binding = keys.get_just_pressed().next().map(|key| Binding::Key(*key))
+    .or_else(|| {
+      gamepads.iter()
+        .find_map(|gamepad| gamepad.get_just_pressed().next())
+        .map(|button| Binding::Gamepad(*button))
+    });
+  let Some(binding) = binding else {
+    return;
+  };
+
+  match controls.rebind(action, binding) {
+    Ok(()) => {
+      save_controls(screen.save_to.as_deref(), &controls);
+      screen.waiting_for = None;
+      screen.message = format!("{} is now {}", action.label(), binding_name(binding));
+    },
+    Err(other) => {
+      screen.message = format!(
+        "{} is already used for {}. Press another, or click {} again to cancel",
+        binding_name(binding),
+        other.label(),
+        action.label(),
+      );
+    },
+  }
+}
+
+fn update_rebind_rows(
+  screen: Res<RebindScreen>,
+  controls: Res<Controls>,
+  mut rows: Query<(&RebindRow, &Children, &mut BackgroundColor)>,
+  mut texts: Query<&mut Text>,
+  messages: Query<Entity, With<RebindMessage>>,
+) {
+  if !screen.is_changed() && !controls.is_changed() {
+    return;
+  }
+
+  let conflicts = controls.conflicts();
+  for (row, children, mut background) in rows.iter_mut() {
+    let in_conflict = conflicts.iter().any(|(_, action, other)| *action == row.0 || *other == row.0);
+    background.0 = if screen.waiting_for == Some(row.0) {
+      WAITING_COLOR
+    } else if in_conflict {
+      CONFLICT_COLOR
+    } else {
+      ROW_COLOR
+    };
+
+    let bindings = controls.get(row.0).cloned().unwrap_or_default();
+    let names: Vec<_> = bindings.keys.iter().map(|key| Binding::Key(*key))
+      .chain(bindings.gamepad.iter().map(|button| Binding::Gamepad(*button)))
+      .map(binding_name)
+      .collect();
+
+    for child in children.iter() {
+      if let Ok(mut text) = texts.get_mut(*child) {
+        text.0 = format!("{}: {}", row.0.label(), names.join(", "));
+      }
+    }
+  }
+
+  for message in messages.iter() {
+    if let Ok(mut text) = texts.get_mut(message) {
+      text.0 = screen.message.clone();
+    }
+  }
+}
+
+/** A short name for a binding, e.g. `R` rather than `KeyR` */
+pub fn binding_name(binding: Binding) -> String {
+  match binding {
+    Binding::Key(key) => {
+      let name = format!("{key:?}");
+      name.strip_prefix("Key")
+        .or_else(|| name.strip_prefix("Digit"))
+        .unwrap_or(&name)
+        .to_string()
+    },
+    Binding::Gamepad(button) => format!("Gamepad {button:?}"),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use bevy::input::gamepad::{GamepadConnection, GamepadConnectionEvent};
+  use bevy::input::InputPlugin;
+  use leafwing_input_manager::user_input::Buttonlike;
+
+  use super::super::settings::load_controls;
+  use super::*;
+
+  /** Waiting to rebind `action`, saving to `save_to` so the player's own controls are left alone */
+  fn app(action: DominoControlAction, save_to: &std::path::Path) -> App {
+    let mut app = App::new();
+    app
+      .add_plugins((MinimalPlugins, InputPlugin))
+      .insert_resource(RebindScreen { waiting_for: Some(action), save_to: Some(save_to.to_path_buf()), ..default() })
+      .init_resource::<Controls>()
+      .add_systems(Update, capture_binding);
+    app
+  }
+
+  #[test]
+  fn captures_and_saves_a_gamepad_button() {
+    let config = std::env::temp_dir().join(format!("protodominoes-rebinding-{}", std::process::id()));
+    let path = config.join("controls.ron");
+    let mut app = app(DominoControlAction::Rotate, &path);
+
+    let gamepad = app.world_mut().spawn_empty().id();
+    app.world_mut().send_event(GamepadConnectionEvent::new(gamepad, GamepadConnection::Connected {
+      name: "Pad".to_string(),
+      vendor_id: None,
+      product_id: None,
+    }));
+    app.update();
+    app.update();
+
+    GamepadButton::Mode.press(app.world_mut());
+    app.update();
+
+    let rotate = app.world().resource::<Controls>().get(DominoControlAction::Rotate).cloned().unwrap();
+    assert_eq!(rotate.gamepad, [GamepadButton::Mode]);
+    assert_eq!(rotate.keys, [KeyCode::KeyR]);
+    assert_eq!(app.world().resource::<RebindScreen>().waiting_for, None);
+
+    let saved = load_controls(Some(&path));
+    assert_eq!(saved.get(DominoControlAction::Rotate).unwrap().gamepad, [GamepadButton::Mode]);
+
+    let _ = std::fs::remove_dir_all(config);
+  }
+
+  #[test]
+  fn escape_and_the_controls_key_cancel_instead_of_binding() {
+    let path = std::env::temp_dir().join(format!("protodominoes-cancel-{}", std::process::id())).join("controls.ron");
+
+    for key in [KeyCode::Escape, KeyCode::F1] {
+      let mut app = app(DominoControlAction::Flip, &path);
+      key.press(app.world_mut());
+      app.update();
+
+      assert_eq!(app.world().resource::<RebindScreen>().waiting_for, None);
+      assert_eq!(app.world().resource::<Controls>().get(DominoControlAction::Flip).unwrap().keys, [KeyCode::KeyF]);
+      assert!(!path.exists());
+    }
+  }
+}
@@ -0,0 +1,82 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use serde::Serialize;
+
+use crate::prelude::*;
+
+use super::actions::Controls;
+
+const SETTINGS_DIR: &str = "protodominoes";
+const CONTROLS_FILE: &str = "controls.ron";
+
+/** Where the player's settings live, following each platform's convention */
+pub fn settings_dir() -> Option<PathBuf> {
+  let base = std::env::var_os("XDG_CONFIG_HOME")
+    .or_else(|| std::env::var_os("APPDATA"))
+    .map(PathBuf::from)
+    .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+  Some(base.join(SETTINGS_DIR))
+}
+
+pub fn controls_path() -> Option<PathBuf> {
+  settings_dir().map(|dir| dir.join(CONTROLS_FILE))
+}
+
+/**
+ Reads the controls saved at `path` (usually `controls_path`), falling back
+ to the defaults for anything missing. A file that can't be read or parsed
+ is ignored with a warning, and bindings shared by several actions are
+ reported but kept so they can be fixed in the controls screen.
+ */
+pub fn load_controls(path: Option<&Path>) -> Controls {
+  let mut controls = Controls::default();
+
+  let Some(path) = path else {
+    return controls;
+  };
+  let Ok(contents) = fs::read_to_string(path) else {
+    return controls;
+  };
+
+  match ron::from_str::<Controls>(&contents) {
+    Ok(saved) => {
+      info!("Loaded controls from {}", path.display());
+      controls.0.extend(saved.0);
+    },
+    Err(error) => warn!("Ignoring controls in {}: {error}", path.display()),
+  }
+
+  for (binding, action, other) in controls.conflicts() {
+    warn!("{binding:?} is bound to both {action:?} and {other:?}");
+  }
+
+  controls
+}
+
+pub fn save_controls(path: Option<&Path>, controls: &Controls) {
+  let Some(path) = path else {
+    warn!("Nowhere to save controls to");
+    return;
+  };
+
+  match write_ron(path, controls) {
+    Ok(()) => info!("Saved controls to {}", path.display()),
+    Err(error) => warn!("Could not save controls to {}: {error}", path.display()),
+  }
+}
+
+/** Writes a value as RON, creating its directory if need be */
+pub fn write_ron(path: &Path, value: &impl Serialize) -> Result<(), String> {
+  let contents = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())
+    .map_err(|error| error.to_string())?;
+  write_text(path, &contents)
+}
+
+/** Writes a file, creating its directory if need be */
+pub fn write_text(path: &Path, contents: &str) -> Result<(), String> {
+  if let Some(dir) = path.parent() {
+    fs::create_dir_all(dir).map_err(|error| error.to_string())?;
+  }
+  fs::write(path, contents).map_err(|error| error.to_string())
+}